@@ -4,6 +4,24 @@ use core::{
     ops::{Add, Sub},
 };
 
+#[cfg(feature = "kernel")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Base of the linear map. It's chosen by the loader, so it's known only at runtime
+#[cfg(feature = "kernel")]
+static LINEAR_BASE: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets base of the linear map. Must be called before any [`LinearAddr`] is created
+#[cfg(feature = "kernel")]
+pub fn set_linear_base(base: usize) {
+    LINEAR_BASE.store(base, Ordering::Relaxed);
+}
+
+#[cfg(feature = "kernel")]
+fn linear_base() -> usize {
+    LINEAR_BASE.load(Ordering::Relaxed)
+}
+
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug)]
 #[repr(transparent)]
 pub struct PhysAddr(usize);
@@ -382,14 +400,14 @@ impl Address for PhysAddr {
 #[cfg(feature = "kernel")]
 impl From<PhysAddr> for LinearAddr {
     fn from(addr: PhysAddr) -> Self {
-        Self(addr.0 + arch::PHYS_OFFSET)
+        Self(addr.0 + linear_base())
     }
 }
 
 #[cfg(feature = "kernel")]
 impl From<LinearAddr> for PhysAddr {
     fn from(addr: LinearAddr) -> Self {
-        Self(addr.bits() - arch::PHYS_OFFSET)
+        Self(addr.bits() - linear_base())
     }
 }
 
@@ -1,6 +1,3 @@
 pub const PAGE_SHIFT: usize = 12;
 pub const PAGE_SIZE: usize = 1 << PAGE_SHIFT;
 pub const PAGE_MASK: usize = PAGE_SIZE - 1;
-
-#[cfg(feature = "kernel")]
-pub const PHYS_OFFSET: usize = 0xFFFF700000000000;
@@ -13,6 +13,8 @@ pub struct Page {
     pub refcount: AtomicUsize,
     pub list: ListNode,
     pub state: PageState,
+    /// Order of the free block, which starts at this page
+    pub order: u8,
 }
 
 impl Page {
@@ -50,9 +50,6 @@ switch_to_user:
 	// Must be last, since x0 is the base register
 	ldp	x0, x1, [x0, #0]
 
-	// TODO: remove this flush
-	tlbi	vmalle1is
-
 	// Jump to user-space
 	eret
 
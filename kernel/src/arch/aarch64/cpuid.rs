@@ -1,10 +1,8 @@
-use aarch64_cpu::registers::{MPIDR_EL1, Readable};
-
-const MPIDR_HWID_MASK: usize = 0xff00ffffff;
+use aarch64_cpu::registers::{Readable, TPIDR_EL1};
 
+/// Logical number of the current CPU. Boot CPU is 0, secondary ones get their numbers in order of
+/// the FDT `/cpus` node
 #[inline]
 pub fn current_cpu() -> usize {
-    let mpidr = MPIDR_EL1.get() as usize;
-
-    mpidr & MPIDR_HWID_MASK
+    TPIDR_EL1.get() as usize
 }
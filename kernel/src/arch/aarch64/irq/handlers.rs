@@ -1,9 +1,12 @@
 use crate::arch::backtrace::backtrace;
-use crate::arch::regs::{Context, TrapReason};
+use crate::arch::regs::{Context, PageFault, TrapReason};
 use crate::drivers::irq::irq_dispatch;
+use crate::mm::vmm::layout::vmm_range;
+use crate::sched::current_vms;
 use aarch64_cpu::registers::{ELR_EL1, ESR_EL1, FAR_EL1, Readable, VBAR_EL1, Writeable};
 use core::arch::global_asm;
 use hal::address::*;
+use loader_protocol::VmmLayoutKind;
 use rtl::linker_var;
 
 global_asm!(include_str!("interrupts.s"));
@@ -43,11 +46,22 @@ fn fixup(v: VirtAddr, ctx: &mut Context) -> bool {
     found
 }
 
+/// Kernel accessed user memory, which is not committed yet. Instruction is restarted, if fault
+/// was resolved
+fn user_fault(fault: PageFault) -> bool {
+    vmm_range(VmmLayoutKind::User).contains_addr(fault.addr)
+        && current_vms().handle_fault(fault.addr, fault.access).is_ok()
+}
+
 fn kern_sync(ctx: &mut Context) {
     let elr_el1: VirtAddr = ctx.elr.into();
     let esr_el1 = ESR_EL1.get();
     let far_el1 = FAR_EL1.get();
 
+    if PageFault::current().is_some_and(user_fault) {
+        return;
+    }
+
     if !fixup(elr_el1, ctx) {
         error!("!!! Kernel sync exception\n");
         error!("{ctx:?}\n");
@@ -12,3 +12,9 @@ pub fn get_flags() -> IrqFlags {
 pub unsafe fn set_flags(flags: IrqFlags) {
     DAIF.set(flags.0 as u64)
 }
+
+/// Sleeps until an interrupt is pending. Masked interrupt wakes CPU up as well
+#[inline]
+pub fn wait_for_interrupt() {
+    unsafe { core::arch::asm!("wfi") }
+}
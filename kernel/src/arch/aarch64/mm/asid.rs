@@ -0,0 +1,233 @@
+//! Address space identifiers. TLB entries of user mappings are tagged with ASID of their address
+//! space, so switching address spaces does not require TLB flush.
+//!
+//! ASIDs are allocated in generations. Once all of them are used, new generation starts and
+//! address spaces get new ASIDs on next switch. ASIDs running on other CPUs at that moment are
+//! reserved and carried into the new generation. Each CPU flushes its TLB before using ASID of
+//! a newer generation, so entries of the old owner of the same ASID can't be hit.
+
+use super::page_table::flush_tlb_local;
+use super::tlb::shootdown;
+use crate::arch::cpuid::current_cpu;
+use crate::smp::{MAX_CPUS, num_cpus};
+use crate::sync::Spinlock;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use hal::address::*;
+
+/// TCR_EL1.AS is not set, so only 8 bits of ASID are used
+const ASID_BITS: u32 = 8;
+const ASID_COUNT: usize = 1 << ASID_BITS;
+const ASID_MASK: u64 = (ASID_COUNT - 1) as u64;
+
+/// ASID 0 is used while kernel page table is loaded into TTBR0
+const ASID_KERNEL: u16 = 0;
+
+struct AsidAllocator {
+    used: [u64; ASID_COUNT / 64],
+    next: usize,
+    /// ASIDs CPUs were running with during last rollover
+    reserved: [u64; MAX_CPUS],
+}
+
+static ALLOCATOR: Spinlock<AsidAllocator> = Spinlock::new(AsidAllocator::new());
+
+// Generation 0 marks ASID which was never allocated
+static GENERATION: AtomicU64 = AtomicU64::new(1);
+
+percpu_global! {
+    // ASID loaded into TTBR0. 0 forces slow path on next switch
+    static ACTIVE_ASID: AtomicU64 = AtomicU64::new(0);
+}
+
+percpu_global! {
+    // Generation TLB of the CPU was flushed for. 0 forces flush on next switch
+    static FLUSHED_GENERATION: AtomicU64 = AtomicU64::new(0);
+}
+
+/// ASID of an address space together with generation it was allocated in
+pub struct Asid {
+    asid: AtomicU64,
+    // CPUs which ran the address space
+    cpus: AtomicUsize,
+}
+
+fn generation(asid: u64) -> u64 {
+    asid >> ASID_BITS
+}
+
+impl AsidAllocator {
+    const fn new() -> Self {
+        let mut used = [0; ASID_COUNT / 64];
+
+        used[0] = 1 << ASID_KERNEL;
+        Self {
+            used,
+            next: 1,
+            reserved: [0; MAX_CPUS],
+        }
+    }
+
+    fn is_used(&self, id: usize) -> bool {
+        self.used[id / 64] & (1 << (id % 64)) != 0
+    }
+
+    fn mark_used(&mut self, id: usize) {
+        self.used[id / 64] |= 1 << (id % 64);
+    }
+
+    fn find_free(&mut self) -> Option<usize> {
+        let id = (self.next..ASID_COUNT)
+            .chain(1..self.next)
+            .find(|&id| !self.is_used(id))?;
+
+        self.mark_used(id);
+        self.next = id + 1;
+        Some(id)
+    }
+
+    fn rollover(&mut self) {
+        let reserved = self.reserved;
+
+        *self = Self::new();
+
+        for (cpu, reserved) in reserved.into_iter().enumerate().take(num_cpus()) {
+            // SAFETY: only atomic swap is performed on other CPU's variable
+            let active = unsafe { ACTIVE_ASID.cpu(cpu) }.swap(0, Ordering::Relaxed);
+
+            // CPU did not switch since previous rollover and still runs with the old ASID
+            let active = if active == 0 { reserved } else { active };
+
+            if active != 0 {
+                self.mark_used((active & ASID_MASK) as usize);
+            }
+
+            self.reserved[cpu] = active;
+        }
+
+        GENERATION.fetch_add(1, Ordering::Release);
+    }
+
+    fn allocate(&mut self, asid: &Asid) -> u64 {
+        let old = asid.asid.load(Ordering::Relaxed);
+
+        // Other CPU switched to the same address space meanwhile
+        if generation(old) == GENERATION.load(Ordering::Relaxed) {
+            return old;
+        }
+
+        let id = if old != 0 && self.reserved.contains(&old) {
+            Some((old & ASID_MASK) as usize)
+        } else {
+            self.find_free()
+        };
+        let id = id.unwrap_or_else(|| {
+            self.rollover();
+
+            if old != 0 && self.reserved.contains(&old) {
+                (old & ASID_MASK) as usize
+            } else {
+                self.find_free().expect("All ASIDs are reserved")
+            }
+        });
+        let new = GENERATION.load(Ordering::Relaxed) << ASID_BITS | id as u64;
+
+        for reserved in self.reserved.iter_mut().filter(|x| **x == old && old != 0) {
+            *reserved = new;
+        }
+
+        asid.asid.store(new, Ordering::Relaxed);
+        new
+    }
+
+    fn free(&mut self, asid: u64) {
+        let id = (asid & ASID_MASK) as usize;
+
+        self.used[id / 64] &= !(1 << (id % 64));
+    }
+}
+
+impl Asid {
+    pub const fn new() -> Self {
+        Self {
+            asid: AtomicU64::new(0),
+            cpus: AtomicUsize::new(0),
+        }
+    }
+
+    /// Value TLB entries of the address space are tagged with
+    pub fn value(&self) -> u16 {
+        (self.asid.load(Ordering::Relaxed) & ASID_MASK) as u16
+    }
+
+    /// Mask of CPUs, which ran the address space and may have its entries in TLB
+    pub fn cpus(&self) -> usize {
+        self.cpus.load(Ordering::SeqCst)
+    }
+
+    /// Returns ASID valid in the current generation, allocating new one if needed. TLB of the
+    /// current CPU is flushed, if it may contain entries of previous generations
+    pub fn activate(&self) -> u16 {
+        // Must be visible before TLB is filled, so shootdowns don't miss this CPU
+        if self.cpus.load(Ordering::Relaxed) & (1 << current_cpu()) == 0 {
+            self.cpus.fetch_or(1 << current_cpu(), Ordering::SeqCst);
+        }
+
+        let asid = self.asid.load(Ordering::Relaxed);
+        let active = ACTIVE_ASID.per_cpu_var_get();
+        let old_active = active.load(Ordering::Relaxed);
+
+        // Fast path: no rollover happened since this CPU switched last time. Rollover resets
+        // active ASIDs, so exchange fails if it races with us
+        if old_active != 0
+            && generation(asid) == GENERATION.load(Ordering::Acquire)
+            && active
+                .compare_exchange(old_active, asid, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            return (asid & ASID_MASK) as u16;
+        }
+
+        let mut allocator = ALLOCATOR.lock();
+        let asid = allocator.allocate(self);
+        let flushed = FLUSHED_GENERATION.per_cpu_var_get();
+
+        if flushed.load(Ordering::Relaxed) < generation(asid) {
+            flush_tlb_local();
+            flushed.store(generation(asid), Ordering::Relaxed);
+        }
+
+        active.store(asid, Ordering::Relaxed);
+        (asid & ASID_MASK) as u16
+    }
+
+    /// ASID of the kernel page table. Lower half of it has global mappings, so TLB must be
+    /// flushed before next user address space is activated
+    pub fn activate_kernel() -> u16 {
+        ACTIVE_ASID.per_cpu_var_get().store(0, Ordering::Relaxed);
+        FLUSHED_GENERATION
+            .per_cpu_var_get()
+            .store(0, Ordering::Relaxed);
+        ASID_KERNEL
+    }
+}
+
+impl Drop for Asid {
+    fn drop(&mut self) {
+        let asid = self.asid.load(Ordering::Relaxed);
+
+        if asid == 0 {
+            return;
+        }
+
+        // Not under the lock, as other CPUs may spin on it with IRQs masked. Extra flush of ASID
+        // reused after rollover is harmless
+        shootdown(self, VirtAddr::from_bits(0), 0);
+
+        let mut allocator = ALLOCATOR.lock();
+
+        // ASIDs of previous generations are owned by someone else already
+        if generation(asid) == GENERATION.load(Ordering::Relaxed) {
+            allocator.free(asid);
+        }
+    }
+}
@@ -1,44 +1,43 @@
 use crate::arch::mm::mmu_flags::*;
-use hal::address::*;
 use rtl::vmm::*;
 
-const KERNEL_DATA: usize = BLOCK_KERNEL_RW | BLOCK_NORMAL_MEM;
-const KERNEL_TEXT: usize = BLOCK_KERNEL_RO & !BLOCK_PXN | BLOCK_NORMAL_MEM;
-const KERNEL_DATA_RO: usize = BLOCK_KERNEL_RO | BLOCK_NORMAL_MEM;
-const KERNEL_RWX: usize = BLOCK_KERNEL_RWX | BLOCK_NORMAL_MEM;
-const KERNEL_DEVICE: usize = BLOCK_KERNEL_RW | BLOCK_DEVICE_MEM;
-
-const USER_DATA: usize = BLOCK_USER_RW | BLOCK_NORMAL_MEM;
-const USER_DATA_RO: usize = BLOCK_USER_RO | BLOCK_NORMAL_MEM;
-const USER_TEXT: usize = BLOCK_USER_RO & !BLOCK_UXN | BLOCK_NORMAL_MEM;
-const USER_RWX: usize = BLOCK_USER_RWX | BLOCK_NORMAL_MEM;
-const USER_DEVICE: usize = BLOCK_USER_RW | BLOCK_DEVICE_MEM;
+fn cache_attr_to_flags(cache: CacheAttr) -> usize {
+    match cache {
+        CacheAttr::WriteBack => BLOCK_NORMAL_MEM,
+        // Normal non-cacheable memory already allows merging writes
+        CacheAttr::WriteCombining | CacheAttr::Uncached => BLOCK_NORMAL_NC_MEM,
+        CacheAttr::DeviceNGnRE => BLOCK_DEVICE_NGNRE_MEM,
+        CacheAttr::DeviceNGnRnE => BLOCK_DEVICE_MEM,
+    }
+}
 
+/// Translates mapping type into PTE attributes. Write or execute permission implies read one
 pub fn mapping_type_to_flags(tp: MappingType, user_mode: bool) -> usize {
-    if user_mode {
-        match tp {
-            MappingType::Data => USER_DATA,
-            MappingType::RoData => USER_DATA_RO,
-            MappingType::Text => USER_TEXT,
-            MappingType::Rwx => USER_RWX,
-            MappingType::Device => USER_DEVICE,
-            MappingType::None => 0,
-        }
+    if tp.is_none() {
+        return 0;
+    }
+
+    let write = tp.contains(MappingType::WRITE);
+    let exec = tp.contains(MappingType::EXECUTE);
+    let perms = if user_mode {
+        let perms = match (write, exec) {
+            (true, true) => BLOCK_USER_RWX,
+            (true, false) => BLOCK_USER_RW,
+            (false, true) => BLOCK_USER_RO & !BLOCK_UXN,
+            (false, false) => BLOCK_USER_RO,
+        };
+
+        // TLB entries of user mappings are tagged with ASID
+        perms | BLOCK_NON_GLOBAL
     } else {
-        match tp {
-            MappingType::Rwx => KERNEL_RWX,
-            MappingType::Text => KERNEL_TEXT,
-            MappingType::Data => KERNEL_DATA,
-            MappingType::RoData => KERNEL_DATA_RO,
-            MappingType::Device => KERNEL_DEVICE,
-            MappingType::None => 0,
+        assert!(!(write && exec), "Kernel mappings must be W^X");
+
+        match (write, exec) {
+            (true, _) => BLOCK_KERNEL_RW,
+            (false, true) => BLOCK_KERNEL_RO & !BLOCK_PXN,
+            (false, false) => BLOCK_KERNEL_RO,
         }
-    }
-}
+    };
 
-// TODO: less aggressive tlb maintainence (i.e last level flush with ASID support)
-pub unsafe fn flush_tlb_page_last(_v: VirtAddr) {
-    unsafe {
-        core::arch::asm!("tlbi       vmalle1");
-    }
+    perms | cache_attr_to_flags(tp.cache())
 }
@@ -22,14 +22,16 @@ pub const BLOCK_ACCESS_FLAG: usize = 1 << 10;
 /* Based on MAIR settings from mm::init() */
 pub const BLOCK_NORMAL_MEM: usize = mair_type(0);
 pub const BLOCK_DEVICE_MEM: usize = mair_type(1);
+pub const BLOCK_NORMAL_NC_MEM: usize = mair_type(2);
+pub const BLOCK_DEVICE_NGNRE_MEM: usize = mair_type(3);
 
-pub const BLOCK_KERNEL_RWX: usize = access_perms(AP_UN_KRW);
-pub const BLOCK_KERNEL_RW: usize = access_perms(AP_UN_KRW) | BLOCK_PXN;
-pub const BLOCK_KERNEL_RO: usize = access_perms(AP_UN_KRO) | BLOCK_PXN | (1 << 54) | (1 << 51);
+// User can't execute kernel pages and kernel can't execute user ones
+pub const BLOCK_KERNEL_RW: usize = access_perms(AP_UN_KRW) | BLOCK_PXN | BLOCK_UXN;
+pub const BLOCK_KERNEL_RO: usize = access_perms(AP_UN_KRO) | BLOCK_PXN | BLOCK_UXN | (1 << 51);
 
-pub const BLOCK_USER_RWX: usize = access_perms(AP_URW_KRW);
-pub const BLOCK_USER_RW: usize = access_perms(AP_URW_KRW) | BLOCK_UXN;
-pub const BLOCK_USER_RO: usize = access_perms(AP_URO_KRO) | BLOCK_UXN;
+pub const BLOCK_USER_RWX: usize = access_perms(AP_URW_KRW) | BLOCK_PXN;
+pub const BLOCK_USER_RW: usize = access_perms(AP_URW_KRW) | BLOCK_PXN | BLOCK_UXN;
+pub const BLOCK_USER_RO: usize = access_perms(AP_URO_KRO) | BLOCK_PXN | BLOCK_UXN;
 
 /* Page Table */
 pub const TABLE_VALID: usize = 0b11;
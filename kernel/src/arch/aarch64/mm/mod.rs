@@ -1,5 +1,7 @@
+pub mod asid;
 pub mod mmu;
 pub mod mmu_flags;
 pub mod page_table;
+pub mod tlb;
 
 core::arch::global_asm!(include_str!("copy_from_user.s"));
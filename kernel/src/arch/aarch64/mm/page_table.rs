@@ -1,3 +1,4 @@
+use super::asid::Asid;
 use crate::arch::PTE_PER_PAGE;
 use aarch64_cpu::registers::{TTBR0_EL1, Writeable};
 use core::arch::asm;
@@ -24,16 +25,94 @@ pub fn l3_linear_offset(va: VirtAddr) -> usize {
     (usize::from(va) >> 12) & (PTE_PER_PAGE - 1)
 }
 
-pub fn switch_context(pa: PhysAddr) {
-    TTBR0_EL1.set(pa.bits() as u64);
-    flush_tlb_all();
+/// Loads translation table base together with ASID of the address space. `None` means kernel
+/// page table
+pub fn switch_context(pa: PhysAddr, asid: Option<&Asid>) {
+    let asid = match asid {
+        Some(asid) => asid.activate(),
+        None => Asid::activate_kernel(),
+    };
+
+    TTBR0_EL1.set(((asid as u64) << 48) | pa.bits() as u64);
+
+    unsafe {
+        asm!("isb");
+    }
 }
 
 pub fn flush_tlb_all() {
     unsafe {
-        asm!("tlbi  vmalle1is");
+        asm!("tlbi  vmalle1is", "dsb ish", "isb");
     }
 
     // Disallow compiler reordering (just in case)
     compiler_fence(Ordering::SeqCst);
 }
+
+/// Flushes TLB of the current CPU only
+pub fn flush_tlb_local() {
+    unsafe {
+        asm!("tlbi  vmalle1", "dsb nsh", "isb");
+    }
+
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Flushes all entries tagged with `asid`
+pub fn flush_tlb_asid(asid: u16) {
+    unsafe {
+        asm!("tlbi  aside1is, {}", "dsb ish", "isb", in(reg) (asid as u64) << 48);
+    }
+
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Flushes entries for the `va`. Global (kernel) entries are flushed if `asid` is
+/// `None`. Caller must issue `dsb ish` once all pages are flushed
+pub fn flush_tlb_page(asid: Option<u16>, va: VirtAddr) {
+    let page = ((va.bits() >> 12) & ((1 << 44) - 1)) as u64;
+
+    unsafe {
+        match asid {
+            Some(asid) => asm!("tlbi  vae1is, {}", in(reg) ((asid as u64) << 48) | page),
+            None => asm!("tlbi  vaae1is, {}", in(reg) page),
+        }
+    }
+}
+
+/// Flushes all entries tagged with `asid` on the current CPU only
+pub fn flush_tlb_asid_local(asid: u16) {
+    unsafe {
+        asm!("tlbi  aside1, {}", "dsb nsh", "isb", in(reg) (asid as u64) << 48);
+    }
+
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Flushes entries for the `va` on the current CPU only. Caller must issue `dsb nsh` once all
+/// pages are flushed
+pub fn flush_tlb_page_local(asid: u16, va: VirtAddr) {
+    let page = ((va.bits() >> 12) & ((1 << 44) - 1)) as u64;
+
+    unsafe {
+        asm!("tlbi  vae1, {}", in(reg) ((asid as u64) << 48) | page);
+    }
+}
+
+/// Waits for completion of preceding [`flush_tlb_page_local`] calls
+pub fn flush_tlb_sync_local() {
+    unsafe {
+        asm!("dsb nsh", "isb");
+    }
+
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Waits for completion of preceding [`flush_tlb_page`] calls
+pub fn flush_tlb_sync() {
+    unsafe {
+        asm!("dsb ish", "isb");
+    }
+
+    compiler_fence(Ordering::SeqCst);
+}
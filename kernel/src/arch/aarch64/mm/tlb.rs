@@ -0,0 +1,105 @@
+//! TLB shootdown. Entries of user address space are cached only by CPUs which ran it, so only
+//! these CPUs are asked to flush them. Each CPU has its own request slot, so shootdowns issued
+//! by different CPUs don't wait for each other. Range is flushed by a single request.
+//!
+//! Requests may be issued with spinlocks held, so CPUs spinning on a lock serve them as well.
+//! Otherwise CPU spinning with IRQs masked would never answer the IPI.
+
+use super::asid::Asid;
+use super::page_table::{flush_tlb_asid_local, flush_tlb_page_local, flush_tlb_sync_local};
+use crate::arch::cpuid::current_cpu;
+use crate::smp::ipi::{Ipi, send_ipi};
+use crate::smp::{MAX_CPUS, num_cpus, online_cpus};
+use core::sync::atomic::{AtomicU16, AtomicUsize, Ordering};
+use hal::address::*;
+use hal::arch::PAGE_SIZE;
+
+/// Shootdown request. CPU waits for its request to complete, so it has at most one in flight
+struct Request {
+    asid: AtomicU16,
+    start: AtomicUsize,
+    // Number of pages to flush. 0 means all entries of the ASID
+    pages: AtomicUsize,
+    // CPUs which did not flush yet
+    pending: AtomicUsize,
+}
+
+impl Request {
+    const fn new() -> Self {
+        Self {
+            asid: AtomicU16::new(0),
+            start: AtomicUsize::new(0),
+            pages: AtomicUsize::new(0),
+            pending: AtomicUsize::new(0),
+        }
+    }
+}
+
+// Indexed by the requesting CPU
+static REQUESTS: [Request; MAX_CPUS] = [const { Request::new() }; MAX_CPUS];
+
+fn flush_local(asid: u16, start: usize, pages: usize) {
+    if pages == 0 {
+        flush_tlb_asid_local(asid);
+        return;
+    }
+
+    for i in 0..pages {
+        flush_tlb_page_local(asid, VirtAddr::from_bits(start + i * PAGE_SIZE));
+    }
+
+    flush_tlb_sync_local();
+}
+
+/// Serves requests of other CPUs, which are addressed to the current CPU
+pub fn handle_shootdown() {
+    let cpu = 1 << current_cpu();
+
+    for req in &REQUESTS[..num_cpus()] {
+        if req.pending.load(Ordering::Acquire) & cpu == 0 {
+            continue;
+        }
+
+        flush_local(
+            req.asid.load(Ordering::Relaxed),
+            req.start.load(Ordering::Relaxed),
+            req.pages.load(Ordering::Relaxed),
+        );
+        req.pending.fetch_and(!cpu, Ordering::Release);
+    }
+}
+
+/// Flushes `pages` pages starting at `start` or all entries of the address space, if `pages` is
+/// 0. Returns once all CPUs, which may cache them, are done
+pub fn shootdown(asid: &Asid, start: VirtAddr, pages: usize) {
+    let cpu = 1 << current_cpu();
+    let cpus = asid.cpus();
+    let targets = cpus & online_cpus() & !cpu;
+
+    if cpus & cpu != 0 {
+        flush_local(asid.value(), start.bits(), pages);
+    }
+
+    if targets == 0 {
+        return;
+    }
+
+    let req = &REQUESTS[current_cpu()];
+
+    debug_assert_eq!(req.pending.load(Ordering::Relaxed), 0);
+
+    req.asid.store(asid.value(), Ordering::Relaxed);
+    req.start.store(start.bits(), Ordering::Relaxed);
+    req.pages.store(pages, Ordering::Relaxed);
+    req.pending.store(targets, Ordering::Release);
+
+    for target in (0..num_cpus()).filter(|target| targets & (1 << target) != 0) {
+        send_ipi(target, Ipi::TlbShootdown);
+    }
+
+    while req.pending.load(Ordering::Acquire) != 0 {
+        // Targets may wait for their own requests to this CPU meanwhile
+        handle_shootdown();
+        core::hint::spin_loop();
+    }
+}
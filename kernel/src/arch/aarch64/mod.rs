@@ -12,14 +12,19 @@ use hal::arch::PAGE_SIZE;
 
 pub const PTE_PER_PAGE: usize = PAGE_SIZE / mem::size_of::<usize>();
 
-// TODO: dtb
-pub const NUM_CPUS: usize = 2;
 pub const PAGE_TABLE_LVLS: u8 = 3;
+/// Smallest block, which could be mapped by a single PTE above the last level
+pub const HUGE_PAGE_SIZE: usize = 1 << 21;
 
 pub fn init(arg: &loader_protocol::LoaderArg) {
     irq::handlers::set_up_vbar();
     crate::drivers::irq::gic::init(arg);
 }
 
+pub fn init_secondary() {
+    irq::handlers::set_up_vbar();
+    crate::drivers::irq::init_secondary();
+}
+
 global_asm!(include_str!("boot.s"));
 global_asm!(include_str!("context.s"));
@@ -1,7 +1,8 @@
 use crate::syscalls::SyscallArgs;
-use aarch64_cpu::registers::{ESR_EL1, Readable};
+use aarch64_cpu::registers::{ESR_EL1, FAR_EL1, Readable};
 use hal::address::VirtAddr;
 use rtl::error::ErrorType;
+use rtl::vmm::MappingType;
 
 unsafe extern "C" {
     // fn kernel_thread_entry_point();
@@ -24,6 +25,14 @@ pub enum TrapReason {
     // TODO: more (and it should be arch independent)
 }
 
+/// Memory access which caused the sync exception
+#[derive(Debug, Clone, Copy)]
+pub struct PageFault {
+    pub addr: VirtAddr,
+    /// Minimal mapping type, which allows the access
+    pub access: MappingType,
+}
+
 #[repr(C)]
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Context {
@@ -78,6 +87,29 @@ impl TryInto<SyscallArgs> for Context {
     }
 }
 
+impl PageFault {
+    // Write not Read bit of data abort ISS
+    const ESR_WNR: u64 = 1 << 6;
+
+    /// Decodes fault of the last taken exception. Returns None, if exception was not caused by
+    /// memory access. Must be called before anything else could fault
+    pub fn current() -> Option<Self> {
+        let access = match ESR_EL1.read(ESR_EL1::EC) {
+            // Instruction abort from lower or current EL
+            0b10_0000 | 0b10_0001 => MappingType::TEXT,
+            // Data abort from lower or current EL
+            0b10_0100 | 0b10_0101 if ESR_EL1.get() & Self::ESR_WNR != 0 => MappingType::DATA,
+            0b10_0100 | 0b10_0101 => MappingType::RODATA,
+            _ => return None,
+        };
+
+        Some(Self {
+            addr: VirtAddr::from_bits(FAR_EL1.get() as usize),
+            access,
+        })
+    }
+}
+
 impl Context {
     pub fn new(ep: VirtAddr, user_stack: VirtAddr, arg: usize) -> Self {
         let mut new: Self = unsafe { core::mem::zeroed() };
@@ -1,31 +1,88 @@
-// use core::arch::asm;
-// use rtl::vmm::types::*;
-
-// unsafe extern "C" {
-//     fn __reset();
-// }
-
-// x1 -- cpu to turn on
-// x2 -- entry point
-// pub unsafe fn boot_cpu(num: usize, ep: usize) {
-//     unsafe {
-//         asm!(
-//          ".equ PSCI_0_2_FN64_CPU_ON, 0xc4000003",
-//          "ldr    w0, =PSCI_0_2_FN64_CPU_ON",
-//          "mov    x3, #0",
-//          "hvc #0",
-//          in("x1") num, in("x2") ep, options(nostack)
-//         );
-//     }
-// }
-//
-// pub fn bring_up_cpus() {
-//     unsafe {
-//         for i in 1..2 {
-//             boot_cpu(
-//                 i,
-//                 PhysAddr::from(VirtAddr::from(__reset as *const u8 as usize)).get(),
-//             );
-//         }
-//     }
-// }
+//! Secondary CPU bring up via PSCI
+
+use aarch64_cpu::registers::{MAIR_EL1, Readable, SCTLR_EL1, TCR_EL1, TTBR1_EL1};
+use core::arch::asm;
+use hal::address::*;
+use loader_protocol::{LoaderArg, PsciMethod, SecondaryBoot};
+use rtl::error::ErrorType;
+
+const PSCI_0_2_FN64_CPU_ON: usize = 0xc4000003;
+const CACHE_LINE: usize = 64;
+
+unsafe extern "C" {
+    fn __secondary_start();
+}
+
+/// Writes back data cache lines of the range, so it could be read with MMU off
+fn clean_dcache(start: usize, size: usize) {
+    for line in (start & !(CACHE_LINE - 1)..start + size).step_by(CACHE_LINE) {
+        unsafe { asm!("dc cvac, {}", in(reg) line) };
+    }
+
+    unsafe { asm!("dsb sy") };
+}
+
+fn psci_cpu_on(method: PsciMethod, mpidr: usize, ep: usize, ctx: usize) -> isize {
+    let mut res = PSCI_0_2_FN64_CPU_ON;
+
+    unsafe {
+        match method {
+            PsciMethod::Hvc => asm!(
+                "hvc #0",
+                inout("x0") res,
+                in("x1") mpidr,
+                in("x2") ep,
+                in("x3") ctx,
+                clobber_abi("C"),
+            ),
+            PsciMethod::Smc => asm!(
+                "smc #0",
+                inout("x0") res,
+                in("x1") mpidr,
+                in("x2") ep,
+                in("x3") ctx,
+                clobber_abi("C"),
+            ),
+            PsciMethod::None => return -1,
+        }
+    }
+
+    res as isize
+}
+
+/// Starts CPU with number `cpu`. It runs with the same translation setup as the current one on
+/// `stack` and enters `start_secondary`. `boot` must stay allocated until CPU is online
+pub fn start_cpu(
+    arg: &LoaderArg,
+    cpu: usize,
+    stack: VirtAddr,
+    boot: LinearAddr,
+) -> Result<(), ErrorType> {
+    unsafe {
+        boot.to_raw_mut::<SecondaryBoot>().write(SecondaryBoot {
+            ttbr: TTBR1_EL1.get(),
+            tcr: TCR_EL1.get(),
+            mair: MAIR_EL1.get(),
+            sctlr: SCTLR_EL1.get(),
+            entry: __secondary_start as usize as u64,
+            stack: stack.bits() as u64,
+            arg: cpu as u64,
+        });
+    }
+
+    // Secondary CPU reads it with MMU and caches off
+    clean_dcache(boot.bits(), core::mem::size_of::<SecondaryBoot>());
+
+    match psci_cpu_on(
+        arg.psci,
+        arg.cpus[cpu],
+        arg.secondary_entry,
+        PhysAddr::from(boot).bits(),
+    ) {
+        0 => Ok(()),
+        err => {
+            error!("Failed to start CPU {cpu}: PSCI error {err}\n");
+            Err(ErrorType::InternalError)
+        }
+    }
+}
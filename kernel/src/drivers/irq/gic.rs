@@ -1,16 +1,19 @@
 use super::IrqController;
+use crate::arch::cpuid::current_cpu;
 use arm_gic::{
     IntId, UniqueMmioPointer,
-    gicv3::{GicCpuInterface, GicV3, Group, InterruptGroup},
+    gicv3::{GicCpuInterface, GicV3, Group, InterruptGroup, SgiTarget, SgiTargetGroup},
 };
+use core::arch::asm;
 use core::ptr::NonNull;
 use hal::address::VirtualAddress;
-use loader_protocol::{DeviceKind, LoaderArg};
+use loader_protocol::{DeviceKind, LoaderArg, MAX_CPUS};
 use rtl::irq::IrqTrigger;
 use crate::sync::spinlock::Spinlock;
 use spin::Once;
 
-pub struct Gic(GicV3<'static>);
+/// GIC and MPIDRs of CPUs SGIs are routed by
+pub struct Gic(GicV3<'static>, [usize; MAX_CPUS]);
 
 #[derive(Debug)]
 pub struct ClaimedIrq(pub IntId);
@@ -22,14 +25,19 @@ impl Gic {
 
         let gicd = unsafe { UniqueMmioPointer::new(NonNull::new(dist.0.to_raw_mut()).unwrap()) };
         let gicr = NonNull::new(redist.0.to_raw_mut()).unwrap();
-        let mut gic = unsafe { GicV3::new(gicd, gicr, 1, false) };
+        let cpus = arg.cpus.len().max(1);
+        let mut gic = unsafe { GicV3::new(gicd, gicr, cpus, false) };
 
         // Enable interrupts of all prios
         GicCpuInterface::set_priority_mask(0xff);
 
         // Initialise the GIC on BS CPU
         gic.setup(0);
-        Self(gic)
+
+        let mut mpidrs = [0; MAX_CPUS];
+
+        mpidrs[..arg.cpus.len()].copy_from_slice(&arg.cpus);
+        Self(gic, mpidrs)
     }
 }
 
@@ -46,17 +54,25 @@ impl IrqController for Spinlock<Gic> {
             IrqTrigger::Level => arm_gic::Trigger::Level,
         };
         let mut gic = self.lock_irqsave();
+        // SGIs and PPIs are configured in redistributor of the current CPU
+        let cpu = Some(if num.is_private() { current_cpu() } else { 0 });
+
+        gic.0.set_interrupt_priority(num, cpu, 0x80).unwrap();
+        gic.0.set_group(num, cpu, Group::Group1NS).unwrap();
 
-        gic.0.set_interrupt_priority(num, Some(0), 0x80).unwrap();
-        gic.0.set_group(num, Some(0), Group::Group1NS).unwrap();
-        gic.0.set_trigger(num, Some(0), trigger).unwrap();
-        gic.0.enable_interrupt(num, Some(0), true).unwrap();
+        // SGIs are always edge triggered
+        if !num.is_sgi() {
+            gic.0.set_trigger(num, cpu, trigger).unwrap();
+        }
+
+        gic.0.enable_interrupt(num, cpu, true).unwrap();
     }
 
     fn mask_irq(&self, num: super::IntId, mask: bool) {
         let mut gic = self.lock_irqsave();
+        let cpu = Some(if num.is_private() { current_cpu() } else { 0 });
 
-        gic.0.enable_interrupt(num, Some(0), !mask).unwrap();
+        gic.0.enable_interrupt(num, cpu, !mask).unwrap();
     }
 
     fn pending(&self) -> Option<IntId> {
@@ -66,6 +82,33 @@ impl IrqController for Spinlock<Gic> {
     fn eoi(&self, int: IntId) {
         GicCpuInterface::end_interrupt(int, InterruptGroup::Group1)
     }
+
+    fn init_cpu(&self, cpu: usize) {
+        self.lock_irqsave().0.init_cpu(cpu);
+
+        GicCpuInterface::set_priority_mask(0xff);
+        GicCpuInterface::enable_group1(true);
+        arm_gic::irq_enable();
+    }
+
+    fn send_sgi(&self, cpu: usize, sgi: IntId) {
+        let mpidr = self.lock_irqsave().1[cpu];
+
+        // Target must observe memory writes made before the interrupt
+        unsafe { asm!("dsb ishst") };
+
+        GicCpuInterface::send_sgi(
+            sgi,
+            SgiTarget::List {
+                affinity3: (mpidr >> 32) as u8,
+                affinity2: (mpidr >> 16) as u8,
+                affinity1: (mpidr >> 8) as u8,
+                target_list: 1 << (mpidr & 0xf),
+            },
+            SgiTargetGroup::CurrentGroup1,
+        )
+        .unwrap();
+    }
 }
 
 pub fn init(arg: &LoaderArg) {
@@ -1,3 +1,4 @@
+use crate::arch::cpuid::current_cpu;
 use crate::sync::Spinlock;
 use alloc::boxed::Box;
 use alloc::collections::LinkedList;
@@ -72,6 +73,21 @@ pub fn unregister_handler(irq: IntId) -> Result<(), ErrorType> {
     Ok(())
 }
 
+/// Enables per-CPU interrupt on the current CPU. Handler must be registered already
+pub fn enable_private(irq: IntId, trigger: IrqTrigger) {
+    CONTROLLER.get().unwrap().enable_irq(irq, trigger);
+}
+
+/// Raises software generated interrupt `sgi` on `cpu`
+pub fn send_sgi(cpu: usize, sgi: IntId) {
+    CONTROLLER.get().unwrap().send_sgi(cpu, sgi);
+}
+
+/// Sets up interrupt controller on secondary CPU
+pub fn init_secondary() {
+    CONTROLLER.get().unwrap().init_cpu(current_cpu());
+}
+
 pub fn irq_dispatch() {
     let controller = CONTROLLER.get().unwrap();
 
@@ -88,4 +104,6 @@ pub trait IrqController: Send + Sync {
     fn mask_irq(&self, num: IntId, mask: bool);
     fn pending(&self) -> Option<IntId>;
     fn eoi(&self, int: IntId);
+    fn init_cpu(&self, cpu: usize);
+    fn send_sgi(&self, cpu: usize, sgi: IntId);
 }
@@ -1,5 +1,5 @@
 use crate::arch::timer::{SYSTEM_TIMER, TIMER_IRQ_NUM};
-use crate::drivers::irq::register_handler;
+use crate::drivers::irq::{enable_private, register_handler};
 use crate::sched::ticks::SYSTEM_TICK;
 use arm_gic::IntId;
 use core::time::Duration;
@@ -18,6 +18,14 @@ pub fn init() {
     SYSTEM_TIMER.enable();
 }
 
+/// Starts tick on secondary CPU. Handler is shared with the boot CPU
+pub fn init_secondary() {
+    enable_private(TIMER_IRQ_NUM, IrqTrigger::Level);
+
+    SYSTEM_TIMER.reprogram(SYSTEM_TICK);
+    SYSTEM_TIMER.enable();
+}
+
 pub fn timer_dispatch(_: IntId) {
     crate::sched::ticks::tick();
     SYSTEM_TIMER.reprogram(SYSTEM_TICK);
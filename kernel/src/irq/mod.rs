@@ -1,5 +1,6 @@
 use crate::drivers::irq::{mask, register_handler, unmask, unregister_handler, IntId};
 use crate::object::KernelObjectBase;
+use crate::object::capabilities::{Capability, CapabilityMask};
 use crate::sync::Event;
 use crate::sync::Spinlock;
 use alloc::sync::Arc;
@@ -56,6 +57,12 @@ impl IrqObject {
         Ok(res)
     }
 
+    pub fn full_caps() -> CapabilityMask {
+        CapabilityMask::from(
+            Capability::Wait | Capability::Write | Capability::Duplicate | Capability::Transfer,
+        )
+    }
+
     pub fn ack(&self) -> Result<(), ErrorType> {
         let mut inner = self.inner.lock_irqsave();
 
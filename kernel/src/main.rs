@@ -12,6 +12,8 @@
 #![test_runner(crate::tests::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 
+#[cfg(not(test))]
+use crate::object::{handle::Handle, resource_object::ResourceObject};
 #[cfg(not(test))]
 use crate::{tasks::elf::prepare_initial_task, tasks::task::init_task};
 
@@ -29,6 +31,7 @@ mod logger;
 mod mm;
 mod object;
 mod panic;
+mod random;
 mod sched;
 mod sync;
 mod syscalls;
@@ -54,24 +57,36 @@ pub async fn init_userspace(prot: &loader_protocol::LoaderArg) {
     let ep = prepare_initial_task(prot).await.unwrap();
     let init_task = init_task();
 
+    let resource = Handle::new(ResourceObject::root(), ResourceObject::full_caps());
+
     init_task
-        .start(ep, None)
+        .start(ep, None, Some(resource))
         .await
         .expect("Failed to start first task");
 }
 
 #[unsafe(no_mangle)]
 extern "C" fn start_kernel(prot: &'static loader_protocol::LoaderArg) -> ! {
+    // Placement of the linear map is randomized by the loader
+    let (linear_base, _) = prot
+        .get_vmm_base(loader_protocol::VmmLayoutKind::LinearMap)
+        .unwrap();
+
+    hal::address::set_linear_base(linear_base.bits());
     drivers::init_logging(prot);
 
     logger::init();
     info!("Booting kernel...\n");
     arch::init(prot);
+    random::init(prot);
 
     mm::init(prot);
-    smp::init_percpu();
+    smp::init_percpu(prot);
     drivers::init(prot);
 
+    #[cfg(not(test))]
+    smp::start_secondaries(prot);
+
     info!("\n{SAMOS_BANNER}\n");
 
     #[cfg(not(test))]
@@ -94,18 +109,17 @@ extern "C" fn start_kernel(prot: &'static loader_protocol::LoaderArg) -> ! {
 }
 
 #[unsafe(no_mangle)]
-extern "C" fn cpu_reset() -> ! {
-    // println!("Cpu {} started!", arch::cpuid::current_cpu());
-    //
-    // arch::irq::handlers::set_up_vbar();
-    // arm_gic::irq_enable();
-    // drivers::timer::init_secondary();
-    //
-    /*
-     * Runqueue for current cpu should already contain
-     * idle thread, so just loop until timer irq
-     */
+extern "C" fn start_secondary() -> ! {
+    arch::init_secondary();
+    drivers::timer::init_secondary();
+    smp::ipi::init_secondary();
+    smp::set_online();
+
+    info!("CPU {} started\n", arch::cpuid::current_cpu());
 
     #[allow(clippy::empty_loop)]
-    loop {}
+    {
+        sched::run();
+        loop {}
+    }
 }
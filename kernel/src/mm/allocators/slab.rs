@@ -4,10 +4,11 @@ use core::alloc::Layout;
 use core::ptr::NonNull;
 use hal::address::*;
 use hal::arch::PAGE_SIZE;
+use rtl::meminfo::{SLAB_CLASSES, SlabInfo};
 
 const MIN_SLAB_SIZE: usize = 8;
 
-static KERNEL_SLABS: [Spinlock<SlabAllocator>; 12] = [
+static KERNEL_SLABS: [Spinlock<SlabAllocator>; SLAB_CLASSES] = [
     Spinlock::new(SlabAllocator::default()),
     Spinlock::new(SlabAllocator::default()),
     Spinlock::new(SlabAllocator::default()),
@@ -44,6 +45,19 @@ pub fn free(ptr: *mut u8, l: Layout) {
     }
 }
 
+/// Usage of every slab size class
+pub fn slab_info() -> [SlabInfo; SLAB_CLASSES] {
+    core::array::from_fn(|i| {
+        let slab = KERNEL_SLABS[i].lock();
+
+        SlabInfo {
+            size: slab.slab_size,
+            used: slab.used,
+            pages: slab.pages,
+        }
+    })
+}
+
 pub fn init_kernel_slabs() -> Option<()> {
     let mut size = MIN_SLAB_SIZE;
 
@@ -58,6 +72,9 @@ pub fn init_kernel_slabs() -> Option<()> {
 pub struct SlabAllocator {
     slab_size: usize,
     freelist: FreeList,
+    // Allocated objects and pages backing them
+    used: usize,
+    pages: usize,
 }
 
 struct FreeList {
@@ -71,6 +88,8 @@ impl SlabAllocator {
         Self {
             slab_size: 0,
             freelist: FreeList::default(),
+            used: 0,
+            pages: 0,
         }
     }
 
@@ -78,19 +97,25 @@ impl SlabAllocator {
         Some(Self {
             slab_size: size,
             freelist: FreeList::new(size)?,
+            used: 0,
+            pages: FreeList::pages(size),
         })
     }
 
     pub fn alloc(&mut self) -> Option<*mut u8> {
-        match self.freelist.alloc().map(|ptr| ptr as *mut u8) {
+        let ptr = match self.freelist.alloc().map(|ptr| ptr as *mut u8) {
             Some(ptr) => Some(ptr),
             None => {
                 self.freelist = FreeList::new(self.slab_size)?;
+                self.pages += FreeList::pages(self.slab_size);
                 self.freelist
                     .alloc()
                     .map(|ptr: *mut FreeList| ptr as *mut u8)
             }
-        }
+        };
+
+        self.used += ptr.is_some() as usize;
+        ptr
     }
 
     pub unsafe fn free(&mut self, addr: *mut u8) {
@@ -101,6 +126,7 @@ impl SlabAllocator {
             let slice = core::slice::from_raw_parts_mut(addr, self.slab_size);
 
             slice.fill(0xa5);
+            self.used -= 1;
             self.freelist
                 .add_to_freelist(NonNull::new_unchecked(addr as *mut FreeList));
         }
@@ -108,11 +134,15 @@ impl SlabAllocator {
 }
 
 impl FreeList {
+    fn pages(size: usize) -> usize {
+        size.div_ceil(PAGE_SIZE)
+    }
+
     /* Allocate one page for the beginning */
     pub fn new(size: usize) -> Option<Self> {
         assert!(size.is_power_of_two());
 
-        let pages = size.div_ceil(PAGE_SIZE);
+        let pages = Self::pages(size);
         let pa = if pages > 1 {
             page_allocator().alloc_contigious(pages)?
         } else {
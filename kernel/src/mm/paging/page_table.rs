@@ -1,9 +1,13 @@
 use crate::{
-    arch::mm::mmu::{self, *},
+    arch::mm::asid::Asid,
+    arch::mm::mmu,
+    arch::mm::page_table::{flush_tlb_all, flush_tlb_page, flush_tlb_sync},
+    arch::mm::tlb::shootdown,
     arch::{self, mm::mmu_flags},
-    mm::pmm::page_alloc::page_allocator,
+    mm::pmm::page_alloc::alloc_page,
     mm::pmm::page_list::PageListIterator,
 };
+use alloc::sync::Arc;
 use hal::address::*;
 use hal::arch::PAGE_SIZE;
 use rtl::error::ErrorType;
@@ -21,11 +25,22 @@ pub struct PageTableBlock {
 #[derive(Clone, Copy, Debug)]
 pub struct PageTableEntry(usize);
 
+/// Operation applied to a range of page table
+enum PageTableOp<'a, S: PageSource> {
+    Map(&'a mut S, MappingType),
+    Protect(MappingType),
+    Unmap,
+}
+
 pub struct PageTable {
     base: LinearAddr,
     is_user: bool,
+    asid: Option<Arc<Asid>>,
 }
 
+/// Ranges larger than this are flushed by ASID instead of page by page
+const TLB_FLUSH_PAGES_MAX: usize = 64;
+
 fn empty_page_source() -> Option<&'static mut impl PageSource> {
     None::<&mut MemRange<PhysAddr>>
 }
@@ -90,6 +105,7 @@ impl PageTableBlock {
         }
     }
 
+    /// Next level table of the entry. Blocks and invalid entries have none
     pub fn next(&self, index: usize) -> Option<Self> {
         if self.is_last() {
             None
@@ -99,7 +115,7 @@ impl PageTableBlock {
                 PageTableEntry::from_bits(va.to_raw::<usize>().add(index).read_volatile())
             };
 
-            if entry_next.valid() {
+            if entry_next.is_table() {
                 Some(Self::new(LinearAddr::from(entry_next.addr()), self.lvl + 1))
             } else {
                 None
@@ -111,15 +127,24 @@ impl PageTableBlock {
 /// Page source for the mapping
 pub trait PageSource {
     fn next_page(&mut self) -> Option<PhysAddr>;
+
+    /// Takes `size` bytes of contiguous memory aligned to `size`, if the source starts with them
+    fn next_block(&mut self, _size: usize) -> Option<PhysAddr> {
+        None
+    }
 }
 
 impl PageSource for MemRange<PhysAddr> {
     fn next_page(&mut self) -> Option<PhysAddr> {
-        if self.size != 0 {
+        self.next_block(PAGE_SIZE)
+    }
+
+    fn next_block(&mut self, size: usize) -> Option<PhysAddr> {
+        if self.size >= size && self.start.bits() & (size - 1) == 0 {
             let page = self.start;
 
-            self.size -= PAGE_SIZE;
-            self.start = self.start + PAGE_SIZE;
+            self.size -= size;
+            self.start = self.start + size;
             Some(page)
         } else {
             None
@@ -160,236 +185,201 @@ impl PageTable {
         Self {
             base: LinearAddr::from(base),
             is_user: false,
+            asid: None,
         }
     }
 
+    /// Physical address of the page `va` belongs to
     pub fn translate(&self, va: VirtAddr) -> Option<PhysAddr> {
         let mut base = self.lvl0();
 
-        for lvl in 0..=arch::PAGE_TABLE_LVLS {
+        loop {
             let index = base.index_of(va);
 
-            if lvl != arch::PAGE_TABLE_LVLS {
-                base = base.next(index)?;
-            } else {
-                return Some(base.get_pte(index).addr());
+            if let Some(next) = base.next(index) {
+                base = next;
+                continue;
             }
-        }
 
-        panic!("Invalid page SW page table walk")
+            let pte = base.get_pte(index);
+            let offset = va.bits() & ((1 << Self::entry_order(base.lvl())) - 1) & !(PAGE_SIZE - 1);
+
+            return pte.valid().then(|| pte.addr() + offset);
+        }
     }
 
     pub fn new() -> Option<Self> {
-        let base: PhysAddr = page_allocator()
-            .alloc_pages(1)?
-            .pop_front()
-            .unwrap()
-            .pfn()
-            .into();
+        let base = alloc_page()?;
         let new_table = Self {
             base: LinearAddr::from(base),
             is_user: true,
+            asid: Some(Arc::try_new(Asid::new()).ok()?),
         };
 
         Some(new_table)
     }
 
-    fn set_leaf_pte(
-        b: &mut PageTableBlock,
-        index: usize,
-        pa: PhysAddr,
-        tp: MappingType,
-        lvl: u8,
-        v: VirtAddr,
-        is_user: bool,
-    ) {
+    /// Order of memory size an entry of level `lvl` maps
+    fn entry_order(lvl: u8) -> usize {
+        match lvl {
+            0 => 39,
+            1 => 30,
+            2 => 21,
+            3 => 12,
+            _ => panic!("Kernel supports 4 lvl page table"),
+        }
+    }
+
+    fn leaf_entry(pa: PhysAddr, tp: MappingType, lvl: u8, is_user: bool) -> PageTableEntry {
         let flags = mmu::mapping_type_to_flags(tp, is_user);
-        let control = if lvl != 3 {
+        let control = if lvl != arch::PAGE_TABLE_LVLS {
             PageFlags::block().bits()
         } else {
             PageFlags::page().bits()
         };
 
-        assert!(
-            !b.is_valid_pte(index),
-            "PTE addr {:p}, PTE content {:x}",
-            b.pte_addr(index),
-            b.get_pte(index).bits()
-        );
-
-        unsafe {
-            b.set_pte(
-                index,
-                PageTableEntry::from_bits(control | flags | pa.bits()),
-            );
-
-            // TODO: this is bad... Need to check if PTE was valid. Otherwise flush is redundant
-            flush_tlb_page_last(v);
-        };
+        PageTableEntry::from_bits(control | flags | pa.bits())
     }
 
-    fn allocate_new_block(
-        b: &mut PageTableBlock,
-        lvl: u8,
-        index: usize,
-    ) -> Result<PageTableBlock, ErrorType> {
-        let new_page: PhysAddr = page_allocator()
-            .alloc_pages(1)
-            .ok_or(ErrorType::NoMemory)?
-            .pop_front()
-            .unwrap()
-            .pfn()
-            .into();
-        let new_entry = PageTableEntry::from_bits(PageFlags::table().bits() | new_page.bits());
-
-        unsafe { b.set_pte(index, new_entry) };
-        Ok(PageTableBlock::new(LinearAddr::from(new_page), lvl + 1))
-    }
-
-    fn abort_walk(
-        _b: &mut PageTableBlock,
-        _lvl: u8,
-        _index: usize,
-    ) -> Result<PageTableBlock, ErrorType> {
-        Err(ErrorType::Generic)
+    fn table_entry(table: PhysAddr) -> PageTableEntry {
+        PageTableEntry::from_bits(PageFlags::table().bits() | table.bits())
     }
 
-    fn clean_tte(
-        b: &mut PageTableBlock,
-        index: usize,
-        _pa: PhysAddr,
-        _tp: MappingType,
-        _lvl: u8,
-        v: VirtAddr,
-    ) {
-        unsafe {
-            b.set_pte(index, PageTableEntry::from_bits(0));
-            flush_tlb_page_last(v);
-        };
+    fn allocate_table() -> Result<PhysAddr, ErrorType> {
+        alloc_page().ok_or(ErrorType::NoMemory)
     }
 
-    fn protect_leaf_pte(
+    /// Replaces block entry with a table of smaller entries, which map the same memory with the
+    /// same attributes
+    fn split_block(
+        &self,
         b: &mut PageTableBlock,
         index: usize,
-        pa: PhysAddr,
-        tp: MappingType,
-        lvl: u8,
-        v: VirtAddr,
-        is_user: bool,
-    ) {
-        let flags = mmu::mapping_type_to_flags(tp, is_user);
-        let control = if lvl != 3 {
-            PageFlags::block().bits()
-        } else {
+        va: VirtAddr,
+    ) -> Result<PageTableBlock, ErrorType> {
+        let pte = b.get_pte(index);
+        let table = Self::allocate_table()?;
+        let mut next = PageTableBlock::new(LinearAddr::from(table), b.lvl() + 1);
+        let size = 1 << Self::entry_order(next.lvl());
+        let control = if next.is_last() {
             PageFlags::page().bits()
+        } else {
+            PageFlags::block().bits()
         };
+        let attrs = pte.flags().bits() & !0b11;
 
-        assert!(b.is_valid_pte(index));
+        for i in 0..arch::PTE_PER_PAGE {
+            let pa = pte.addr() + i * size;
 
-        unsafe {
-            b.set_pte(
-                index,
-                PageTableEntry::from_bits(control | flags | pa.bits()),
-            );
-            flush_tlb_page_last(v);
+            unsafe { next.set_pte(i, PageTableEntry::from_bits(control | attrs | pa.bits())) };
         }
+
+        // Break-before-make. Kernel may be running from the block being split, so its entries
+        // are replaced in place, which is fine since old and new translations are the same
+        if let Some(asid) = &self.asid {
+            unsafe { b.set_pte(index, PageTableEntry::from_bits(0)) };
+            shootdown(asid, va, 1);
+        }
+
+        unsafe { b.set_pte(index, Self::table_entry(table)) };
+        Ok(next)
     }
 
-    #[allow(clippy::too_many_arguments)]
-    fn op_lvl<
-        F: FnMut(&mut PageTableBlock, usize, PhysAddr, MappingType, u8, VirtAddr, bool) + Copy, // Set leaf
-        G: FnMut(&mut PageTableBlock, u8, usize) -> Result<PageTableBlock, ErrorType> + Copy, // Process walk
-    >(
-        mut base: PageTableBlock,
-        lvl: u8,
+    /// Applies `op` to the part of `v` covered by `b`. Blocks are used for mapping, if source
+    /// memory and range allow, and split if operation covers them partially
+    fn walk<S: PageSource>(
+        &self,
+        mut b: PageTableBlock,
         v: &mut MemRange<VirtAddr>,
-        mut p: Option<&mut impl PageSource>,
-        map: MappingType,
-        mut cb: F,
-        mut cb_b: G,
-        use_huge_pages: bool,
-        is_user: bool,
-    ) -> Result<VirtAddr, ErrorType> {
-        let order = match lvl {
-            0 => 39,
-            1 => 30,
-            2 => 21,
-            3 => 12,
-            _ => panic!("Kernel supports 4 lvl page table"),
-        };
+        op: &mut PageTableOp<'_, S>,
+    ) -> Result<(), ErrorType> {
+        let order = Self::entry_order(b.lvl());
         let size = 1 << order;
-        let res = v.start();
 
         while {
-            let index = base.index_of(v.start());
-
-            if lvl < arch::PAGE_TABLE_LVLS
-                && !(use_huge_pages
-                    && v.start().is_aligned(order)
-                    && v.size().next_multiple_of(1 << order) == v.size())
-            {
-                let next_block = match base.next(index) {
-                    Some(e) => e,
-                    None => cb_b(&mut base, lvl, index)?,
-                };
-
-                Self::op_lvl(
-                    next_block,
-                    lvl + 1,
-                    v,
-                    p.as_deref_mut(),
-                    map,
-                    cb,
-                    cb_b,
-                    use_huge_pages,
-                    is_user,
-                )?;
+            let index = b.index_of(v.start());
+            let pte = b.get_pte(index);
+            let whole = v.start().is_aligned(order) && v.size() >= size;
+
+            if !pte.valid() {
+                match op {
+                    PageTableOp::Map(source, tp) => {
+                        // Level 0 entries can't be blocks
+                        let pa = if b.is_last() {
+                            Some(source.next_page().ok_or(ErrorType::InvalidArgument)?)
+                        } else if whole && b.lvl() != 0 {
+                            source.next_block(size)
+                        } else {
+                            None
+                        };
+
+                        if let Some(pa) = pa {
+                            let entry = Self::leaf_entry(pa, *tp, b.lvl(), self.is_user);
+
+                            // PTE was invalid, so TLB can't cache it and no flush is needed
+                            unsafe { b.set_pte(index, entry) };
+                            v.truncate(size);
+                        } else {
+                            let table = Self::allocate_table()?;
+
+                            unsafe { b.set_pte(index, Self::table_entry(table)) };
+                            self.walk(
+                                PageTableBlock::new(LinearAddr::from(table), b.lvl() + 1),
+                                v,
+                                op,
+                            )?;
+                        }
+                    }
+                    // Range may have holes, if memory is committed on demand
+                    _ => {
+                        let left = size - (v.start().bits() & (size - 1));
+
+                        v.truncate(left.min(v.size()));
+                    }
+                }
+            } else if let Some(next) = b.next(index) {
+                self.walk(next, v, op)?;
             } else {
-                debug_assert!(v.start().is_aligned(order));
-
-                let pa = match p.as_deref_mut() {
-                    Some(source) => source.next_page().ok_or(ErrorType::InvalidArgument)?,
-                    None => base.get_pte(index).addr(),
-                };
-
-                cb(&mut base, index, pa, map, lvl, v.start(), is_user);
-
-                v.truncate(size);
+                match op {
+                    PageTableOp::Map(..) => panic!(
+                        "PTE addr {:p}, PTE content {:x}",
+                        b.pte_addr(index),
+                        pte.bits()
+                    ),
+                    PageTableOp::Unmap if whole => {
+                        unsafe { b.set_pte(index, PageTableEntry::from_bits(0)) };
+                        v.truncate(size);
+                    }
+                    PageTableOp::Protect(tp) if whole => {
+                        let entry = Self::leaf_entry(pte.addr(), *tp, b.lvl(), self.is_user);
+
+                        unsafe { b.set_pte(index, entry) };
+                        v.truncate(size);
+                    }
+                    _ => {
+                        let next = self.split_block(&mut b, index, v.start())?;
+
+                        self.walk(next, v, op)?;
+                    }
+                }
             }
 
             v.size() != 0 && index != (arch::PTE_PER_PAGE - 1)
         } {}
 
-        Ok(res)
+        Ok(())
     }
 
-    fn map_internal(
+    pub fn map(
         &mut self,
         mut p: impl PageSource,
         mut v: MemRange<VirtAddr>,
         m_type: MappingType,
-        hp: bool,
     ) -> Result<VirtAddr, ErrorType> {
-        Self::op_lvl(
-            self.lvl0(),
-            0,
-            &mut v,
-            Some(&mut p),
-            m_type,
-            Self::set_leaf_pte,
-            Self::allocate_new_block,
-            hp,
-            self.is_user,
-        )
-    }
+        let res = v.start();
 
-    pub fn map(
-        &mut self,
-        p: impl PageSource,
-        v: MemRange<VirtAddr>,
-        m_type: MappingType,
-    ) -> Result<VirtAddr, ErrorType> {
-        self.map_internal(p, v, m_type, false)
+        self.walk(self.lvl0(), &mut v, &mut PageTableOp::Map(&mut p, m_type))?;
+        Ok(res)
     }
 
     pub fn protect(
@@ -397,18 +387,16 @@ impl PageTable {
         mut v: MemRange<VirtAddr>,
         m_type: MappingType,
     ) -> Result<(), ErrorType> {
-        Self::op_lvl(
+        let range = v;
+        let res = self.walk(
             self.lvl0(),
-            0,
             &mut v,
-            empty_page_source(),
-            m_type,
-            Self::protect_leaf_pte,
-            Self::abort_walk,
-            true,
-            self.is_user,
-        )
-        .map(|_| ())
+            &mut PageTableOp::<MemRange<PhysAddr>>::Protect(m_type),
+        );
+
+        // Part of the range could be changed even on error
+        self.flush_range(range);
+        res
     }
 
     pub fn map_linear(
@@ -419,24 +407,53 @@ impl PageTable {
         let v_range: MemRange<LinearAddr> = MemRange::new(p.start().into(), p.size());
         let va_range: MemRange<VirtAddr> = MemRange::new(v_range.start().into(), v_range.size());
 
-        self.map_internal(p, va_range, m_type, false)
+        self.map(p, va_range, m_type)
     }
 
     pub fn unmap(&mut self, mut v: MemRange<VirtAddr>) -> Result<(), ErrorType> {
-        Self::op_lvl(
+        let range = v;
+        let res = self.walk(
             self.lvl0(),
-            0,
             &mut v,
-            empty_page_source(),
-            MappingType::None,
-            |base, index, pa, tp, lvl, v, _| {
-                Self::clean_tte(base, index, pa, tp, lvl, v);
-            },
-            Self::abort_walk,
-            true,
-            self.is_user,
-        )
-        .map(|_| ())
+            &mut PageTableOp::<MemRange<PhysAddr>>::Unmap,
+        );
+
+        self.flush_range(range);
+        res
+    }
+
+    /// Invalidates TLB entries for the range after its PTEs were changed or cleared
+    fn flush_range(&self, v: MemRange<VirtAddr>) {
+        let pages = v.size() / PAGE_SIZE;
+
+        // Kernel entries may be cached by any CPU, so they are flushed everywhere
+        let Some(asid) = &self.asid else {
+            if pages > TLB_FLUSH_PAGES_MAX {
+                flush_tlb_all();
+            } else {
+                for page in (v.start().bits()..v.start().bits() + v.size()).step_by(PAGE_SIZE) {
+                    flush_tlb_page(None, VirtAddr::from_bits(page));
+                }
+
+                flush_tlb_sync();
+            }
+
+            return;
+        };
+
+        // 0 pages flushes all entries of the ASID
+        let pages = if pages > TLB_FLUSH_PAGES_MAX {
+            0
+        } else {
+            pages
+        };
+
+        shootdown(asid, v.start(), pages);
+    }
+
+    /// ASID TLB entries of the table are tagged with. Kernel table has none
+    pub fn asid(&self) -> Option<&Arc<Asid>> {
+        self.asid.as_ref()
     }
 
     #[inline]
@@ -470,6 +487,11 @@ impl PageTableEntry {
     pub fn valid(&self) -> bool {
         self.0 & 0b11 != 0
     }
+
+    /// Whether entry points to the next level table. Meaningless for the last level
+    pub fn is_table(&self) -> bool {
+        self.0 & 0b11 == mmu_flags::TABLE_VALID
+    }
 }
 
 impl core::fmt::Debug for PageTableBlock {
@@ -480,3 +502,37 @@ impl core::fmt::Debug for PageTableBlock {
         ))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+    use test_macros::*;
+
+    #[kernel_test]
+    fn page_table_split_block() {
+        let mut table = PageTable::new().unwrap();
+        let block = 1 << PageTable::entry_order(2);
+        let pa = MemRange::new(PhysAddr::from_bits(0x4000_0000), block);
+        let va = VirtAddr::from_bits(0x4000_0000);
+
+        table
+            .map(pa, MemRange::new(va, block), MappingType::DATA)
+            .unwrap();
+        test_assert_eq!(
+            table.translate(va + PAGE_SIZE),
+            Some(pa.start() + PAGE_SIZE)
+        );
+
+        // Unmapping a page in the middle of the block keeps the rest mapped
+        table
+            .unmap(MemRange::new(va + PAGE_SIZE, PAGE_SIZE))
+            .unwrap();
+        test_assert_eq!(table.translate(va + PAGE_SIZE), None);
+        test_assert_eq!(table.translate(va), Some(pa.start()));
+        test_assert_eq!(
+            table.translate(va + 2 * PAGE_SIZE),
+            Some(pa.start() + 2 * PAGE_SIZE)
+        );
+    }
+}
@@ -1,38 +1,140 @@
+//! Buddy page allocator
+//!
+//! Free memory is kept as naturally aligned blocks of 2^order pages, one list per order. Freed
+//! block is merged with its buddy, if the buddy is free as well. Single pages are served from
+//! per-CPU caches, so page faults rarely touch the global lock.
+
 use crate::mm::memset_pages;
 use crate::mm::pmm::page::Page;
-use crate::mm::pmm::page_list::PageList;
+use crate::mm::pmm::page_list::{PageList, pfn_to_halpage};
 use crate::mm::pmm::phys_layout::phys_info;
+use crate::smp::{num_cpus, percpu_ready};
 use crate::sync::{Spinlock, spinlock::SpinlockGuard};
 use hal::address::*;
+use hal::page::{Page as HalPage, PageState};
+
+/// Largest block is 1 GiB
+const MAX_ORDER: usize = 18;
+
+/// Pages moved between per-CPU cache and the global allocator at once
+const PCP_BATCH: usize = 16;
+const PCP_HIGH: usize = PCP_BATCH * 4;
 
 pub struct PageAlloc {
-    list: PageList,
+    free: [PageList; MAX_ORDER + 1],
+    free_pages: usize,
+    total_pages: usize,
+}
+
+/// Pages cached by a CPU. They are counted as allocated by [`PageAlloc`]
+struct PageCache {
+    pages: PageList,
 }
 
 pub static PAGE_ALLOC: Spinlock<PageAlloc> = Spinlock::new(PageAlloc::default());
 
+percpu_global! {
+    static PAGE_CACHE: Spinlock<PageCache> = Spinlock::new(PageCache {
+        pages: PageList::default(),
+    });
+}
+
 pub fn page_allocator() -> SpinlockGuard<'static, PageAlloc> {
     PAGE_ALLOC.lock()
 }
 
+fn halpage(pfn: Pfn) -> &'static mut HalPage {
+    unsafe { pfn_to_halpage(pfn).as_mut() }
+}
+
 impl PageAlloc {
     const fn default() -> Self {
         Self {
-            list: PageList::default(),
+            free: [const { PageList::default() }; MAX_ORDER + 1],
+            free_pages: 0,
+            total_pages: 0,
+        }
+    }
+
+    /// Buddy of the block, if it's backed by the page array
+    fn buddy(pfn: Pfn, order: usize) -> Option<Pfn> {
+        let buddy = Pfn::from(usize::from(pfn) ^ (1 << order));
+        let info = phys_info();
+
+        (info.lowest_pfn() <= buddy && buddy < info.highest_pfn()).then_some(buddy)
+    }
+
+    fn push_block(&mut self, pfn: Pfn, order: usize) {
+        self.free[order].push_front_with_cb(Page(pfn), |page| {
+            page.mark_free();
+            page.order = order as u8;
+        });
+    }
+
+    fn alloc_order(&mut self, order: usize) -> Option<Pfn> {
+        let mut current = (order..=MAX_ORDER).find(|&o| self.free[o].pages() != 0)?;
+        let pfn = self.free[current]
+            .pop_front_with_cb(|page| {
+                assert!(page.is_free());
+                page.mark_occupied();
+            })?
+            .pfn();
+
+        // Return upper halves of the split block
+        while current > order {
+            current -= 1;
+            self.push_block(pfn + (1 << current), current);
+        }
+
+        self.free_pages -= 1 << order;
+        Some(pfn)
+    }
+
+    fn free_order(&mut self, mut pfn: Pfn, mut order: usize) {
+        self.free_pages += 1 << order;
+
+        while order < MAX_ORDER {
+            let Some(buddy) = Self::buddy(pfn, order) else {
+                break;
+            };
+            let page = halpage(buddy);
+
+            if !page.is_free() || page.order as usize != order {
+                break;
+            }
+
+            unsafe {
+                self.free[order].remove_pfn_with_cb(buddy, |page| page.mark_occupied());
+            }
+
+            pfn = pfn.min(buddy);
+            order += 1;
+        }
+
+        self.push_block(pfn, order);
+    }
+
+    /// Frees arbitrary range of pages by splitting it into aligned blocks
+    fn free_range(&mut self, mut pfn: Pfn, mut count: usize) {
+        while count != 0 {
+            let order = (usize::from(pfn).trailing_zeros() as usize)
+                .min(count.ilog2() as usize)
+                .min(MAX_ORDER);
+
+            self.free_order(pfn, order);
+            pfn = pfn + (1 << order);
+            count -= 1 << order;
         }
     }
 
     fn alloc_page(&mut self) -> Option<Page> {
-        let next = self.list.pop_front_with_cb(|page| {
-            assert!(page.is_free());
-            page.mark_occupied();
-        })?;
+        let next = self.alloc_order(0)?;
 
         unsafe {
-            memset_pages(next.0, 1);
+            memset_pages(next, 1);
         }
 
-        Some(next)
+        Some(Page(next))
     }
 
     pub fn alloc_pages(&mut self, count: usize) -> Option<PageList> {
@@ -54,91 +156,184 @@ impl PageAlloc {
     }
 
     pub fn alloc_contigious(&mut self, count: usize) -> Option<PhysAddr> {
-        let mut found_pfn: Option<Pfn> = None;
+        self.alloc_contigious_aligned(count, 1)
+    }
 
-        'outer: for reg in phys_info().regions() {
-            if count > reg.size() / hal::arch::PAGE_SIZE {
-                continue;
-            }
+    /// Allocates `count` zeroed contiguous pages, first of which is aligned to `align` pages.
+    /// `align` must be a power of two
+    pub fn alloc_contigious_aligned(&mut self, count: usize, align: usize) -> Option<PhysAddr> {
+        assert!(align.is_power_of_two());
 
-            let mut candidate: Option<Pfn> = None;
-            let mut found = 0;
-
-            for pfn in reg.pfn_range() {
-                unsafe {
-                    let page = super::page_list::pfn_to_halpage(pfn);
-
-                    if page.as_ref().is_free() {
-                        if candidate.is_none() {
-                            candidate = Some(pfn);
-                            found = 1;
-                        } else {
-                            found += 1;
-                        }
-                    } else {
-                        candidate = None;
-                        found = 0;
-                    }
-
-                    if found == count {
-                        found_pfn = candidate;
-                        break 'outer;
-                    }
-                }
-            }
+        let order = count.next_power_of_two().max(align).ilog2() as usize;
+
+        if order > MAX_ORDER {
+            return None;
         }
 
-        if let Some(pfn) = found_pfn {
-            for i in 0..count {
-                unsafe {
-                    self.list.remove_pfn_with_cb(pfn + i, |page| {
-                        assert!(page.is_free());
-                        page.mark_occupied();
-                    });
-                }
-            }
+        let pfn = self.alloc_order(order)?;
+
+        // Blocks are naturally aligned, so only the tail needs to be returned
+        self.free_range(pfn + count, (1 << order) - count);
+
+        unsafe {
+            memset_pages(pfn, count);
         }
 
-        found_pfn.map(|x| x.into())
+        Some(pfn.into())
     }
 
     pub fn free_contig(&mut self, pa: PhysAddr, count: usize) {
         let pfn: Pfn = pa.into();
 
         for i in 0..count {
-            self.list.push_back_with_cb(Page(pfn + i), |page| {
-                assert!(!page.is_free());
-                page.mark_free();
-            });
+            let page = halpage(pfn + i);
+
+            assert!(!page.is_free());
         }
+
+        self.free_range(pfn, count);
+    }
+
+    /// Free pages, not counting ones in per-CPU caches
+    pub fn free_pages(&self) -> usize {
+        self.free_pages
+    }
+
+    pub fn total_pages(&self) -> usize {
+        self.total_pages
     }
 
     pub fn free(&mut self, mut list: PageList) {
         while let Some(next) = list.pop_front() {
-            self.list.push_front_with_cb(next, |page| {
-                assert!(!page.is_free());
-                page.mark_free();
-            });
+            assert!(!halpage(next.pfn()).is_free());
+            self.free_order(next.pfn(), 0);
+        }
+    }
+}
+
+/// Allocates zeroed page. Uses per-CPU cache, so common case does not take the global lock
+pub fn alloc_page() -> Option<PhysAddr> {
+    if !percpu_ready() {
+        return page_allocator().alloc_page().map(|x| x.pfn().into());
+    }
+
+    let pfn = {
+        let mut cache = PAGE_CACHE.per_cpu_var_get().lock();
+
+        if cache.pages.pages() == 0 {
+            let mut allocator = page_allocator();
+
+            for _ in 0..PCP_BATCH {
+                match allocator.alloc_order(0) {
+                    Some(pfn) => cache.pages.push_back(Page(pfn)),
+                    None => break,
+                }
+            }
         }
+
+        cache.pages.pop_front()?.pfn()
+    };
+
+    unsafe {
+        memset_pages(pfn, 1);
     }
+
+    Some(pfn.into())
+}
+
+/// Free pages sitting in per-CPU caches
+pub fn cached_pages() -> usize {
+    (0..num_cpus())
+        .map(|cpu| unsafe { PAGE_CACHE.cpu(cpu) }.lock().pages.pages())
+        .sum()
+}
+
+/// Frees page allocated with [`alloc_page`]
+pub fn free_page(pa: PhysAddr) {
+    let pfn: Pfn = pa.into();
+
+    assert!(!halpage(pfn).is_free());
+
+    if !percpu_ready() {
+        page_allocator().free_order(pfn, 0);
+        return;
+    }
+
+    let mut cache = PAGE_CACHE.per_cpu_var_get().lock();
+
+    if cache.pages.pages() >= PCP_HIGH {
+        let mut allocator = page_allocator();
+
+        for _ in 0..PCP_HIGH - PCP_BATCH {
+            let page = cache.pages.pop_front().unwrap();
+
+            allocator.free_order(page.pfn(), 0);
+        }
+    }
+
+    // Recently freed page is likely to be cache hot, so it's reused first
+    cache.pages.push_front(Page(pfn));
 }
 
 pub fn init() {
     let mut allocator = PAGE_ALLOC.lock();
-    let mut list = PageList::default();
+    let info = phys_info();
 
-    for reg in phys_info().regions() {
+    // Page array covers holes between regions as well. They must never look free
+    for i in usize::from(info.lowest_pfn())..usize::from(info.highest_pfn()) {
+        unsafe {
+            pfn_to_halpage(Pfn::from(i)).write(HalPage {
+                refcount: Default::default(),
+                list: Default::default(),
+                state: PageState::Occupied,
+                order: 0,
+            });
+        }
+    }
+
+    for reg in info.regions() {
         info!(
             "Page allocator region {:x} size {:x}\n",
             reg.start, reg.size
         );
 
-        for i in reg.pfn_range() {
-            list.push_back_with_cb(Page(i), |page| {
-                page.mark_free();
-            });
-        }
+        allocator.free_range(reg.start().pfn(), reg.size() / hal::arch::PAGE_SIZE);
+        allocator.total_pages += reg.size() / hal::arch::PAGE_SIZE;
     }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+    use hal::arch::PAGE_SIZE;
+    use test_macros::*;
 
-    allocator.list = list;
+    #[kernel_test]
+    fn page_alloc_contig_aligned() {
+        let a = page_allocator().alloc_contigious_aligned(3, 4).unwrap();
+        let b = page_allocator().alloc_contigious_aligned(3, 4).unwrap();
+
+        test_assert!(a.bits() % (4 * PAGE_SIZE) == 0);
+        test_assert!(b.bits() % (4 * PAGE_SIZE) == 0);
+        test_assert!(a.bits().abs_diff(b.bits()) >= 3 * PAGE_SIZE);
+
+        page_allocator().free_contig(a, 3);
+        page_allocator().free_contig(b, 3);
+    }
+
+    #[kernel_test]
+    fn page_alloc_cache() {
+        let a = alloc_page().unwrap();
+        let b = alloc_page().unwrap();
+
+        test_assert!(a != b);
+
+        // Freed page is reused by the same CPU
+        free_page(b);
+        test_assert_eq!(alloc_page(), Some(b));
+
+        free_page(a);
+        free_page(b);
+    }
 }
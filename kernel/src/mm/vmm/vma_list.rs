@@ -1,6 +1,5 @@
-use crate::mm::pmm::page_list::PageList;
-
 use super::vmo::VmObject;
+use crate::random;
 use alloc::boxed::Box;
 use alloc::sync::Arc;
 use core::cmp::Ordering;
@@ -8,10 +7,18 @@ use core::ops::Bound;
 use core::pin::Pin;
 use core::ptr::NonNull;
 use hal::address::*;
+use hal::arch::PAGE_SIZE;
 use rtl::error::ErrorType;
 use rtl::vmm::MappingType;
 use wavltree::{Linked, Links, Side, WAVLTree};
 
+/// Randomized mappings are placed above this offset from the start of address space, so images
+/// linked at fixed low addresses do not collide with them
+const ASLR_MIN: usize = 1 << 32;
+
+/// Random placements tried before falling back to the first fit
+const ASLR_ATTEMPTS: usize = 16;
+
 #[derive(Default, Debug, Clone)]
 struct NodeState {
     min_byte: VirtAddr,
@@ -20,10 +27,15 @@ struct NodeState {
 }
 
 pub enum VmaState {
-    Anonymous { list: PageList },
-    Vmo { object: Arc<VmObject> },
-    Mmio { range: MemRange<PhysAddr> },
+    Vmo {
+        object: Arc<VmObject>,
+    },
+    Mmio {
+        range: MemRange<PhysAddr>,
+    },
     Reserved,
+    /// Inaccessible range below a stack
+    Guard,
 }
 
 pub enum VmaStateInner {
@@ -35,6 +47,8 @@ struct Vma {
     links: Links<Self>,
     range: MemRange<VirtAddr>,
     prot: MappingType,
+    // Protection VMA was created with. It can't be raised above it later
+    max_prot: MappingType,
     state: VmaStateInner,
     stats: NodeState,
 }
@@ -112,8 +126,14 @@ unsafe impl Linked for Vma {
 }
 
 impl VmaReservation<'_> {
-    pub fn commit(self, mt: MappingType, state: VmaState) -> Result<VirtAddr, ErrorType> {
-        self.list.new_vma_raw(self.range, mt, state)
+    /// Creates VMA with protection `mt`, which can later be changed up to `max_prot`
+    pub fn commit(
+        self,
+        mt: MappingType,
+        max_prot: MappingType,
+        state: VmaState,
+    ) -> Result<VirtAddr, ErrorType> {
+        self.list.new_vma_raw(self.range, mt, max_prot, state)
     }
 
     pub fn range(&self) -> MemRange<VirtAddr> {
@@ -122,12 +142,18 @@ impl VmaReservation<'_> {
 }
 
 impl Vma {
-    pub fn new(range: MemRange<VirtAddr>, prot: MappingType, state: VmaState) -> Self {
+    pub fn new(
+        range: MemRange<VirtAddr>,
+        prot: MappingType,
+        max_prot: MappingType,
+        state: VmaState,
+    ) -> Self {
         Self {
             links: Links::new(),
             range,
             stats: NodeState::default(),
             prot,
+            max_prot,
             state: VmaStateInner::Valid(state),
         }
     }
@@ -194,6 +220,7 @@ pub struct VmaList {
     tree: WAVLTree<Vma>,
     start: usize,
     size: usize,
+    aslr: bool,
 }
 
 impl VmaList {
@@ -204,6 +231,7 @@ impl VmaList {
             tree: WAVLTree::new(),
             start: range.start.into(),
             size: range.size(),
+            aslr: random::aslr_enabled(),
         }
     }
 
@@ -214,6 +242,7 @@ impl VmaList {
             tree: WAVLTree::new(),
             start: range.start.into(),
             size: range.size,
+            aslr: false,
         }
     }
 
@@ -239,11 +268,47 @@ impl VmaList {
         }
     }
 
+    /// Checks that `range` does not intersect any VMA
+    fn is_free(&self, range: MemRange<VirtAddr>) -> bool {
+        let last = VirtAddr::from_bits(range.start().bits() + range.size() - 1);
+
+        match self.tree.upper_bound(Bound::Included(&last)).get() {
+            Some(vma) => vma.last_occupied_byte() < range.start(),
+            None => true,
+        }
+    }
+
+    /// Picks random free range above [`ASLR_MIN`]. Gives up after few attempts, so crowded address
+    /// space falls back to the first fit
+    fn find_random_space(&self, size: usize) -> Option<VirtAddr> {
+        let low = self.start + ASLR_MIN;
+        let high = (self.start + self.size).checked_sub(size)?;
+
+        if high < low {
+            return None;
+        }
+
+        (0..ASLR_ATTEMPTS)
+            .map(|_| {
+                VirtAddr::from_bits(
+                    low + random::next_below((high - low) / PAGE_SIZE + 1) * PAGE_SIZE,
+                )
+            })
+            .find(|&start| self.is_free(MemRange::new(start, size)))
+    }
+
     fn find_free_space(&self, size: usize, base: Option<usize>) -> Result<VirtAddr, ErrorType> {
         if size > self.size {
             return Err(ErrorType::InvalidArgument);
         }
 
+        if base.is_none()
+            && self.aslr
+            && let Some(start) = self.find_random_space(size)
+        {
+            return Ok(start);
+        }
+
         if self.tree.is_empty() {
             // If tree is empty just take the address from the beginning.
             let start = base.unwrap_or(self.start);
@@ -380,11 +445,13 @@ impl VmaList {
         }
     }
 
+    /// Changes permissions of VMA covering exactly `range`. Memory type of the VMA is kept.
+    /// Returns new protection of the VMA
     pub fn vma_protect(
         &mut self,
         range: MemRange<VirtAddr>,
         mt: MappingType,
-    ) -> Result<(), ErrorType> {
+    ) -> Result<MappingType, ErrorType> {
         let mut cursor = self.tree.lower_bound_mut(Bound::Included(&range.start()));
 
         if let Some(vma) = cursor.get_mut()
@@ -392,13 +459,31 @@ impl VmaList {
         {
             let vma = unsafe { Pin::into_inner_unchecked(vma) };
 
-            vma.prot = mt;
-            Ok(())
+            if mt.is_greater(vma.max_prot) {
+                return Err(ErrorType::AccessDenied);
+            }
+
+            vma.prot = mt.with_cache(vma.prot.cache());
+            Ok(vma.prot)
         } else {
             Err(ErrorType::NotFound)
         }
     }
 
+    /// Looks up VMA containing `va`
+    pub fn find(&self, va: VirtAddr) -> Option<(MemRange<VirtAddr>, MappingType, &VmaState)> {
+        let vma = self.tree.upper_bound(Bound::Included(&va)).get()?;
+
+        if !vma.range.contains_addr(va) {
+            return None;
+        }
+
+        match &vma.state {
+            VmaStateInner::Valid(state) => Some((vma.range, vma.prot, state)),
+            VmaStateInner::Invalid => None,
+        }
+    }
+
     pub fn reserve_space<'a>(
         &'a mut self,
         size: usize,
@@ -416,11 +501,13 @@ impl VmaList {
         &mut self,
         range: MemRange<VirtAddr>,
         mt: MappingType,
+        max_prot: MappingType,
         state: VmaState,
     ) -> Result<VirtAddr, ErrorType> {
         debug_assert!(range.start().is_page_aligned());
 
-        let vma = Box::try_new(Vma::new(range, mt, state)).map_err(|_| ErrorType::NoMemory)?;
+        let vma =
+            Box::try_new(Vma::new(range, mt, max_prot, state)).map_err(|_| ErrorType::NoMemory)?;
 
         self.tree.insert(vma.into());
         Ok(range.start())
@@ -434,7 +521,53 @@ impl VmaList {
         state: VmaState,
     ) -> Result<VirtAddr, ErrorType> {
         let start = self.find_free_space(size, base)?;
-        self.new_vma_raw(MemRange::new(start, size), mt, state)
+        self.new_vma_raw(MemRange::new(start, size), mt, mt, state)
+    }
+
+    /// Same as [`Self::new_vma`] with no fixed address, but VMA starts at `align` boundary, if
+    /// there is enough space for that
+    pub fn new_vma_aligned(
+        &mut self,
+        size: usize,
+        align: usize,
+        mt: MappingType,
+        state: VmaState,
+    ) -> Result<VirtAddr, ErrorType> {
+        let start = self
+            .find_free_space(size + align - PAGE_SIZE, None)
+            .map(|x| VirtAddr::from_bits(x.bits().next_multiple_of(align)))
+            .or_else(|_| self.find_free_space(size, None))?;
+
+        self.new_vma_raw(MemRange::new(start, size), mt, mt, state)
+    }
+
+    /// Creates VMA with inaccessible guard VMA of `guard` bytes right below it
+    pub fn new_guarded_vma(
+        &mut self,
+        size: usize,
+        guard: usize,
+        mt: MappingType,
+        state: VmaState,
+    ) -> Result<VirtAddr, ErrorType> {
+        let start = self.find_free_space(size + guard, None)?;
+        let guard = MemRange::new(start, guard);
+
+        self.new_vma_raw(guard, MappingType::NONE, MappingType::NONE, VmaState::Guard)?;
+        self.new_vma_raw(
+            MemRange::new(VirtAddr::from_bits(start.bits() + guard.size()), size),
+            mt,
+            mt,
+            state,
+        )
+        .inspect_err(|_| self.free(guard, |_, _| {}).unwrap())
+    }
+
+    /// VMOs mapped by the VMAs
+    pub fn objects(&self) -> impl Iterator<Item = &Arc<VmObject>> {
+        self.tree.iter().filter_map(|vma| match &vma.state {
+            VmaStateInner::Valid(VmaState::Vmo { object }) => Some(object),
+            _ => None,
+        })
     }
 
     pub fn free<F: FnMut(VmaState, &MemRange<VirtAddr>)>(
@@ -464,6 +597,20 @@ impl VmaList {
             Err(ErrorType::NotFound)
         }
     }
+
+    /// Removes all VMAs from the list
+    pub fn free_all<F: FnMut(VmaState, &MemRange<VirtAddr>)>(&mut self, mut cb: F) {
+        while !self.tree.is_empty() {
+            let mut cursor = self.tree.lower_bound_mut(Bound::Unbounded);
+            let mut vma = unsafe { Pin::into_inner_unchecked(cursor.remove().unwrap()) };
+
+            if let VmaStateInner::Valid(state) =
+                core::mem::replace(&mut vma.state, VmaStateInner::Invalid)
+            {
+                cb(state, &vma.range);
+            }
+        }
+    }
 }
 
 impl PartialEq for Vma {
@@ -490,6 +637,7 @@ impl Eq for Vma {}
 mod test {
     use super::*;
     use crate::*;
+    use rtl::vmm::CacheAttr;
     use test_macros::*;
 
     #[kernel_test]
@@ -497,20 +645,68 @@ mod test {
         let mut list = VmaList::new_user();
 
         let new_vma = list
-            .new_vma(0x1000, Some(0x20000), MappingType::None, VmaState::Reserved)
+            .new_vma(0x1000, Some(0x20000), MappingType::NONE, VmaState::Reserved)
             .unwrap();
 
         test_assert_eq!(new_vma, VirtAddr::from_bits(0x20000));
 
         let new_vma = list
-            .new_vma(0x1000, Some(0x30000), MappingType::None, VmaState::Reserved)
+            .new_vma(0x1000, Some(0x30000), MappingType::NONE, VmaState::Reserved)
             .unwrap();
         test_assert_eq!(new_vma, VirtAddr::from_bits(0x30000));
 
-        let new_vma = list.new_vma(0x1000, Some(0x1000), MappingType::None, VmaState::Reserved);
+        let new_vma = list.new_vma(0x1000, Some(0x1000), MappingType::NONE, VmaState::Reserved);
         test_assert!(new_vma.is_err());
 
-        let new_vma = list.new_vma(0x1000, Some(0x0), MappingType::None, VmaState::Reserved);
+        let new_vma = list.new_vma(0x1000, Some(0x0), MappingType::NONE, VmaState::Reserved);
         test_assert!(new_vma.is_err());
     }
+
+    #[kernel_test]
+    fn vma_list_protect() {
+        let mut list = VmaList::new_user();
+        let tp = MappingType::DATA.with_cache(CacheAttr::Uncached);
+        let va = list
+            .new_vma(0x1000, Some(0x20000), tp, VmaState::Reserved)
+            .unwrap();
+        let range = MemRange::new(va, 0x1000);
+
+        // Memory type is kept, permissions can't be raised above initial ones
+        let new = list.vma_protect(range, MappingType::RODATA).unwrap();
+        test_assert!(new == MappingType::RODATA.with_cache(CacheAttr::Uncached));
+        test_assert!(list.vma_protect(range, MappingType::RWX).is_err());
+        test_assert!(list.vma_protect(range, MappingType::DATA).is_ok());
+    }
+
+    #[kernel_test]
+    fn vma_list_guard() {
+        let mut list = VmaList::new_user();
+        let va = list
+            .new_guarded_vma(0x2000, 0x1000, MappingType::DATA, VmaState::Reserved)
+            .unwrap();
+
+        test_assert!(matches!(
+            list.find(VirtAddr::from_bits(va.bits() - 1)),
+            Some((_, _, VmaState::Guard))
+        ));
+        test_assert!(matches!(list.find(va), Some((_, _, VmaState::Reserved))));
+    }
+
+    #[kernel_test]
+    fn vma_list_random() {
+        let mut list = VmaList::new_user();
+
+        list.aslr = true;
+
+        let a = list
+            .new_vma(0x4000, None, MappingType::DATA, VmaState::Reserved)
+            .unwrap();
+        let b = list
+            .new_vma(0x4000, None, MappingType::DATA, VmaState::Reserved)
+            .unwrap();
+
+        test_assert!(a.bits() >= list.start + ASLR_MIN);
+        test_assert!(b.bits() >= list.start + ASLR_MIN);
+        test_assert!(a.bits().abs_diff(b.bits()) >= 0x4000);
+    }
 }
@@ -1,127 +1,684 @@
+use super::vms::Vms;
+use crate::arch::HUGE_PAGE_SIZE;
 use crate::mm::paging::page_table::PageSource;
-use crate::mm::pmm::page_alloc::page_allocator;
-use crate::mm::pmm::page_list::{PageList, PageListIterator};
+use crate::mm::pmm::page_alloc::{alloc_page, free_page, page_allocator};
+use crate::mm::user_buffer::UserPtr;
 use crate::object::KernelObjectBase;
-use alloc::sync::Arc;
-use hal::address::{MemRange, PhysAddr};
+use crate::object::capabilities::{Capability, CapabilityMask};
+use crate::object::job_object::{Job, JobCharge, Resource};
+use crate::object::port_object::Port;
+use crate::sched::current_task;
+use crate::sync::spinlock::SpinlockGuard;
+use crate::sync::{Event, Spinlock};
+use adt::Vec;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use core::future::{Future, poll_fn};
+use core::pin::pin;
+use core::task::Poll;
+use hal::address::{Address, LinearAddr, MemRange, PhysAddr, VirtAddr, VirtualAddress};
 use hal::arch::PAGE_SIZE;
+use rtl::error::ErrorType;
 use rtl::signal::Signal;
-use rtl::vmm::MappingType;
+use rtl::vmm::{MappingType, PagerRequest, VmoChildKind};
+
+/// Physical page committed to one or more VMOs. It is shared between a VMO and its COW children
+/// until one of them writes to it. Page is charged to the job of the VMO, which committed it.
+struct Frame {
+    pa: PhysAddr,
+    _charge: JobCharge,
+}
+
+type Frames = BTreeMap<usize, Arc<Frame>>;
 
 enum VmPageBacking {
-    List(PageList),
-    Contig(MemRange<PhysAddr>),
+    Contig {
+        range: MemRange<PhysAddr>,
+        _charge: JobCharge,
+    },
+    /// Maps page index to the committed frame. Missing pages are committed on first access
+    Paged {
+        pages: usize,
+        frames: Spinlock<Frames>,
+    },
 }
 
 pub enum VmObjectPagesIter<'a> {
-    List(PageListIterator<'a>),
     Contig(MemRange<PhysAddr>),
+    /// Committed pages in order. Stops at the first page, which is not committed
+    Paged {
+        frames: SpinlockGuard<'a, Frames>,
+        next: usize,
+    },
 }
 
 impl PageSource for VmObjectPagesIter<'_> {
     fn next_page(&mut self) -> Option<PhysAddr> {
         match self {
-            Self::List(list) => list.next_page(),
             Self::Contig(range) => range.next_page(),
+            Self::Paged { frames, next } => {
+                let pa = frames.get(next)?.pa;
+
+                *next += 1;
+                Some(pa)
+            }
+        }
+    }
+
+    fn next_block(&mut self, size: usize) -> Option<PhysAddr> {
+        match self {
+            Self::Contig(range) => range.next_block(size),
+            Self::Paged { .. } => None,
         }
     }
 }
 
-struct VmObjectInner {
-    source: VmPageBacking,
-    mt: MappingType,
+/// Place, where VMO is mapped. Used to update mappings, when pages of the VMO change
+struct VmoMapping {
+    vms: Weak<Vms>,
+    tt_base: PhysAddr,
+    base: VirtAddr,
+}
+
+/// User-space server, which supplies contents of the VMO. Requests for non-resident pages are
+/// sent to `port`
+struct Pager {
+    port: Arc<Port>,
+    key: usize,
+    // Requested pages, which were not supplied yet. Event fires once the page arrives
+    pending: Spinlock<BTreeMap<usize, Arc<Event>>>,
+    // Handles, which can't supply pages, and mappings of the object
+    clients: Spinlock<usize>,
+}
+
+impl Pager {
+    /// Asks the pager for page `index`, unless it was already asked for it
+    fn request(&self, index: usize, job: &Arc<Job>) -> Result<(), ErrorType> {
+        let mut pending = self.pending.lock();
+
+        if pending.contains_key(&index) {
+            return Ok(());
+        }
+
+        let event = Arc::try_new(Event::new()).map_err(|_| ErrorType::NoMemory)?;
+        let req = PagerRequest {
+            key: self.key,
+            offset: index * PAGE_SIZE,
+            size: PAGE_SIZE,
+        };
+
+        // SAFETY: PagerRequest is repr(C) and has no padding
+        let data = unsafe {
+            core::slice::from_raw_parts(
+                (&req as *const PagerRequest).cast::<u8>(),
+                size_of::<PagerRequest>(),
+            )
+        };
+
+        self.port.send_kernel(data, job)?;
+        pending.insert(index, event);
+        Ok(())
+    }
 }
 
 pub struct VmObject {
-    inner: VmObjectInner,
+    source: VmPageBacking,
+    mt: MappingType,
+    // Job new pages are charged to
+    job: Arc<Job>,
+    pager: Option<Pager>,
+    mappings: Spinlock<Vec<VmoMapping>>,
     base: KernelObjectBase,
 }
 
-crate::kernel_object!(VmObject, Signal::None.into());
-
-impl VmPageBacking {
-    fn source<'a>(&'a self) -> VmObjectPagesIter<'a> {
-        match self {
-            Self::Contig(range) => VmObjectPagesIter::Contig(*range),
-            Self::List(list) => VmObjectPagesIter::List(list.iter()),
+// Pager VMO raises PeerClosed once the last client is gone, so the pager could stop serving it
+crate::kernel_object!(VmObject, Signal::PeerClosed.into(), {
+    fn handle_opened(&self, rights: &CapabilityMask) {
+        if !rights.is_set(Capability::Write.into()) {
+            self.client_opened();
         }
     }
 
-    fn pages(&self) -> usize {
-        match self {
-            Self::Contig(range) => range.size() / PAGE_SIZE,
-            Self::List(list) => list.pages(),
+    fn handle_closed(&self, rights: &CapabilityMask) {
+        if !rights.is_set(Capability::Write.into()) {
+            self.client_closed();
         }
     }
-}
+});
 
-impl VmObjectInner {
-    pub fn new(size: usize, mt: MappingType) -> Option<Self> {
-        let pages = size.div_ceil(PAGE_SIZE);
-        let list = page_allocator().alloc_pages(pages)?;
-        let source = VmPageBacking::List(list);
+impl Frame {
+    /// Allocates zeroed page charged to `job`
+    fn new(job: &Arc<Job>) -> Result<Arc<Self>, ErrorType> {
+        let charge = job.charge(Resource::Pages, 1)?;
+        let pa = alloc_page().ok_or(ErrorType::NoMemory)?;
 
-        Some(Self { source, mt })
+        Arc::try_new(Self {
+            pa,
+            _charge: charge,
+        })
+        .map_err(|_| ErrorType::NoMemory)
     }
 
-    pub fn new_contig(size: usize, mt: MappingType) -> Option<Self> {
-        let pages = size.div_ceil(PAGE_SIZE);
-        let pa = page_allocator().alloc_contigious(pages)?;
-        let source = VmPageBacking::Contig(MemRange::new(pa, pages * PAGE_SIZE));
+    /// Allocates private copy of the frame
+    fn copy(&self, job: &Arc<Job>) -> Result<Arc<Self>, ErrorType> {
+        let new = Self::new(job)?;
 
-        Some(Self { source, mt })
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                LinearAddr::from(self.pa).to_raw::<u8>(),
+                LinearAddr::from(new.pa).to_raw_mut::<u8>(),
+                PAGE_SIZE,
+            );
+        }
+
+        Ok(new)
+    }
+
+    fn is_shared(self: &Arc<Self>) -> bool {
+        Arc::strong_count(self) != 1
+    }
+}
+
+impl Drop for Frame {
+    fn drop(&mut self) {
+        free_page(self.pa);
+    }
+}
+
+impl VmPageBacking {
+    fn pages(&self) -> usize {
+        match self {
+            Self::Contig { range, .. } => range.size() / PAGE_SIZE,
+            Self::Paged { pages, .. } => *pages,
+        }
     }
 }
 
 impl VmObject {
-    pub fn new(size: usize, tp: MappingType) -> Option<Arc<Self>> {
+    fn from_source(source: VmPageBacking, mt: MappingType) -> Option<Arc<Self>> {
         Arc::try_new(Self {
-            inner: VmObjectInner::new(size, tp)?,
+            source,
+            mt,
+            job: current_task().job().clone(),
+            pager: None,
+            mappings: Spinlock::new(Vec::new()),
             base: KernelObjectBase::new(),
         })
         .ok()
     }
 
-    pub fn new_contig(size: usize, tp: MappingType) -> Option<Arc<Self>> {
+    /// Creates VMO, which commits pages on first access
+    pub fn new(size: usize, tp: MappingType) -> Option<Arc<Self>> {
+        Self::from_source(
+            VmPageBacking::Paged {
+                pages: size.div_ceil(PAGE_SIZE),
+                frames: Spinlock::new(BTreeMap::new()),
+            },
+            tp,
+        )
+    }
+
+    /// Creates VMO, which pages are supplied by user-space pager listening on `port`. Each
+    /// request carries `key`, so one port can serve several objects
+    pub fn new_pager(
+        size: usize,
+        tp: MappingType,
+        port: Arc<Port>,
+        key: usize,
+    ) -> Result<Arc<Self>, ErrorType> {
         Arc::try_new(Self {
-            inner: VmObjectInner::new_contig(size, tp)?,
+            source: VmPageBacking::Paged {
+                pages: size.div_ceil(PAGE_SIZE),
+                frames: Spinlock::new(BTreeMap::new()),
+            },
+            mt: tp,
+            job: current_task().job().clone(),
+            pager: Some(Pager {
+                port,
+                key,
+                pending: Spinlock::new(BTreeMap::new()),
+                clients: Spinlock::new(0),
+            }),
+            mappings: Spinlock::new(Vec::new()),
             base: KernelObjectBase::new(),
         })
-        .ok()
+        .map_err(|_| ErrorType::NoMemory)
+    }
+
+    /// Creates VMO with all pages committed up front
+    pub fn new_committed(size: usize, tp: MappingType) -> Option<Arc<Self>> {
+        let vmo = Self::new(size, tp)?;
+
+        for i in 0..vmo.source.pages() {
+            vmo.commit_page(i).ok()?;
+        }
+
+        Some(vmo)
+    }
+
+    /// Large ranges are aligned, so they could be mapped with huge pages
+    fn alloc_contig(pages: usize) -> Option<PhysAddr> {
+        let mut allocator = page_allocator();
+
+        if pages * PAGE_SIZE >= HUGE_PAGE_SIZE
+            && let Some(pa) = allocator.alloc_contigious_aligned(pages, HUGE_PAGE_SIZE / PAGE_SIZE)
+        {
+            return Some(pa);
+        }
+
+        allocator.alloc_contigious(pages)
+    }
+
+    pub fn new_contig(size: usize, tp: MappingType) -> Option<Arc<Self>> {
+        let pages = size.div_ceil(PAGE_SIZE);
+        let charge = current_task().job().charge(Resource::Pages, pages).ok()?;
+        let pa = Self::alloc_contig(pages)?;
+
+        Self::from_source(
+            VmPageBacking::Contig {
+                range: MemRange::new(pa, pages * PAGE_SIZE),
+                _charge: charge,
+            },
+            tp,
+        )
+        .or_else(|| {
+            page_allocator().free_contig(pa, pages);
+            None
+        })
+    }
+
+    pub fn full_caps() -> CapabilityMask {
+        CapabilityMask::from(
+            Capability::Read
+                | Capability::Write
+                | Capability::Execute
+                | Capability::Map
+                | Capability::Duplicate
+                | Capability::Transfer,
+        )
+    }
+
+    pub fn full_caps_contig() -> CapabilityMask {
+        CapabilityMask::from(Self::full_caps().bits() | Capability::GetPhysInfo)
     }
 
     pub fn size(&self) -> usize {
-        self.inner.source.pages() * PAGE_SIZE
+        self.source.pages() * PAGE_SIZE
     }
 
     pub fn get_phys_info(&self) -> Option<PhysAddr> {
-        match self.inner.source {
-            VmPageBacking::Contig(range) => Some(range.start()),
-            VmPageBacking::List(_) => None,
+        match &self.source {
+            VmPageBacking::Contig { range, .. } => Some(range.start()),
+            VmPageBacking::Paged { .. } => None,
         }
     }
 
-    pub fn source(&self) -> impl PageSource {
-        self.inner.source.source()
+    /// Pages backed by physical memory
+    pub fn committed_pages(&self) -> usize {
+        match &self.source {
+            VmPageBacking::Contig { range, .. } => range.size() / PAGE_SIZE,
+            VmPageBacking::Paged { frames, .. } => frames.lock().len(),
+        }
+    }
+
+    /// Committed pages in order, starting from the first one
+    pub fn source(&self) -> VmObjectPagesIter<'_> {
+        match &self.source {
+            VmPageBacking::Contig { range, .. } => VmObjectPagesIter::Contig(*range),
+            VmPageBacking::Paged { frames, .. } => VmObjectPagesIter::Paged {
+                frames: frames.lock(),
+                next: 0,
+            },
+        }
     }
 
     pub fn mapping_type(&self) -> MappingType {
-        self.inner.mt
+        self.mt
     }
-}
 
-impl Drop for VmObjectInner {
-    fn drop(&mut self) {
-        let mut old = PageList::default();
+    /// Returns page at `index`, committing it if it was never accessed before
+    pub fn commit_page(&self, index: usize) -> Result<PhysAddr, ErrorType> {
+        self.fault_page(index, false, |pa, _| Ok(pa))
+    }
+
+    /// Resolves access to page at `index`. `map` is called with the resulting page and whether
+    /// it can be mapped writable, while pages of the object are locked, so COW children can't
+    /// be created in between. If write access hits a page shared with COW relatives, page is
+    /// copied and other mappings of the old page are removed. Non-resident page of pager VMO is
+    /// requested from the pager and [`ErrorType::WouldBlock`] is returned, so caller could wait
+    /// for it with [`VmObject::wait_page`].
+    pub fn fault_page<T, F: FnOnce(PhysAddr, bool) -> Result<T, ErrorType>>(
+        &self,
+        index: usize,
+        write: bool,
+        map: F,
+    ) -> Result<T, ErrorType> {
+        if index >= self.source.pages() {
+            return Err(ErrorType::InvalidArgument);
+        }
 
-        match &mut self.source {
-            VmPageBacking::List(list) => {
-                core::mem::swap(list, &mut old);
-                page_allocator().free(old);
+        let frames = match &self.source {
+            VmPageBacking::Contig { range, .. } => {
+                return map(
+                    PhysAddr::from_bits(range.start().bits() + index * PAGE_SIZE),
+                    true,
+                );
             }
-            VmPageBacking::Contig(range) => {
-                page_allocator().free_contig(range.start(), range.size() / PAGE_SIZE);
+            VmPageBacking::Paged { frames, .. } => frames,
+        };
+        let mut frames = frames.lock();
+
+        let frame = match frames.get(&index) {
+            Some(frame) if write && frame.is_shared() => {
+                let mappings = self.mappings()?;
+                let new = frame.copy(&self.job)?;
+
+                frames.insert(index, new.clone());
+
+                // Other users of this VMO could map the old page read-only
+                for (vms, base) in mappings {
+                    vms.unmap_object_page(base, self, index);
+                }
+
+                new
+            }
+            Some(frame) => frame.clone(),
+            None => {
+                if let Some(pager) = &self.pager {
+                    pager.request(index, &self.job)?;
+                    return Err(ErrorType::WouldBlock);
+                }
+
+                let new = Frame::new(&self.job)?;
+
+                frames.insert(index, new.clone());
+                new
             }
+        };
+
+        // Reference held by `frame` itself must not count
+        let writable = Arc::strong_count(&frame) == 2;
+
+        map(frame.pa, writable)
+    }
+
+    /// Waits until page `index` requested by [`VmObject::fault_page`] is supplied. Fails with
+    /// [`ErrorType::TaskDead`] if the pager goes away before that
+    pub async fn wait_page(&self, index: usize) -> Result<(), ErrorType> {
+        let Some(pager) = &self.pager else {
+            return Ok(());
+        };
+
+        // Page was supplied already
+        let Some(event) = pager.pending.lock().get(&index).cloned() else {
+            return Ok(());
+        };
+
+        let mut supplied = pin!(event.wait());
+        let mut closed = pin!(pager.port.wait_signal(Signal::PeerClosed.into()));
+
+        poll_fn(|cx| match supplied.as_mut().poll(cx) {
+            Poll::Ready(res) => Poll::Ready(res),
+            Poll::Pending => closed
+                .as_mut()
+                .poll(cx)
+                .map(|res| res.and(Err(ErrorType::TaskDead))),
+        })
+        .await
+    }
+
+    /// Commits pages of pager VMO starting from `offset` with contents of user buffer `src` and
+    /// wakes up threads waiting for them. Pages, which are resident already, are left as is
+    pub fn supply_pages(&self, offset: usize, src: *const u8, len: usize) -> Result<(), ErrorType> {
+        let (Some(pager), VmPageBacking::Paged { frames, .. }) = (&self.pager, &self.source) else {
+            return Err(ErrorType::InvalidArgument);
+        };
+
+        if offset % PAGE_SIZE != 0 || len % PAGE_SIZE != 0 {
+            return Err(ErrorType::InvalidArgument);
+        }
+
+        let first = offset / PAGE_SIZE;
+        let pages = len / PAGE_SIZE;
+
+        if first
+            .checked_add(pages)
+            .is_none_or(|end| end > self.source.pages())
+        {
+            return Err(ErrorType::InvalidArgument);
+        }
+
+        for i in 0..pages {
+            let frame = Frame::new(&self.job)?;
+
+            // Copy may fault, so no locks are held here
+            // SAFETY: frame is not visible to anyone yet
+            let dst = unsafe {
+                core::slice::from_raw_parts_mut(LinearAddr::from(frame.pa).to_raw_mut(), PAGE_SIZE)
+            };
+
+            UserPtr::new_array(src.wrapping_add(i * PAGE_SIZE), PAGE_SIZE)
+                .read_to(dst)
+                .ok_or(ErrorType::Fault)?;
+
+            frames.lock().entry(first + i).or_insert(frame);
+
+            if let Some(event) = pager.pending.lock().remove(&(first + i)) {
+                event.broadcast();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Creates child VMO covering `size` bytes starting from `offset`
+    pub fn create_child(
+        &self,
+        kind: VmoChildKind,
+        offset: usize,
+        size: usize,
+    ) -> Result<Arc<Self>, ErrorType> {
+        let VmoChildKind::Cow = kind;
+
+        if offset % PAGE_SIZE != 0 || size % PAGE_SIZE != 0 || size == 0 {
+            return Err(ErrorType::InvalidArgument);
+        }
+
+        let first = offset / PAGE_SIZE;
+        let pages = size / PAGE_SIZE;
+
+        if first
+            .checked_add(pages)
+            .is_none_or(|end| end > self.source.pages())
+        {
+            return Err(ErrorType::InvalidArgument);
+        }
+
+        // Physical memory of contiguous VMOs is owned by device drivers. Children of pager VMOs
+        // would miss pages, which are not resident yet
+        let VmPageBacking::Paged { frames, .. } = &self.source else {
+            return Err(ErrorType::InvalidArgument);
+        };
+
+        if self.pager.is_some() {
+            return Err(ErrorType::InvalidArgument);
+        }
+        let frames = frames.lock();
+
+        let mut shared = BTreeMap::new();
+        for (index, frame) in frames.range(first..first + pages) {
+            shared.insert(index - first, frame.clone());
+        }
+
+        let mappings = self.mappings()?;
+        let child = Self::from_source(
+            VmPageBacking::Paged {
+                pages,
+                frames: Spinlock::new(shared),
+            },
+            self.mt,
+        )
+        .ok_or(ErrorType::NoMemory)?;
+
+        // Shared pages must fault on write from now on
+        for (vms, base) in mappings {
+            vms.protect_object(base, self);
+        }
+
+        drop(frames);
+        Ok(child)
+    }
+
+    /// Remembers that object is mapped at `base` of `vms`
+    pub fn add_mapping(&self, vms: &Arc<Vms>, base: VirtAddr) -> Result<(), ErrorType> {
+        self.mappings.lock().try_push(VmoMapping {
+            vms: Arc::downgrade(vms),
+            tt_base: vms.base(),
+            base,
+        })?;
+
+        self.client_opened();
+        Ok(())
+    }
+
+    pub fn remove_mapping(&self, tt_base: PhysAddr, base: VirtAddr) {
+        let removed = {
+            let mut mappings = self.mappings.lock();
+            let len = mappings.len();
+
+            mappings.retain(|x| x.tt_base != tt_base || x.base != base);
+            len - mappings.len()
+        };
+
+        for _ in 0..removed {
+            self.client_closed();
+        }
+    }
+
+    fn client_opened(&self) {
+        if let Some(pager) = &self.pager {
+            let mut clients = pager.clients.lock();
+
+            if *clients == 0 {
+                self.signal_clear(Signal::PeerClosed.into());
+            }
+
+            *clients += 1;
         }
     }
+
+    fn client_closed(&self) {
+        if let Some(pager) = &self.pager {
+            let mut clients = pager.clients.lock();
+
+            *clients -= 1;
+
+            if *clients == 0 {
+                self.signal_fire(Signal::PeerClosed.into());
+            }
+        }
+    }
+
+    /// Address spaces object is mapped into. Mappings lock is taken under address space lock,
+    /// so it must not be held while address spaces are updated
+    fn mappings(&self) -> Result<Vec<(Arc<Vms>, VirtAddr)>, ErrorType> {
+        let mut res = Vec::new();
+
+        for m in self.mappings.lock().iter() {
+            if let Some(vms) = m.vms.upgrade() {
+                res.try_push((vms, m.base))?;
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+impl Drop for VmObject {
+    fn drop(&mut self) {
+        if let VmPageBacking::Contig { range, .. } = &self.source {
+            page_allocator().free_contig(range.start(), range.size() / PAGE_SIZE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::object::handle::Handle;
+    use crate::*;
+    use test_macros::*;
+
+    #[kernel_test]
+    fn vmo_lazy_commit() {
+        let job = current_task().job().clone();
+        let usage = job.usage(Resource::Pages);
+        let vmo = VmObject::new(4 * PAGE_SIZE, MappingType::DATA).unwrap();
+
+        // Nothing is committed until first access
+        test_assert_eq!(job.usage(Resource::Pages), usage);
+
+        let pa = vmo.commit_page(1).unwrap();
+        test_assert_eq!(vmo.commit_page(1).unwrap().bits(), pa.bits());
+        test_assert_eq!(job.usage(Resource::Pages), usage + 1);
+        test_assert!(vmo.commit_page(4).is_err());
+
+        drop(vmo);
+        test_assert_eq!(job.usage(Resource::Pages), usage);
+    }
+
+    #[kernel_test]
+    fn vmo_cow_child() {
+        let vmo = VmObject::new(2 * PAGE_SIZE, MappingType::DATA).unwrap();
+        let pa = vmo.commit_page(0).unwrap();
+        let child = vmo
+            .create_child(VmoChildKind::Cow, 0, 2 * PAGE_SIZE)
+            .unwrap();
+
+        // Reads share the page, write gets a private copy
+        let (child_pa, writable) = child.fault_page(0, false, |pa, w| Ok((pa, w))).unwrap();
+        test_assert_eq!(child_pa.bits(), pa.bits());
+        test_assert!(!writable);
+
+        let (child_pa, writable) = child.fault_page(0, true, |pa, w| Ok((pa, w))).unwrap();
+        test_assert!(child_pa.bits() != pa.bits());
+        test_assert!(writable);
+
+        // Parent is the only owner of the old page now
+        let (parent_pa, writable) = vmo.fault_page(0, true, |pa, w| Ok((pa, w))).unwrap();
+        test_assert_eq!(parent_pa.bits(), pa.bits());
+        test_assert!(writable);
+    }
+
+    #[kernel_test]
+    fn vmo_pager_request() {
+        let port = Port::new(current_task()).unwrap();
+        let vmo = VmObject::new_pager(2 * PAGE_SIZE, MappingType::RODATA, port, 0).unwrap();
+
+        // Nothing is committed locally, page has to come from the pager
+        test_assert!(matches!(vmo.commit_page(1), Err(ErrorType::WouldBlock)));
+        test_assert!(vmo.create_child(VmoChildKind::Cow, 0, PAGE_SIZE).is_err());
+    }
+
+    #[kernel_test]
+    fn vmo_pager_last_client() {
+        let port = Port::new(current_task()).unwrap();
+        let vmo = VmObject::new_pager(PAGE_SIZE, MappingType::RODATA, port, 0).unwrap();
+        let pager = Handle::new(vmo.clone(), VmObject::full_caps());
+        let client = Handle::new(vmo.clone(), CapabilityMask::from(Capability::Read));
+        let closed = || vmo.signals().contains(Signal::PeerClosed.into());
+
+        test_assert!(!closed());
+
+        // Handle of the pager itself is not a client
+        drop(client);
+        test_assert!(closed());
+
+        let client = Handle::new(vmo.clone(), CapabilityMask::from(Capability::Read));
+        test_assert!(!closed());
+
+        drop(client);
+        drop(pager);
+        test_assert!(closed());
+    }
 }
@@ -1,17 +1,20 @@
 use super::vma_list::{VmaList, VmaState};
 use super::vmo::VmObject;
+use crate::arch::HUGE_PAGE_SIZE;
+use crate::arch::mm::asid::Asid;
 use crate::arch::mm::page_table::switch_context;
 use crate::mm::paging::kernel_page_table::kernel_page_table;
-use crate::mm::{paging::page_table::PageTable, pmm::page_alloc::page_allocator};
+use crate::mm::paging::page_table::PageTable;
 use crate::object::KernelObjectBase;
 use crate::object::capabilities::{Capability, CapabilityMask};
-use crate::sync::Mutex;
+use crate::sync::Spinlock;
+use adt::Vec;
 use alloc::sync::Arc;
 use hal::address::{Address, MemRange, PhysAddr, VirtAddr, VirtualAddress};
 use hal::arch::*;
 use rtl::error::ErrorType;
 use rtl::signal::Signal;
-use rtl::vmm::MappingType;
+use rtl::vmm::{CacheAttr, MappingType};
 
 pub struct VmsInner {
     ttbr0: Option<PageTable>,
@@ -39,19 +42,30 @@ impl VmsInner {
         vmo: Arc<VmObject>,
         tp: MappingType,
     ) -> Result<VirtAddr, ErrorType> {
-        let va = self.vmas.new_vma(
-            vmo.size(),
-            v.map(|x| x.start()).map(|x| x.bits()),
-            tp,
-            VmaState::Vmo {
-                object: vmo.clone(),
-            },
-        )?;
+        let state = VmaState::Vmo {
+            object: vmo.clone(),
+        };
+        let va = match vmo.get_phys_info() {
+            Some(pa) => self.new_contig_vma(v, pa, vmo.size(), tp, state)?,
+            None => self.vmas.new_vma(
+                vmo.size(),
+                v.map(|x| x.start()).map(|x| x.bits()),
+                tp,
+                state,
+            )?,
+        };
 
-        self.ttbr0
-            .as_mut()
-            .unwrap()
-            .map(vmo.source(), MemRange::new(va, vmo.size()), tp)?;
+        // Other VMOs are mapped page by page on fault
+        if let Some(pa) = vmo.get_phys_info() {
+            self.ttbr0
+                .as_mut()
+                .unwrap_or(&mut kernel_page_table())
+                .map(
+                    MemRange::new(pa, vmo.size()),
+                    MemRange::new(va, vmo.size()),
+                    tp,
+                )?;
+        }
 
         Ok(va)
     }
@@ -67,12 +81,7 @@ impl VmsInner {
 
         let size = p.size();
 
-        let va = self.vmas.new_vma(
-            size,
-            v.map(|x| x.start()).map(|x| x.bits()),
-            tp,
-            VmaState::Mmio { range: p },
-        )?;
+        let va = self.new_contig_vma(v, p.start(), size, tp, VmaState::Mmio { range: p })?;
 
         self.ttbr0
             .as_mut()
@@ -82,6 +91,26 @@ impl VmsInner {
         Ok(va)
     }
 
+    /// Places contiguous memory at the same huge page offset as its physical address, so it could
+    /// be mapped with blocks
+    fn new_contig_vma(
+        &mut self,
+        v: Option<MemRange<VirtAddr>>,
+        pa: PhysAddr,
+        size: usize,
+        tp: MappingType,
+        state: VmaState,
+    ) -> Result<VirtAddr, ErrorType> {
+        match v {
+            None if size >= HUGE_PAGE_SIZE && pa.bits() % HUGE_PAGE_SIZE == 0 => {
+                self.vmas.new_vma_aligned(size, HUGE_PAGE_SIZE, tp, state)
+            }
+            v => self
+                .vmas
+                .new_vma(size, v.map(|x| x.start().bits()), tp, state),
+        }
+    }
+
     pub fn vm_protect(
         &mut self,
         range: MemRange<VirtAddr>,
@@ -95,7 +124,17 @@ impl VmsInner {
             return Err(ErrorType::InvalidArgument);
         }
 
-        self.vmas.vma_protect(range, tp)?;
+        let tp = self.vmas.vma_protect(range, tp)?;
+
+        // User pages of VMOs could be shared with COW relatives, so they are not upgraded in
+        // place. They will be mapped again with new protection on next access
+        if let Some(ttbr0) = self.ttbr0.as_mut()
+            && let Some((_, _, VmaState::Vmo { .. })) = self.vmas.find(range.start())
+        {
+            ttbr0.unmap(range)?;
+            return Ok(());
+        }
+
         self.ttbr0
             .as_mut()
             .unwrap_or(&mut kernel_page_table())
@@ -105,34 +144,88 @@ impl VmsInner {
         Ok(())
     }
 
-    // ToDo: on-demand allocation of physical memory
+    /// Allocates anonymous memory. Pages of user address space are committed on first access
     pub fn vm_allocate(
         &mut self,
         size: usize,
         tp: MappingType,
         hint: Option<VirtAddr>,
     ) -> Result<VirtAddr, ErrorType> {
-        if size.next_multiple_of(PAGE_SIZE) != size {
+        // Anonymous memory is accessed by the kernel through cacheable linear mapping as well
+        if size.next_multiple_of(PAGE_SIZE) != size || tp.cache() != CacheAttr::WriteBack {
             return Err(ErrorType::InvalidArgument);
         }
 
+        // Kernel cannot take faults on its own memory, so it's committed up front
+        let vmo = if self.ttbr0.is_some() {
+            VmObject::new(size, tp)
+        } else {
+            VmObject::new_committed(size, tp)
+        }
+        .ok_or(ErrorType::NoMemory)?;
+
         let reserve = self
             .vmas
             .reserve_space(size, hint.map(|x| x.bits()))
             .ok_or(ErrorType::InvalidArgument)?;
 
-        let list = page_allocator()
-            .alloc_pages(size / PAGE_SIZE)
-            .ok_or(ErrorType::NoMemory)?;
+        if self.ttbr0.is_none() {
+            kernel_page_table()
+                .map(vmo.source(), reserve.range(), tp)
+                .map_err(|_| ErrorType::NoMemory)?;
+        }
 
-        // TODO: clean up in case of an error
-        self.ttbr0
-            .as_mut()
-            .unwrap_or(&mut kernel_page_table())
-            .map(list.iter(), reserve.range(), tp)
-            .map_err(|_| ErrorType::NoMemory)?;
+        // Anonymous memory is private to the address space, so its protection may be raised
+        reserve.commit(
+            tp,
+            MappingType::RWX.with_cache(tp.cache()),
+            VmaState::Vmo { object: vmo },
+        )
+    }
+
+    /// Allocates user stack with `guard` bytes of inaccessible space below it, so overflow faults
+    /// instead of corrupting neighbouring mapping
+    pub fn vm_allocate_stack(&mut self, size: usize, guard: usize) -> Result<VirtAddr, ErrorType> {
+        if self.ttbr0.is_none() {
+            return Err(ErrorType::InvalidArgument);
+        }
+
+        let vmo = VmObject::new(size, MappingType::DATA).ok_or(ErrorType::NoMemory)?;
+
+        self.vmas.new_guarded_vma(
+            size,
+            guard,
+            MappingType::DATA,
+            VmaState::Vmo { object: vmo },
+        )
+    }
+
+    /// Returns range and protection of VMA at `base`, if it still maps `object`
+    fn object_vma(
+        &self,
+        base: VirtAddr,
+        object: &VmObject,
+    ) -> Option<(MemRange<VirtAddr>, MappingType)> {
+        match self.vmas.find(base)? {
+            (range, prot, VmaState::Vmo { object: o })
+                if range.start() == base && core::ptr::eq(Arc::as_ptr(o), object) =>
+            {
+                Some((range, prot))
+            }
+            _ => None,
+        }
+    }
+
+    fn map_page(&mut self, va: VirtAddr, pa: PhysAddr, tp: MappingType) -> Result<(), ErrorType> {
+        let ttbr0 = self.ttbr0.as_mut().ok_or(ErrorType::Fault)?;
+        let page = MemRange::new(va, PAGE_SIZE);
 
-        reserve.commit(tp, VmaState::Anonymous { list })
+        if ttbr0.translate(va).is_some() {
+            ttbr0.unmap(page)?;
+        }
+
+        ttbr0.map(MemRange::new(pa, PAGE_SIZE), page, tp)?;
+        Ok(())
     }
 
     pub fn vm_free(&mut self, range: MemRange<VirtAddr>) -> Result<(), ErrorType> {
@@ -141,32 +234,47 @@ impl VmsInner {
 
         self.vmas
             .free(range, |state, range| {
-                self.ttbr0
-                    .as_mut()
-                    .unwrap_or(&mut kernel_page_table())
-                    .unmap(*range)
-                    .unwrap();
-
-                if let VmaState::Anonymous { list } = state {
-                    page_allocator().free(list);
-                }
+                Self::release_vma(&mut self.ttbr0, state, range)
             })
             .unwrap();
         Ok(())
     }
 
+    /// Unmaps all VMAs
+    pub fn free_all(&mut self) {
+        self.vmas
+            .free_all(|state, range| Self::release_vma(&mut self.ttbr0, state, range));
+    }
+
+    fn release_vma(ttbr0: &mut Option<PageTable>, state: VmaState, range: &MemRange<VirtAddr>) {
+        let mut kernel = kernel_page_table();
+        let table = ttbr0.as_mut().unwrap_or(&mut kernel);
+
+        table.unmap(*range).unwrap();
+
+        // Backing memory is released with the last reference to VMO
+        if let VmaState::Vmo { object } = state {
+            object.remove_mapping(table.base(), range.start());
+        }
+    }
+
     pub fn ttbr0(&self) -> Option<PhysAddr> {
         self.ttbr0.as_ref().map(|ttbr0| ttbr0.base())
     }
 
+    pub fn asid(&self) -> Option<Arc<Asid>> {
+        self.ttbr0.as_ref()?.asid().cloned()
+    }
+
     pub fn translate(&self, va: VirtAddr) -> Option<PhysAddr> {
         self.ttbr0.as_ref()?.translate(va)
     }
 }
 
 pub struct Vms {
-    inner: Mutex<VmsInner>,
+    inner: Spinlock<VmsInner>,
     tt_base: PhysAddr,
+    asid: Option<Arc<Asid>>,
     base: KernelObjectBase,
 }
 
@@ -177,7 +285,8 @@ impl Vms {
         let vms = VmsInner::new_user()?;
         let new = Self {
             tt_base: vms.ttbr0().unwrap(),
-            inner: Mutex::new(vms),
+            asid: vms.asid(),
+            inner: Spinlock::new(vms),
             base: KernelObjectBase::new(),
         };
 
@@ -188,7 +297,8 @@ impl Vms {
         let vms = VmsInner::new_kernel();
         let new = Self {
             tt_base: kernel_page_table().base(),
-            inner: Mutex::new(vms),
+            asid: None,
+            inner: Spinlock::new(vms),
             base: KernelObjectBase::new(),
         };
 
@@ -196,18 +306,24 @@ impl Vms {
     }
 
     pub fn full_caps() -> CapabilityMask {
-        CapabilityMask::from(Capability::MapPhys)
+        CapabilityMask::from(Capability::Map | Capability::Duplicate | Capability::Transfer)
     }
 
     pub async fn vm_map_vmo(
-        &self,
+        self: &Arc<Self>,
         v: Option<MemRange<VirtAddr>>,
         obj: Arc<VmObject>,
         tp: MappingType,
     ) -> Result<VirtAddr, ErrorType> {
-        let mut inner = self.inner.lock().await?;
+        let mut inner = self.inner.lock();
+        let va = inner.vm_map_vmo(v, obj.clone(), tp)?;
 
-        inner.vm_map_vmo(v, obj, tp)
+        if let Err(err) = obj.add_mapping(self, va) {
+            inner.vm_free(MemRange::new(va, obj.size()))?;
+            return Err(err);
+        }
+
+        Ok(va)
     }
 
     pub async fn vm_map(
@@ -216,7 +332,7 @@ impl Vms {
         p: MemRange<PhysAddr>,
         tp: MappingType,
     ) -> Result<VirtAddr, ErrorType> {
-        let mut inner = self.inner.lock().await?;
+        let mut inner = self.inner.lock();
 
         debug_assert!(p.start().is_page_aligned());
         debug_assert_eq!(p.size().next_multiple_of(PAGE_SIZE), p.size());
@@ -230,47 +346,175 @@ impl Vms {
         tp: MappingType,
         hint: Option<VirtAddr>,
     ) -> Result<VirtAddr, ErrorType> {
-        let mut inner = self.inner.lock().await?;
+        let mut inner = self.inner.lock();
         let res = inner.vm_allocate(size, tp, hint)?;
 
         debug_assert!(res.is_page_aligned());
         Ok(res)
     }
 
+    pub async fn vm_allocate_stack(
+        &self,
+        size: usize,
+        guard: usize,
+    ) -> Result<VirtAddr, ErrorType> {
+        self.inner.lock().vm_allocate_stack(size, guard)
+    }
+
     pub async fn vm_protect(
         &self,
         range: MemRange<VirtAddr>,
         tp: MappingType,
     ) -> Result<(), ErrorType> {
-        let mut inner = self.inner.lock().await?;
+        let mut inner = self.inner.lock();
 
         inner.vm_protect(range, tp)
     }
 
     pub async fn vm_free(&self, base: VirtAddr, size: usize) -> Result<(), ErrorType> {
-        let mut inner = self.inner.lock().await?;
+        let mut inner = self.inner.lock();
 
         inner
             .vm_free(MemRange::new(base, size))
             .map_err(|_| ErrorType::InvalidArgument)
     }
 
+    /// Releases all mappings of the address space
+    pub async fn destroy(&self) -> Result<(), ErrorType> {
+        self.inner.lock().free_all();
+        Ok(())
+    }
+
     pub fn base(&self) -> PhysAddr {
         self.tt_base
     }
 
     pub async fn map_phys(&self, pa: PhysAddr, size: usize) -> Result<*mut u8, ErrorType> {
-        let mut inner = self.inner.lock().await?;
+        let mut inner = self.inner.lock();
 
-        let va = inner.vm_map(None, MemRange::new(pa, size), MappingType::Device)?;
+        let va = inner.vm_map(None, MemRange::new(pa, size), MappingType::DEVICE)?;
         Ok(va.to_raw_mut::<u8>())
     }
 
+    /// Pages committed to VMOs mapped into the address space. VMO shared with other address
+    /// spaces is counted by each of them
+    pub fn committed_pages(&self) -> Result<usize, ErrorType> {
+        let mut objects = Vec::new();
+
+        // VMO locks can't be taken under the address space one
+        for object in self.inner.lock().vmas.objects() {
+            objects.try_push(object.clone())?;
+        }
+
+        Ok(objects.iter().map(|x| x.committed_pages()).sum())
+    }
+
+    /// Checks if `va` belongs to a stack guard
+    pub fn is_guard(&self, va: VirtAddr) -> bool {
+        matches!(
+            self.inner.lock().vmas.find(va),
+            Some((_, _, VmaState::Guard))
+        )
+    }
+
     pub fn switch_to(&self) {
-        switch_context(self.base());
+        switch_context(self.base(), self.asid.as_deref());
     }
 
     pub async fn translate(&self, va: VirtAddr) -> Option<PhysAddr> {
-        self.inner.lock().await.ok().and_then(|x| x.translate(va))
+        self.inner.lock().translate(va)
+    }
+
+    /// Resolves page fault at `va` caused by `access` against the VMA covering it. Commits the
+    /// backing page, if it was never touched before. Does not sleep, so can be called from
+    /// exception context
+    pub fn handle_fault(&self, va: VirtAddr, access: MappingType) -> Result<(), ErrorType> {
+        let page = VirtAddr::from_bits(va.bits() & !PAGE_MASK);
+        let (base, object) = {
+            let inner = self.inner.lock();
+
+            if inner.ttbr0.is_none() {
+                return Err(ErrorType::Fault);
+            }
+
+            let (range, prot, state) = inner.vmas.find(va).ok_or(ErrorType::Fault)?;
+
+            if access.is_greater(prot) {
+                return Err(ErrorType::AccessDenied);
+            }
+
+            let VmaState::Vmo { object } = state else {
+                return Err(ErrorType::Fault);
+            };
+
+            (range.start(), object.clone())
+        };
+        let index = (page.bits() - base.bits()) / PAGE_SIZE;
+        let write = access.contains(MappingType::WRITE);
+
+        // Object locks its pages before address space, so VMA is looked up again
+        object.fault_page(index, write, |pa, writable| {
+            let mut inner = self.inner.lock();
+
+            // VMA was unmapped meanwhile. Access will fault again, if it's repeated
+            let Some((_, prot)) = inner.object_vma(base, &object) else {
+                return Ok(());
+            };
+
+            inner.map_page(page, pa, if writable { prot } else { prot.read_only() })
+        })
+    }
+
+    /// Same as [`Vms::handle_fault`], but waits for pages of pager VMOs to become resident
+    pub async fn resolve_fault(&self, va: VirtAddr, access: MappingType) -> Result<(), ErrorType> {
+        loop {
+            match self.handle_fault(va, access) {
+                Err(ErrorType::WouldBlock) => {}
+                res => return res,
+            }
+
+            let (base, object) = {
+                let inner = self.inner.lock();
+
+                match inner.vmas.find(va) {
+                    Some((range, _, VmaState::Vmo { object })) => (range.start(), object.clone()),
+                    // Unmapped meanwhile, next attempt reports it
+                    _ => continue,
+                }
+            };
+
+            object
+                .wait_page((va.bits() - base.bits()) / PAGE_SIZE)
+                .await?;
+        }
+    }
+
+    /// Write protects pages of `object` mapped at `base`
+    pub fn protect_object(&self, base: VirtAddr, object: &VmObject) {
+        let mut inner = self.inner.lock();
+
+        if let Some((range, prot)) = inner.object_vma(base, object)
+            && let Some(ttbr0) = inner.ttbr0.as_mut()
+        {
+            ttbr0
+                .protect(range, prot.read_only())
+                .expect("Page table has unexpected state");
+        }
+    }
+
+    /// Unmaps page `index` of `object` mapped at `base`
+    pub fn unmap_object_page(&self, base: VirtAddr, object: &VmObject, index: usize) {
+        let mut inner = self.inner.lock();
+
+        if inner.object_vma(base, object).is_some()
+            && let Some(ttbr0) = inner.ttbr0.as_mut()
+        {
+            ttbr0
+                .unmap(MemRange::new(
+                    VirtAddr::from_bits(base.bits() + index * PAGE_SIZE),
+                    PAGE_SIZE,
+                ))
+                .expect("Page table has unexpected state");
+        }
     }
 }
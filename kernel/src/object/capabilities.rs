@@ -1,24 +1,6 @@
-use bitmask::bitmask;
+use rtl::vmm::MappingType;
 
-bitmask! {
-    pub mask CapabilityBits: u8 where flags Capability {
-        None = 0,
-
-        // Ports
-        Send = (1 << 0),
-        Receive = (1 << 1),
-        Call = (1 << 2),
-
-        // VmSpace
-        MapPhys = (1 << 3),
-
-        // Wait
-        Wait = (1 << 4),
-
-        // VMO
-        GetPhysInfo = (1 << 5),
-    }
-}
+pub use rtl::capabilities::{Capability, CapabilityBits};
 
 #[derive(Clone)]
 pub struct CapabilityMask(CapabilityBits);
@@ -35,4 +17,34 @@ impl CapabilityMask {
     pub fn is_set(&self, caps: CapabilityMask) -> bool {
         self.0 & caps.0 == caps.0
     }
+
+    pub fn bits(&self) -> CapabilityBits {
+        self.0
+    }
+
+    /// Rights that are required to map object with specified mapping type
+    pub fn for_mapping(tp: MappingType) -> Self {
+        let mut caps = CapabilityBits::from(Capability::Map);
+
+        if tp.contains(MappingType::READ) {
+            caps = caps | Capability::Read;
+        }
+
+        if tp.contains(MappingType::WRITE) {
+            caps = caps | Capability::Read | Capability::Write;
+        }
+
+        if tp.contains(MappingType::EXECUTE) {
+            caps = caps | Capability::Read | Capability::Execute;
+        }
+
+        Self(caps)
+    }
+
+    /// Narrows rights down to `caps`. Fails if `caps` contains rights which are not set.
+    pub fn narrow(&self, caps: CapabilityBits) -> Option<Self> {
+        let new = Self(caps);
+
+        self.is_set(new.clone()).then_some(new)
+    }
 }
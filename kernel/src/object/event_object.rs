@@ -0,0 +1,144 @@
+use crate::object::KernelObjectBase;
+use crate::object::capabilities::{Capability, CapabilityMask};
+use crate::sync::Spinlock;
+use alloc::sync::{Arc, Weak};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use rtl::error::ErrorType;
+use rtl::signal::{Signal, Signals, USER_SIGNALS};
+
+/// Object, which only carries user signals
+pub struct Event {
+    base: KernelObjectBase,
+}
+
+crate::kernel_object!(Event, USER_SIGNALS, {
+    fn signal_user(&self, set: Signals, clear: Signals) -> Result<(), ErrorType> {
+        self.set_user_signals(set, clear)
+    }
+});
+
+impl Event {
+    pub fn new() -> Result<Arc<Self>, ErrorType> {
+        Arc::try_new(Self {
+            base: KernelObjectBase::new(),
+        })
+        .map_err(|_| ErrorType::NoMemory)
+    }
+
+    pub fn full_caps() -> CapabilityMask {
+        CapabilityMask::from(
+            Capability::Wait | Capability::Signal | Capability::Duplicate | Capability::Transfer,
+        )
+    }
+}
+
+/// One end of the event pair. Signaling one end changes signals of the other one, so two
+/// parties can notify each other. Once all handles to one end are closed, the other end gets
+/// `PeerClosed`.
+pub struct EventPair {
+    base: KernelObjectBase,
+    peer: Spinlock<Weak<EventPair>>,
+    handles: AtomicUsize,
+}
+
+crate::kernel_object!(
+    EventPair,
+    USER_SIGNALS | Signals::from(Signal::PeerClosed),
+    {
+        fn handle_opened(&self, _rights: &CapabilityMask) {
+            self.handles.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn handle_closed(&self, _rights: &CapabilityMask) {
+            if self.handles.fetch_sub(1, Ordering::AcqRel) == 1
+                && let Some(peer) = self.peer.lock().upgrade()
+            {
+                peer.signal_fire(Signal::PeerClosed.into());
+            }
+        }
+
+        fn signal_user(&self, set: Signals, clear: Signals) -> Result<(), ErrorType> {
+            self.peer
+                .lock()
+                .upgrade()
+                .ok_or(ErrorType::TaskDead)?
+                .set_user_signals(set, clear)
+        }
+    }
+);
+
+impl EventPair {
+    fn new() -> Result<Arc<Self>, ErrorType> {
+        Arc::try_new(Self {
+            base: KernelObjectBase::new(),
+            peer: Spinlock::new(Weak::new()),
+            handles: AtomicUsize::new(0),
+        })
+        .map_err(|_| ErrorType::NoMemory)
+    }
+
+    /// Creates two connected ends
+    pub fn new_pair() -> Result<(Arc<Self>, Arc<Self>), ErrorType> {
+        let first = Self::new()?;
+        let second = Self::new()?;
+
+        *first.peer.lock() = Arc::downgrade(&second);
+        *second.peer.lock() = Arc::downgrade(&first);
+
+        Ok((first, second))
+    }
+
+    pub fn full_caps() -> CapabilityMask {
+        Event::full_caps()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::object::KernelObject;
+    use crate::object::handle::Handle;
+    use crate::*;
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+    use test_macros::*;
+
+    #[kernel_test]
+    fn event_pair_signal() {
+        let (first, second) = EventPair::new_pair().unwrap();
+        let first_handle = Handle::new(first.clone(), EventPair::full_caps());
+        let _second_handle = Handle::new(second.clone(), EventPair::full_caps());
+
+        test_assert!(
+            first
+                .signal_user(Signal::User0.into(), Signal::None.into())
+                .is_ok()
+        );
+        test_assert!(second.signals().contains(Signal::User0.into()));
+        test_assert!(!first.signals().contains(Signal::User0.into()));
+
+        // Only user signals can be set
+        test_assert!(matches!(
+            first.signal_user(Signal::PeerClosed.into(), Signal::None.into()),
+            Err(ErrorType::InvalidArgument)
+        ));
+
+        drop(first_handle);
+        test_assert!(second.signals().contains(Signal::PeerClosed.into()));
+    }
+
+    #[kernel_test]
+    fn event_wait_any_signal() {
+        let event = Event::new().unwrap();
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut wait = pin!(event.wait_signal(Signal::User0 | Signal::User1));
+
+        test_assert!(wait.as_mut().poll(&mut cx).is_pending());
+
+        // One of requested signals is enough to complete the wait
+        event
+            .set_user_signals(Signal::User1.into(), Signal::None.into())
+            .unwrap();
+        test_assert!(matches!(wait.as_mut().poll(&mut cx), Poll::Ready(Ok(()))));
+    }
+}
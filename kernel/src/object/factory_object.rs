@@ -1,3 +1,5 @@
+use super::event_object::{Event, EventPair};
+use super::job_object::Job;
 use super::port_object::Port;
 use crate::drivers::irq::IntId;
 use crate::irq::IrqObject;
@@ -5,14 +7,15 @@ use crate::mm::vmm::vmo::VmObject;
 use crate::object::KernelObjectBase;
 use crate::object::capabilities::{Capability, CapabilityMask};
 use crate::object::handle::Handle;
-use crate::sched::current;
 use crate::sched::timer_object::TimerObject;
+use crate::sched::{current, current_task};
 use crate::tasks::task::{Task, TaskName};
 use alloc::sync::Arc;
 use rtl::error::ErrorType;
 use rtl::irq::IrqTrigger;
+use rtl::job::JobLimits;
 use rtl::signal::Signal;
-use rtl::vmm::MappingType;
+use rtl::vmm::{CacheAttr, MappingType};
 use spin::Lazy;
 
 pub struct Factory {
@@ -31,14 +34,41 @@ impl Factory {
         .ok()
     }
 
-    pub fn create_task(&self, name: &str) -> Result<Handle, ErrorType> {
+    pub fn full_caps() -> CapabilityMask {
+        CapabilityMask::from(Capability::Create | Capability::Duplicate | Capability::Transfer)
+    }
+
+    /// Pages of non-contiguous VMOs are touched by the kernel through cacheable linear mapping,
+    /// so other memory types would alias it
+    fn check_paged_cache(mt: MappingType) -> Result<(), ErrorType> {
+        if mt.cache() == CacheAttr::WriteBack {
+            Ok(())
+        } else {
+            Err(ErrorType::InvalidArgument)
+        }
+    }
+
+    /// Creates task inside `job`. By default it inherits job of the current task
+    pub fn create_task(&self, name: &str, job: Option<Arc<Job>>) -> Result<Handle, ErrorType> {
         let name = TaskName::try_from(name).map_err(|_| ErrorType::BufferTooBig)?;
-        let task = Task::new(name).ok_or(ErrorType::NoMemory)?;
-        let handle = Handle::new(task, CapabilityMask::any());
+        let job = job.unwrap_or_else(|| current_task().job().clone());
+        let task = Task::new(name, job).ok_or(ErrorType::NoMemory)?;
+        let handle = Handle::new(task, Task::full_caps());
 
         Ok(handle)
     }
 
+    /// Creates job nested into `parent`, or into the job of the current task
+    pub fn create_job(
+        &self,
+        parent: Option<Arc<Job>>,
+        limits: &JobLimits,
+    ) -> Result<Handle, ErrorType> {
+        let parent = parent.unwrap_or_else(|| current_task().job().clone());
+
+        Ok(Handle::new(parent.new_child(limits)?, Job::full_caps()))
+    }
+
     pub fn create_port(&self) -> Result<Handle, ErrorType> {
         let task = current().task();
         let port = Port::new(task.clone()).ok_or(ErrorType::NoMemory)?;
@@ -47,24 +77,51 @@ impl Factory {
     }
 
     pub fn create_vmo(&self, size: usize, mt: MappingType) -> Result<Handle, ErrorType> {
+        Self::check_paged_cache(mt)?;
+
         let vmo = VmObject::new(size, mt).ok_or(ErrorType::NoMemory)?;
 
-        Ok(Handle::new(vmo, CapabilityMask::any()))
+        Ok(Handle::new(vmo, VmObject::full_caps()))
+    }
+
+    /// Creates VMO, which pages are requested from pager listening on `port`
+    pub fn create_pager_vmo(
+        &self,
+        size: usize,
+        mt: MappingType,
+        port: Arc<Port>,
+        key: usize,
+    ) -> Result<Handle, ErrorType> {
+        Self::check_paged_cache(mt)?;
+
+        let vmo = VmObject::new_pager(size, mt, port, key)?;
+
+        Ok(Handle::new(vmo, VmObject::full_caps()))
     }
 
     pub fn create_vmo_contig(&self, size: usize, mt: MappingType) -> Result<Handle, ErrorType> {
         let vmo = VmObject::new_contig(size, mt).ok_or(ErrorType::NoMemory)?;
 
-        Ok(Handle::new(
-            vmo,
-            CapabilityMask::from(Capability::GetPhysInfo),
-        ))
+        Ok(Handle::new(vmo, VmObject::full_caps_contig()))
     }
 
     pub fn create_timer(&self) -> Result<Handle, ErrorType> {
         let timer = TimerObject::new()?;
 
-        Ok(Handle::new(timer, CapabilityMask::from(Capability::Wait)))
+        Ok(Handle::new(timer, TimerObject::full_caps()))
+    }
+
+    pub fn create_event(&self) -> Result<Handle, ErrorType> {
+        Ok(Handle::new(Event::new()?, Event::full_caps()))
+    }
+
+    pub fn create_event_pair(&self) -> Result<(Handle, Handle), ErrorType> {
+        let (first, second) = EventPair::new_pair()?;
+
+        Ok((
+            Handle::new(first, EventPair::full_caps()),
+            Handle::new(second, EventPair::full_caps()),
+        ))
     }
 
     pub fn create_irq(&self, num: usize, trigger: IrqTrigger) -> Result<Handle, ErrorType> {
@@ -76,7 +133,7 @@ impl Factory {
 
         Ok(Handle::new(
             IrqObject::new(IntId::spi(num), trigger)?,
-            CapabilityMask::from(Capability::Wait),
+            IrqObject::full_caps(),
         ))
     }
 }
@@ -2,18 +2,61 @@ use super::capabilities::CapabilityMask;
 use crate::object::KernelObject;
 use alloc::sync::Arc;
 use core::any::TypeId;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use rtl::error::ErrorType;
+
+/// Node of capability derivation tree.
+///
+/// Every derived handle remembers generation of its parent at the moment of derivation. Revoking
+/// a handle bumps its generation, which invalidates whole subtree below it.
+pub struct DerivationNode {
+    parent: Option<(Arc<DerivationNode>, usize)>,
+    generation: AtomicUsize,
+}
+
+impl DerivationNode {
+    fn new(parent: Option<Arc<DerivationNode>>) -> Result<Arc<Self>, ErrorType> {
+        let parent = parent.map(|p| {
+            let generation = p.generation.load(Ordering::Acquire);
+            (p, generation)
+        });
+
+        Arc::try_new(Self {
+            parent,
+            generation: AtomicUsize::new(0),
+        })
+        .map_err(|_| ErrorType::NoMemory)
+    }
+
+    fn is_revoked(&self) -> bool {
+        let mut cur = self;
+
+        while let Some((parent, generation)) = &cur.parent {
+            if parent.generation.load(Ordering::Acquire) != *generation {
+                return true;
+            }
+
+            cur = parent;
+        }
+
+        false
+    }
+}
 
-#[derive(Clone)]
 pub struct Handle {
     obj: Option<Arc<dyn KernelObject + Send + Sync>>,
     rights: CapabilityMask,
+    node: Option<Arc<DerivationNode>>,
 }
 
 impl Handle {
     pub fn new(o: Arc<dyn KernelObject + Send + Sync>, rights: CapabilityMask) -> Self {
+        o.handle_opened(&rights);
+
         Self {
             obj: Some(o),
             rights,
+            node: None,
         }
     }
 
@@ -21,6 +64,43 @@ impl Handle {
         self.rights.is_set(caps)
     }
 
+    pub fn rights(&self) -> &CapabilityMask {
+        &self.rights
+    }
+
+    /// Creates new handle derived from this one. Derived handle becomes invalid once
+    /// this handle (or any of its ancestors) is revoked
+    pub fn derive(&mut self, rights: CapabilityMask) -> Result<Self, ErrorType> {
+        debug_assert!(self.rights.is_set(rights.clone()));
+
+        if self.node.is_none() {
+            self.node = Some(DerivationNode::new(None)?);
+        }
+
+        let node = DerivationNode::new(self.node.clone())?;
+
+        if let Some(obj) = &self.obj {
+            obj.handle_opened(&rights);
+        }
+
+        Ok(Self {
+            obj: self.obj.clone(),
+            rights,
+            node: Some(node),
+        })
+    }
+
+    /// Invalidates all handles derived from this one. Handle itself stays valid
+    pub fn revoke(&self) {
+        if let Some(node) = &self.node {
+            node.generation.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    pub fn is_revoked(&self) -> bool {
+        self.node.as_ref().is_some_and(|x| x.is_revoked())
+    }
+
     pub fn obj<T: KernelObject + Sized + 'static + Send>(&self) -> Option<Arc<T>> {
         if let Some(o) = &self.obj {
             if o.as_any().type_id() == TypeId::of::<T>() {
@@ -46,3 +126,25 @@ impl Handle {
         }
     }
 }
+
+impl Clone for Handle {
+    fn clone(&self) -> Self {
+        if let Some(obj) = &self.obj {
+            obj.handle_opened(&self.rights);
+        }
+
+        Self {
+            obj: self.obj.clone(),
+            rights: self.rights.clone(),
+            node: self.node.clone(),
+        }
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        if let Some(obj) = &self.obj {
+            obj.handle_closed(&self.rights);
+        }
+    }
+}
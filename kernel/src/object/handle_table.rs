@@ -1,82 +1,246 @@
 use crate::object::KernelObject;
-use crate::object::capabilities::CapabilityMask;
+use crate::object::capabilities::{Capability, CapabilityBits, CapabilityMask};
 use crate::object::handle::Handle;
-use alloc::collections::btree_map::BTreeMap;
+use crate::object::job_object::{Job, Resource};
+use adt::Vec;
 use alloc::sync::Arc;
+use rtl::error::ErrorType;
 use rtl::handle::HandleBase;
 
-const MAX_HANDLES: usize = 25;
+/// Default maximum number of handles per task
+pub const DEFAULT_HANDLE_QUOTA: usize = 4096;
+
+// Handle value layout: | 0 | generation | slot index |. Top bit is kept clear, so that
+// handle stays positive when returned from a syscall.
+const SLOT_BITS: usize = 24;
+const SLOT_MASK: usize = (1 << SLOT_BITS) - 1;
+const GENERATION_MASK: usize = (1 << (usize::BITS as usize - SLOT_BITS - 1)) - 1;
+
+struct Slot {
+    generation: usize,
+    handle: Option<Handle>,
+}
 
 pub struct HandleTable {
-    table: BTreeMap<HandleBase, Handle>,
-    id: usize,
+    slots: Vec<Slot>,
+    free: Vec<usize>,
+    quota: usize,
+    used: usize,
+    // Job handles are charged to
+    job: Option<Arc<Job>>,
 }
 
 impl HandleTable {
     pub fn new() -> Self {
+        Self::with_quota(DEFAULT_HANDLE_QUOTA)
+    }
+
+    fn with_quota(quota: usize) -> Self {
         Self {
-            table: BTreeMap::new(),
-            id: 0,
+            slots: Vec::new(),
+            free: Vec::new(),
+            quota: quota.min(SLOT_MASK),
+            used: 0,
+            job: None,
         }
     }
 
-    // TODO: This is something that tastes like shit, but whatever
-    fn allocate_id(&mut self) -> HandleBase {
-        let res = self.id;
-        let (new, overflow) = self.id.overflowing_add(1);
+    /// Creates table, which handles are also charged to `job`. Quota of the table comes from
+    /// limits of the job
+    pub fn new_charged(job: Arc<Job>) -> Self {
+        let mut table = Self::with_quota(job.task_handles());
 
-        assert!(!overflow);
-        self.id = new;
-        res
+        table.job = Some(job);
+        table
+    }
+
+    fn encode(index: usize, generation: usize) -> HandleBase {
+        (generation << SLOT_BITS) | index
+    }
+
+    fn decode(hdl: HandleBase) -> (usize, usize) {
+        (hdl & SLOT_MASK, hdl >> SLOT_BITS)
     }
 
-    #[must_use]
-    pub fn add(&mut self, handle: Handle) -> HandleBase {
-        let res = self.allocate_id();
+    fn slot(&self, hdl: HandleBase) -> Option<&Slot> {
+        let (index, generation) = Self::decode(hdl);
 
-        self.table.insert(res, handle);
-        res
+        self.slots
+            .get(index)
+            .filter(|x| x.generation == generation && x.handle.is_some())
+    }
+
+    pub fn add(&mut self, handle: Handle) -> Result<HandleBase, ErrorType> {
+        if self.used >= self.quota {
+            return Err(ErrorType::NoMemory);
+        }
+
+        if let Some(job) = &self.job {
+            job.try_charge(Resource::Handles, 1)?;
+        }
+
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                // Any slot may end up in the free list, so room for it is reserved up front and
+                // removal can't fail
+                let res = self
+                    .free
+                    .try_reserve(self.slots.len() + 1)
+                    .map_err(|_| ErrorType::NoMemory)
+                    .and_then(|_| {
+                        self.slots.try_push(Slot {
+                            generation: 0,
+                            handle: None,
+                        })
+                    });
+
+                if let Err(err) = res {
+                    self.uncharge(1);
+                    return Err(err);
+                }
+
+                self.slots.len() - 1
+            }
+        };
+
+        let slot = &mut self.slots[index];
+
+        slot.handle = Some(handle);
+        self.used += 1;
+        Ok(Self::encode(index, slot.generation))
     }
 
     pub fn remove(&mut self, hdl: HandleBase) -> bool {
-        self.table.remove(&hdl).is_some()
+        if self.slot(hdl).is_none() {
+            return false;
+        }
+
+        let (index, _) = Self::decode(hdl);
+        let res = self.free.push_within_capacity(index);
+
+        debug_assert!(res.is_ok());
+
+        // Bump generation, so that stale copies of the handle are rejected
+        let slot = &mut self.slots[index];
+
+        slot.handle = None;
+        slot.generation = (slot.generation + 1) & GENERATION_MASK;
+        self.used -= 1;
+        self.uncharge(1);
+        true
+    }
+
+    fn uncharge(&self, count: usize) {
+        if let Some(job) = &self.job {
+            job.uncharge(Resource::Handles, count);
+        }
+    }
+
+    fn lookup(&self, hdl: HandleBase, rights: CapabilityMask) -> Result<&Handle, ErrorType> {
+        let handle = self
+            .slot(hdl)
+            .and_then(|x| x.handle.as_ref())
+            .filter(|x| !x.is_revoked())
+            .ok_or(ErrorType::InvalidHandle)?;
+
+        if handle.has_capabitity(rights) {
+            Ok(handle)
+        } else {
+            Err(ErrorType::AccessDenied)
+        }
+    }
+
+    /// Derives new handle from `hdl`. If `rights` is set, rights of new handle are narrowed down
+    /// to it
+    pub fn derive(
+        &mut self,
+        hdl: HandleBase,
+        required: CapabilityMask,
+        rights: Option<CapabilityBits>,
+    ) -> Result<Handle, ErrorType> {
+        self.lookup(hdl, required)?;
+
+        let (index, _) = Self::decode(hdl);
+        let handle = self.slots[index].handle.as_mut().unwrap();
+        let rights = match rights {
+            Some(r) => handle.rights().narrow(r).ok_or(ErrorType::AccessDenied)?,
+            None => handle.rights().clone(),
+        };
+
+        handle.derive(rights)
+    }
+
+    /// Adds handles derived from `handles` of `from`, which must have `Transfer` right, and
+    /// replaces values in `handles` with new ones. Either all of them are added or none
+    pub fn transfer_from(
+        &mut self,
+        from: &mut HandleTable,
+        handles: &mut [HandleBase],
+    ) -> Result<(), ErrorType> {
+        let mut added = 0;
+        let res = handles.iter_mut().try_for_each(|hdl| {
+            *hdl = self.add(from.derive(*hdl, Capability::Transfer.into(), None)?)?;
+            added += 1;
+            Ok(())
+        });
+
+        if res.is_err() {
+            for hdl in &handles[..added] {
+                self.remove(*hdl);
+            }
+        }
+
+        res
+    }
+
+    /// Revokes all handles derived from `hdl`
+    pub fn revoke(&self, hdl: HandleBase) -> Result<(), ErrorType> {
+        self.lookup(hdl, CapabilityMask::any())?.revoke();
+        Ok(())
     }
 
     pub fn find<T: KernelObject + Sized + 'static>(
         &self,
         hdl: HandleBase,
         rights: CapabilityMask,
-    ) -> Option<Arc<T>> {
-        self.table
-            .get(&hdl)
-            .filter(|x| x.has_capabitity(rights))
-            .and_then(|x| x.obj::<T>())
+    ) -> Result<Arc<T>, ErrorType> {
+        self.lookup(hdl, rights)?
+            .obj::<T>()
+            .ok_or(ErrorType::InvalidHandle)
     }
 
     pub fn find_handle<T: KernelObject + Sized + 'static>(
         &self,
         hdl: HandleBase,
         rights: CapabilityMask,
-    ) -> Option<Handle> {
-        self.table
-            .get(&hdl)
-            .filter(|x| x.has_capabitity(rights))
-            .cloned()
+    ) -> Result<Handle, ErrorType> {
+        let handle = self.lookup(hdl, rights)?;
+
+        handle
+            .obj::<T>()
+            .map(|_| handle.clone())
+            .ok_or(ErrorType::InvalidHandle)
     }
 
     pub fn find_poly(
         &self,
         hdl: HandleBase,
         rights: CapabilityMask,
-    ) -> Option<Arc<dyn KernelObject + Send + Sync>> {
-        self.table
-            .get(&hdl)
-            .filter(|x| x.has_capabitity(rights))
-            .and_then(|x| x.obj_poly())
+    ) -> Result<Arc<dyn KernelObject + Send + Sync>, ErrorType> {
+        self.lookup(hdl, rights)?
+            .obj_poly()
+            .ok_or(ErrorType::InvalidHandle)
     }
 
     pub fn find_raw_handle(&self, hdl: HandleBase) -> Option<Handle> {
-        self.table.get(&hdl).cloned()
+        self.slot(hdl).and_then(|x| x.handle.clone())
+    }
+}
+
+impl Drop for HandleTable {
+    fn drop(&mut self) {
+        self.uncharge(self.used);
     }
 }
 
@@ -93,24 +257,20 @@ mod test {
     fn find_invalid() {
         let table = HandleTable::new();
 
-        test_assert!(
-            table
-                .find_poly(12123812398, CapabilityMask::any())
-                .is_none()
-        );
+        test_assert!(table.find_poly(12123812398, CapabilityMask::any()).is_err());
     }
 
     #[kernel_test]
     fn add_handle_find_poly() {
         let mut table = HandleTable::new();
 
-        let t = Task::new("test".try_into().unwrap()).unwrap();
+        let t = Task::new("test".try_into().unwrap(), Job::root()).unwrap();
         let h = Handle::new(t.clone(), CapabilityMask::any());
 
-        let hdl = table.add(h);
+        let hdl = table.add(h).unwrap();
         let found = table.find_poly(hdl, CapabilityMask::any());
 
-        test_assert!(found.is_some());
+        test_assert!(found.is_ok());
 
         test_assert_eq!(
             Arc::as_ptr(found.as_ref().unwrap()) as *const u8 as usize,
@@ -122,16 +282,173 @@ mod test {
     fn add_handle_find() {
         let mut table = HandleTable::new();
 
-        let t = Task::new("test".try_into().unwrap()).unwrap();
+        let t = Task::new("test".try_into().unwrap(), Job::root()).unwrap();
         let h = Handle::new(t.clone(), CapabilityMask::any());
 
-        let hdl = table.add(h);
+        let hdl = table.add(h).unwrap();
         let found = table.find::<Task>(hdl, CapabilityMask::any());
-        test_assert!(found.is_some());
+        test_assert!(found.is_ok());
 
         test_assert_eq!(
             Arc::as_ptr(found.as_ref().unwrap()) as *const u8 as usize,
             Arc::as_ptr(&t) as usize
         );
     }
+
+    #[kernel_test]
+    fn find_without_rights() {
+        use crate::object::capabilities::Capability;
+
+        let mut table = HandleTable::new();
+
+        let t = Task::new("test".try_into().unwrap(), Job::root()).unwrap();
+        let h = Handle::new(t.clone(), CapabilityMask::from(Capability::Wait));
+
+        let hdl = table.add(h).unwrap();
+
+        test_assert!(
+            table
+                .find::<Task>(hdl, CapabilityMask::from(Capability::Wait))
+                .is_ok()
+        );
+        test_assert!(matches!(
+            table.find::<Task>(hdl, CapabilityMask::from(Capability::Manage)),
+            Err(ErrorType::AccessDenied)
+        ));
+    }
+
+    #[kernel_test]
+    fn revoke_derived() {
+        let mut table = HandleTable::new();
+
+        let t = Task::new("test".try_into().unwrap(), Job::root()).unwrap();
+        let h = Handle::new(t.clone(), CapabilityMask::any());
+
+        let parent = table.add(h).unwrap();
+        let child = table.derive(parent, CapabilityMask::any(), None).unwrap();
+        let child = table.add(child).unwrap();
+        let grandchild = table.derive(child, CapabilityMask::any(), None).unwrap();
+        let grandchild = table.add(grandchild).unwrap();
+
+        test_assert!(table.revoke(parent).is_ok());
+
+        test_assert!(table.find::<Task>(parent, CapabilityMask::any()).is_ok());
+        test_assert!(matches!(
+            table.find::<Task>(child, CapabilityMask::any()),
+            Err(ErrorType::InvalidHandle)
+        ));
+        test_assert!(matches!(
+            table.find::<Task>(grandchild, CapabilityMask::any()),
+            Err(ErrorType::InvalidHandle)
+        ));
+
+        // Handles derived after revocation are valid
+        let child = table.derive(parent, CapabilityMask::any(), None).unwrap();
+        let child = table.add(child).unwrap();
+
+        test_assert!(table.find::<Task>(child, CapabilityMask::any()).is_ok());
+    }
+
+    #[kernel_test]
+    fn stale_handle() {
+        let mut table = HandleTable::new();
+
+        let t = Task::new("test".try_into().unwrap(), Job::root()).unwrap();
+        let old = table
+            .add(Handle::new(t.clone(), CapabilityMask::any()))
+            .unwrap();
+
+        test_assert!(table.remove(old));
+
+        // Slot is reused, but old handle value must not be accepted
+        let new = table
+            .add(Handle::new(t.clone(), CapabilityMask::any()))
+            .unwrap();
+
+        test_assert!(old != new);
+        test_assert!(table.find::<Task>(old, CapabilityMask::any()).is_err());
+        test_assert!(table.find::<Task>(new, CapabilityMask::any()).is_ok());
+        test_assert!(!table.remove(old));
+    }
+
+    #[kernel_test]
+    fn quota() {
+        let mut table = HandleTable::with_quota(2);
+
+        let t = Task::new("test".try_into().unwrap(), Job::root()).unwrap();
+        let h = table
+            .add(Handle::new(t.clone(), CapabilityMask::any()))
+            .unwrap();
+
+        test_assert!(
+            table
+                .add(Handle::new(t.clone(), CapabilityMask::any()))
+                .is_ok()
+        );
+        test_assert!(matches!(
+            table.add(Handle::new(t.clone(), CapabilityMask::any())),
+            Err(ErrorType::NoMemory)
+        ));
+
+        test_assert!(table.remove(h));
+        test_assert!(
+            table
+                .add(Handle::new(t.clone(), CapabilityMask::any()))
+                .is_ok()
+        );
+    }
+
+    #[kernel_test]
+    fn transfer_rollback() {
+        use rtl::job::JobLimits;
+
+        let limits = JobLimits {
+            task_handles: 1,
+            ..JobLimits::unlimited()
+        };
+        let job = Job::root().new_child(&limits).unwrap();
+        let mut from = HandleTable::new();
+        let mut to = HandleTable::new_charged(job.clone());
+        let caps = CapabilityMask::from(Capability::Transfer);
+
+        let t = Task::new("test".try_into().unwrap(), Job::root()).unwrap();
+        let mut handles = [
+            from.add(Handle::new(t.clone(), caps.clone())).unwrap(),
+            from.add(Handle::new(t.clone(), caps.clone())).unwrap(),
+        ];
+
+        // Second handle exceeds the quota, so the first one must not stay behind
+        test_assert!(matches!(
+            to.transfer_from(&mut from, &mut handles),
+            Err(ErrorType::NoMemory)
+        ));
+        test_assert_eq!(job.usage(Resource::Handles), 0);
+        test_assert!(to.add(Handle::new(t.clone(), caps)).is_ok());
+    }
+
+    #[kernel_test]
+    fn port_peer_closed() {
+        use crate::object::capabilities::Capability;
+        use crate::object::port_object::Port;
+        use rtl::signal::Signal;
+
+        let mut table = HandleTable::new();
+
+        let t = Task::new("test".try_into().unwrap(), Job::root()).unwrap();
+        let port = Port::new(t).unwrap();
+        let hdl = table
+            .add(Handle::new(port.clone(), Port::full_caps()))
+            .unwrap();
+        let send = table
+            .derive(hdl, CapabilityMask::any(), Some(Capability::Send.into()))
+            .unwrap();
+
+        test_assert!(!port.signals().contains(Signal::PeerClosed.into()));
+
+        // Send-only handle does not keep the port open
+        test_assert!(table.remove(hdl));
+        test_assert!(port.signals().contains(Signal::PeerClosed.into()));
+
+        drop(send);
+    }
 }
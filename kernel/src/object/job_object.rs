@@ -0,0 +1,195 @@
+use crate::object::KernelObjectBase;
+use crate::object::capabilities::{Capability, CapabilityMask};
+use crate::object::handle_table::DEFAULT_HANDLE_QUOTA;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use rtl::error::ErrorType;
+use rtl::job::JobLimits;
+use rtl::signal::Signal;
+use spin::Lazy;
+
+/// Resources accounted by [`Job`]
+#[derive(Clone, Copy, Debug)]
+pub enum Resource {
+    Pages,
+    Handles,
+    IpcBytes,
+    Threads,
+}
+
+const RESOURCE_COUNT: usize = 4;
+
+/// Group of tasks sharing resource limits. Jobs form a tree: usage is charged to the job and
+/// all of its ancestors, so a child can never consume more than its parent allows.
+pub struct Job {
+    base: KernelObjectBase,
+    parent: Option<Arc<Job>>,
+    limits: [usize; RESOURCE_COUNT],
+    usage: [AtomicUsize; RESOURCE_COUNT],
+    // Handle quota of each task, which is not charged to the job
+    task_handles: usize,
+}
+
+crate::kernel_object!(Job, Signal::None.into());
+
+static ROOT_JOB: Lazy<Arc<Job>> = Lazy::new(|| {
+    let limits = JobLimits {
+        task_handles: DEFAULT_HANDLE_QUOTA,
+        ..JobLimits::unlimited()
+    };
+
+    Job::new(None, &limits).expect("No memory for root job")
+});
+
+/// Charged amount of a resource. Returned back to the job on drop
+pub struct JobCharge {
+    job: Arc<Job>,
+    res: Resource,
+    amount: usize,
+}
+
+impl Job {
+    fn new(parent: Option<Arc<Job>>, limits: &JobLimits) -> Result<Arc<Self>, ErrorType> {
+        let task_handles = match &parent {
+            Some(parent) => limits.task_handles.min(parent.task_handles),
+            None => limits.task_handles,
+        };
+
+        Arc::try_new(Self {
+            base: KernelObjectBase::new(),
+            parent,
+            limits: [
+                limits.pages,
+                limits.handles,
+                limits.ipc_bytes,
+                limits.threads,
+            ],
+            usage: Default::default(),
+            task_handles,
+        })
+        .map_err(|_| ErrorType::NoMemory)
+    }
+
+    /// Job without limits all other jobs descend from
+    pub fn root() -> Arc<Self> {
+        ROOT_JOB.clone()
+    }
+
+    pub fn new_child(self: &Arc<Self>, limits: &JobLimits) -> Result<Arc<Self>, ErrorType> {
+        Self::new(Some(self.clone()), limits)
+    }
+
+    pub fn full_caps() -> CapabilityMask {
+        CapabilityMask::from(Capability::Manage | Capability::Duplicate | Capability::Transfer)
+    }
+
+    /// Charges `amount` of `res` to the job and its ancestors. Fails with
+    /// [`ErrorType::NoMemory`] if any of them would exceed its limit
+    pub fn try_charge(&self, res: Resource, amount: usize) -> Result<(), ErrorType> {
+        let usage = &self.usage[res as usize];
+        let limit = self.limits[res as usize];
+
+        usage
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |x| {
+                x.checked_add(amount).filter(|x| *x <= limit)
+            })
+            .map_err(|_| ErrorType::NoMemory)?;
+
+        if let Some(parent) = &self.parent
+            && let Err(err) = parent.try_charge(res, amount)
+        {
+            usage.fetch_sub(amount, Ordering::AcqRel);
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    /// Returns `amount` of `res` previously taken by [`Job::try_charge`]
+    pub fn uncharge(&self, res: Resource, amount: usize) {
+        let mut job = Some(self);
+
+        while let Some(cur) = job {
+            cur.usage[res as usize].fetch_sub(amount, Ordering::AcqRel);
+            job = cur.parent.as_deref();
+        }
+    }
+
+    /// Same as [`Job::try_charge`], but charge is returned once the result is dropped
+    pub fn charge(self: &Arc<Self>, res: Resource, amount: usize) -> Result<JobCharge, ErrorType> {
+        self.try_charge(res, amount)?;
+
+        Ok(JobCharge {
+            job: self.clone(),
+            res,
+            amount,
+        })
+    }
+
+    pub fn usage(&self, res: Resource) -> usize {
+        self.usage[res as usize].load(Ordering::Relaxed)
+    }
+
+    /// Maximum number of handles each task of the job may hold
+    pub fn task_handles(&self) -> usize {
+        self.task_handles
+    }
+}
+
+impl Drop for JobCharge {
+    fn drop(&mut self) {
+        self.job.uncharge(self.res, self.amount);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+    use test_macros::*;
+
+    #[kernel_test]
+    fn job_limits() {
+        let parent = Job::root()
+            .new_child(&JobLimits {
+                pages: 10,
+                ..JobLimits::unlimited()
+            })
+            .unwrap();
+        let child = parent.new_child(&JobLimits::unlimited()).unwrap();
+
+        let charge = child.charge(Resource::Pages, 8).unwrap();
+        test_assert_eq!(parent.usage(Resource::Pages), 8);
+
+        // Parent limit applies to the child as well
+        test_assert!(child.charge(Resource::Pages, 3).is_err());
+        test_assert_eq!(child.usage(Resource::Pages), 8);
+
+        drop(charge);
+        test_assert_eq!(parent.usage(Resource::Pages), 0);
+        test_assert!(child.charge(Resource::Pages, 10).is_ok());
+    }
+
+    #[kernel_test]
+    fn job_task_handles() {
+        let parent = Job::root()
+            .new_child(&JobLimits {
+                task_handles: 8,
+                ..JobLimits::unlimited()
+            })
+            .unwrap();
+
+        // Child can't raise the quota of its parent
+        let child = parent.new_child(&JobLimits::unlimited()).unwrap();
+        test_assert_eq!(child.task_handles(), 8);
+
+        let child = parent
+            .new_child(&JobLimits {
+                task_handles: 2,
+                ..JobLimits::unlimited()
+            })
+            .unwrap();
+        test_assert_eq!(child.task_handles(), 2);
+        test_assert_eq!(Job::root().task_handles(), DEFAULT_HANDLE_QUOTA);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::object::capabilities::CapabilityMask;
 use crate::sync::Spinlock;
 use adt::Vec;
 use alloc::boxed::Box;
@@ -6,17 +7,20 @@ use core::any::Any;
 use core::future::Future;
 use core::ops::Deref;
 use core::pin::Pin;
-use core::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+use core::sync::atomic::{AtomicU16, AtomicU64, Ordering};
 use core::task::{Context, Poll};
 use rtl::error::ErrorType;
-use rtl::signal::Signals;
+use rtl::signal::{Signals, USER_SIGNALS};
 
 pub mod capabilities;
 pub mod handle;
 pub mod handle_table;
 
+pub mod event_object;
 pub mod factory_object;
+pub mod job_object;
 pub mod port_object;
+pub mod resource_object;
 
 /// Callback that is called on event
 struct Observer {
@@ -89,12 +93,13 @@ impl KernelObjectBase {
         Ok(token)
     }
 
+    /// Waits until any of `sig` is set
     pub async fn wait_signal(&self, sig: Signals) -> Result<(), ErrorType> {
         struct Wait<'a> {
             sig: Signals,
             base: &'a KernelObjectBase,
             // TODO: add atomic signals
-            pending: Arc<AtomicU8>,
+            pending: Arc<AtomicU16>,
             token: Option<ObserverToken>,
         }
 
@@ -121,7 +126,7 @@ impl KernelObjectBase {
 
                     let token = self.base.add_observer(Observer::new(
                         Box::try_new(move |sig: Signals| {
-                            if sig.contains(wait_sig) {
+                            if *(sig & wait_sig) != 0 {
                                 pending.fetch_or(*(sig & wait_sig), Ordering::Release);
                                 waker.wake_by_ref();
                                 true
@@ -142,7 +147,7 @@ impl KernelObjectBase {
             }
         }
 
-        let pending = Arc::try_new(AtomicU8::new(0)).map_err(|_| ErrorType::NoMemory)?;
+        let pending = Arc::try_new(AtomicU16::new(0)).map_err(|_| ErrorType::NoMemory)?;
 
         Wait {
             base: self,
@@ -159,6 +164,17 @@ impl KernelObjectBase {
         inner.signals &= !sig;
     }
 
+    /// Clears `clear` and then fires `set`. Only user signals can be touched
+    pub fn set_user_signals(&self, set: Signals, clear: Signals) -> Result<(), ErrorType> {
+        if *((set | clear) & !USER_SIGNALS) != 0 {
+            return Err(ErrorType::InvalidArgument);
+        }
+
+        self.signal_clear(clear);
+        self.signal_fire(set);
+        Ok(())
+    }
+
     pub fn signal_fire(&self, sig: Signals) {
         let mut inner = self.0.lock();
         inner.signals |= sig;
@@ -177,7 +193,7 @@ pub struct WaitManyArg {
 pub async fn wait_many(entries: &mut Vec<WaitManyArg>) -> Result<(), ErrorType> {
     struct Registration {
         obj: Arc<dyn KernelObject + Send>,
-        pending: Arc<AtomicU8>,
+        pending: Arc<AtomicU16>,
         token: ObserverToken,
     }
 
@@ -193,7 +209,7 @@ pub async fn wait_many(entries: &mut Vec<WaitManyArg>) -> Result<(), ErrorType>
                 let obj = self.entries[entry_index].obj.clone();
                 let waitfor = self.entries[entry_index].waitfor;
                 let waker = cx.waker().clone();
-                let pending = Arc::try_new(AtomicU8::new(0)).map_err(|_| ErrorType::NoMemory)?;
+                let pending = Arc::try_new(AtomicU16::new(0)).map_err(|_| ErrorType::NoMemory)?;
 
                 // We need to clone to shut up rust....
                 let callback_pending = pending.clone();
@@ -202,7 +218,7 @@ pub async fn wait_many(entries: &mut Vec<WaitManyArg>) -> Result<(), ErrorType>
                 // signals after callback is too late.
                 let token = obj.add_observer(Observer::new(
                     Box::try_new(move |sig: Signals| {
-                        if sig.contains(waitfor) {
+                        if *(sig & waitfor) != 0 {
                             // release matches with release in observer.
                             callback_pending.fetch_or(*(sig & waitfor), Ordering::Release);
                             waker.wake_by_ref();
@@ -297,11 +313,25 @@ pub trait KernelObject: Send + Deref<Target = KernelObjectBase> {
 
     /// Signals that can be fired on this object
     fn supported_signals(&self) -> Signals;
+
+    /// Called when handle with `rights` to this object is created
+    fn handle_opened(&self, _rights: &CapabilityMask) {}
+
+    /// Called when handle with `rights` to this object is destroyed
+    fn handle_closed(&self, _rights: &CapabilityMask) {}
+
+    /// Sets and clears user signals on behalf of user space
+    fn signal_user(&self, _set: Signals, _clear: Signals) -> Result<(), ErrorType> {
+        Err(ErrorType::NoOperation)
+    }
 }
 
 #[macro_export]
 macro_rules! kernel_object {
     ($class:ty, $signals:expr) => {
+        $crate::kernel_object!($class, $signals, {});
+    };
+    ($class:ty, $signals:expr, { $($hooks:tt)* }) => {
         impl $crate::object::KernelObject for $class {
             fn as_any(&self) -> &dyn core::any::Any {
                 self
@@ -310,6 +340,8 @@ macro_rules! kernel_object {
             fn supported_signals(&self) -> rtl::signal::Signals {
                 $signals
             }
+
+            $($hooks)*
         }
 
         impl core::ops::Deref for $class {
@@ -1,43 +1,286 @@
+use crate::mm::paging::page_table::PageSource;
 use crate::mm::user_buffer::UserPtr;
+use crate::mm::vmm::vmo::VmObject;
 use crate::object::KernelObjectBase;
 use crate::object::capabilities::{Capability, CapabilityMask};
 use crate::object::handle::Handle;
 use crate::object::handle_table::HandleTable;
-use crate::sched::current_task;
-use crate::sync::WaitQueue;
+use crate::object::job_object::{Job, JobCharge, Resource};
+use crate::sched::timer::with_deadline;
+use crate::sched::{current, current_task, handoff};
+use crate::sync::{Spinlock, WaitQueue};
 use crate::tasks::task::Task;
+use crate::tasks::thread::Thread;
+use adt::Vec;
 use alloc::boxed::Box;
 use alloc::sync::{Arc, Weak};
+use core::future::{Future, poll_fn};
+use core::pin::pin;
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use core::task::Poll;
+use core::time::Duration;
+use hal::address::{LinearAddr, VirtualAddress};
+use hal::arch::PAGE_SIZE;
 use rtl::error::ErrorType;
 use rtl::handle::*;
 use rtl::ipc::*;
 use rtl::signal::Signal;
+use rtl::vmm::MappingType;
 
 /// Port holds weak reference to owner task, since thread may die while
 /// other task has handle to it
 pub struct Port {
     base: KernelObjectBase,
     task: Weak<Task>,
-    queue: WaitQueue<IpcMessage<'static>>, // Kernel holds a copy, so just lie about lifetime for now
+    queue: WaitQueue<KernelMessage>,
+    // Threads blocked in receive. Sender writes message right into buffers of one of them and
+    // hands CPU off to it
+    receivers: Spinlock<Vec<Arc<Receiver>>>,
+    // Reply ports of calls, which were sent to this port, but not replied yet
+    calls: Spinlock<Vec<Weak<Port>>>,
+    // Number of handles allowed to receive from this port
+    receive_handles: AtomicUsize,
+    closed: AtomicBool,
 }
 
-crate::kernel_object!(Port, Signal::MessageReady.into());
+crate::kernel_object!(Port, Signal::MessageReady | Signal::PeerClosed, {
+    fn handle_opened(&self, rights: &CapabilityMask) {
+        if rights.is_set(Capability::Receive.into()) {
+            self.receive_handles.fetch_add(1, Ordering::Relaxed);
+        }
+    }
 
-fn copy_ipc_message_from_user(
+    fn handle_closed(&self, rights: &CapabilityMask) {
+        if rights.is_set(Capability::Receive.into())
+            && self.receive_handles.fetch_sub(1, Ordering::AcqRel) == 1
+        {
+            self.close();
+        }
+    }
+});
+
+/// Payload of the message in flight. Small payloads are stored inline, so fast path does not
+/// touch the heap. Large ones are copied into pages, which are later mapped into the receiver
+enum Payload {
+    Inline {
+        data: [u8; IPC_INLINE_SIZE],
+        len: usize,
+    },
+    Heap(Box<[u8]>),
+    Pages {
+        vmo: Arc<VmObject>,
+        len: usize,
+    },
+}
+
+/// Kernel copy of the message. Arenas of `msg` point to user memory of the sender and must not
+/// be touched
+struct KernelMessage {
+    msg: IpcMessage<'static>,
+    payload: Option<Payload>,
+    // Queued bytes are charged to the sender until the message is received
+    _charge: JobCharge,
+}
+
+/// Copies user segments `src` into kernel chunks `dst` until either of them runs out
+fn gather_from_user<'a>(
+    src: &[&[u8]],
+    dst: impl Iterator<Item = &'a mut [u8]>,
+) -> Result<(), ErrorType> {
+    let mut src = src.iter();
+    let mut cur: &[u8] = &[];
+
+    for mut chunk in dst {
+        while !chunk.is_empty() {
+            if cur.is_empty() {
+                match src.next() {
+                    Some(next) => cur = next,
+                    None => return Ok(()),
+                }
+
+                continue;
+            }
+
+            let n = usize::min(cur.len(), chunk.len());
+
+            UserPtr::new_array(cur.as_ptr(), n)
+                .read_to(&mut chunk[..n])
+                .ok_or(ErrorType::Fault)?;
+
+            cur = &cur[n..];
+            chunk = &mut core::mem::take(&mut chunk)[n..];
+        }
+    }
+
+    Ok(())
+}
+
+/// Copies `src` into user segments `dst`. Fails if `src` does not fit
+fn scatter_to_user(mut src: &[u8], dst: &[&[u8]]) -> Result<(), ErrorType> {
+    for d in dst {
+        if src.is_empty() {
+            break;
+        }
+
+        let n = usize::min(src.len(), d.len());
+
+        UserPtr::new_array(d.as_ptr(), n).write_array(&src[..n])?;
+        src = &src[n..];
+    }
+
+    if src.is_empty() {
+        Ok(())
+    } else {
+        Err(ErrorType::InvalidArgument)
+    }
+}
+
+/// Reads message from user memory and checks its counts
+fn read_ipc_message(
     user_msg: UserPtr<IpcMessage<'static>>,
 ) -> Result<IpcMessage<'static>, ErrorType> {
-    let mut user_msg = user_msg.read().ok_or(ErrorType::Fault)?;
+    let msg = user_msg.read().ok_or(ErrorType::Fault)?;
+
+    if !msg.is_valid() {
+        return Err(ErrorType::InvalidArgument);
+    }
+
+    Ok(msg)
+}
+
+fn copy_ipc_message_from_user(
+    user_msg: UserPtr<IpcMessage<'static>>,
+) -> Result<KernelMessage, ErrorType> {
+    let msg = read_ipc_message(user_msg)?;
+    let iov = msg.out_iovecs();
+    let len = iov
+        .iter()
+        .try_fold(0usize, |acc, x| acc.checked_add(x.len()))
+        .ok_or(ErrorType::InvalidArgument)?;
+    let charge = current_task()
+        .job()
+        .charge(Resource::IpcBytes, size_of::<IpcMessage>() + len)?;
+
+    let payload = match len {
+        0 => None,
+        _ if len <= IPC_INLINE_SIZE => {
+            let mut data = [0; IPC_INLINE_SIZE];
+
+            gather_from_user(iov, core::iter::once(&mut data[..len]))?;
+            Some(Payload::Inline { data, len })
+        }
+        _ if len <= IPC_MAP_THRESHOLD => {
+            let mut data = Vec::with_capacity(len)?;
+
+            data.resize(len, 0);
+
+            let mut data = data.into_boxed_slice();
 
-    let data = user_msg.out_arena();
+            gather_from_user(iov, core::iter::once(&mut data[..]))?;
+            Some(Payload::Heap(data))
+        }
+        _ => {
+            let vmo = VmObject::new_committed(len, MappingType::DATA).ok_or(ErrorType::NoMemory)?;
+            let mut source = vmo.source();
+            let pages = core::iter::from_fn(|| {
+                let pa = source.next_page()?;
+
+                // SAFETY: pages are owned by vmo, which is not visible to anyone yet
+                Some(unsafe {
+                    core::slice::from_raw_parts_mut(LinearAddr::from(pa).to_raw_mut(), PAGE_SIZE)
+                })
+            });
+
+            gather_from_user(iov, pages)?;
+            drop(source);
+
+            Some(Payload::Pages { vmo, len })
+        }
+    };
+
+    Ok(KernelMessage {
+        msg,
+        payload,
+        _charge: charge,
+    })
+}
+
+const RECEIVER_WAITING: u8 = 0;
+const RECEIVER_CLAIMED: u8 = 1;
+const RECEIVER_GONE: u8 = 2;
+
+/// Thread blocked in receive. Sender, which claims it, writes the message right into its
+/// buffers instead of queueing it
+struct Receiver {
+    thread: Arc<Thread>,
+    msg: UserPtr<IpcMessage<'static>>,
+    state: AtomicU8,
+    // Result of the direct delivery
+    delivered: WaitQueue<Result<usize, ErrorType>>,
+}
+
+/// What blocked receiver got
+enum Incoming {
+    Direct(Result<usize, ErrorType>),
+    Queued(KernelMessage),
+}
+
+impl Receiver {
+    fn new(msg: UserPtr<IpcMessage<'static>>) -> Result<Arc<Self>, ErrorType> {
+        Arc::try_new(Self {
+            thread: current(),
+            msg,
+            state: AtomicU8::new(RECEIVER_WAITING),
+            delivered: WaitQueue::new(),
+        })
+        .map_err(|_| ErrorType::NoMemory)
+    }
+
+    /// Called by sender. Only one of them can claim the receiver
+    fn claim(&self) -> bool {
+        self.state
+            .compare_exchange(
+                RECEIVER_WAITING,
+                RECEIVER_CLAIMED,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    }
+
+    /// Called by receiver once it stops waiting. Fails if sender has claimed it already
+    fn leave(&self) -> bool {
+        self.state
+            .compare_exchange(
+                RECEIVER_WAITING,
+                RECEIVER_GONE,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    }
+}
 
-    if let Some(d) = data {
-        let user_buffer = UserPtr::new_array(d.as_ptr(), d.len());
-        let user_buffer = user_buffer.read_on_heap()?;
+/// Registers receiver blocked on the port
+struct ReceiverGuard<'a> {
+    port: &'a Port,
+    receiver: Arc<Receiver>,
+}
 
-        user_msg.set_out_arena(Box::leak(user_buffer));
+impl<'a> ReceiverGuard<'a> {
+    fn new(port: &'a Port, receiver: Arc<Receiver>) -> Result<Self, ErrorType> {
+        port.receivers.lock().try_push(receiver.clone())?;
+        Ok(Self { port, receiver })
     }
+}
 
-    Ok(user_msg)
+impl Drop for ReceiverGuard<'_> {
+    fn drop(&mut self) {
+        self.port
+            .receivers
+            .lock()
+            .retain(|x| !Arc::ptr_eq(x, &self.receiver));
+    }
 }
 
 impl Port {
@@ -45,6 +288,10 @@ impl Port {
         Arc::try_new(Self {
             task: Arc::downgrade(&thread),
             queue: WaitQueue::new(),
+            receivers: Spinlock::new(Vec::new()),
+            calls: Spinlock::new(Vec::new()),
+            receive_handles: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
             base: KernelObjectBase::new(),
         })
         .ok()
@@ -52,23 +299,23 @@ impl Port {
 
     pub fn full_caps() -> CapabilityMask {
         CapabilityMask::from(
-            Capability::Call | Capability::Send | Capability::Receive | Capability::Wait,
+            Capability::Call
+                | Capability::Send
+                | Capability::Receive
+                | Capability::Wait
+                | Capability::Duplicate
+                | Capability::Transfer,
         )
     }
 
     async fn transfer_handles_from_current(
-        from: &HandleTable,
+        from: &mut HandleTable,
         to: &Arc<Task>,
-        msg: &mut IpcMessage<'static>,
+        msg: &mut KernelMessage,
     ) -> Result<(), ErrorType> {
-        let mut to_table = to.handle_table().await?;
-
-        // TODO remove handles in case of an error
-        for i in msg.handles_mut() {
-            *i = to_table.add(from.find_raw_handle(*i).ok_or(ErrorType::InvalidHandle)?);
-        }
-
-        Ok(())
+        to.handle_table()
+            .await?
+            .transfer_from(from, msg.msg.handles_mut())
     }
 
     async fn send_impl(
@@ -78,18 +325,37 @@ impl Port {
         let mut client_msg = copy_ipc_message_from_user(client_msg_uptr)?;
         let task = self.task.upgrade().ok_or(ErrorType::TaskDead)?;
         let self_task = current_task();
-        let self_table = self_task.handle_table().await?;
-        let reply_port = self_table
-            .find_handle::<Self>(client_msg.reply_port(), CapabilityMask::any())
-            .ok_or(ErrorType::InvalidHandle)?;
+        let mut self_table = self_task.handle_table().await?;
+        let receive = CapabilityMask::from(Capability::Receive);
+        let reply_port =
+            self_table.find_handle::<Self>(client_msg.msg.reply_port(), receive.clone())?;
+
+        self.add_call(&reply_port.obj::<Self>().unwrap())?;
 
-        Self::transfer_handles_from_current(&self_table, &task, &mut client_msg).await?;
+        // Receiver is only allowed to reply
+        let server_reply_port = self_table.derive(
+            client_msg.msg.reply_port(),
+            receive,
+            Some(Capability::Send.into()),
+        )?;
+
+        Self::transfer_handles_from_current(&mut self_table, &task, &mut client_msg).await?;
 
         // Drop self lock before waiting for the message
         drop(self_table);
-        let my_port = task.handle_table().await?.add(reply_port.clone());
-        client_msg.set_reply_port(my_port);
-        self.produce(client_msg);
+
+        let my_port = {
+            let mut table = task.handle_table().await?;
+
+            table.add(server_reply_port).inspect_err(|_| {
+                // Message is not sent, so receiver must not keep its handles
+                for hdl in client_msg.msg.handles() {
+                    table.remove(*hdl);
+                }
+            })?
+        };
+        client_msg.msg.set_reply_port(my_port);
+        self.send_message(client_msg);
 
         Ok(reply_port)
     }
@@ -101,16 +367,44 @@ impl Port {
         self.send_impl(client_msg_uptr).await.map(|_| ())
     }
 
+    /// Queues message built by the kernel on behalf of `job`. Data must fit inline and there is
+    /// no reply port, so receiver can't answer it
+    pub fn send_kernel(&self, data: &[u8], job: &Arc<Job>) -> Result<(), ErrorType> {
+        if data.len() > IPC_INLINE_SIZE {
+            return Err(ErrorType::InvalidArgument);
+        }
+
+        if self.closed.load(Ordering::Acquire) {
+            return Err(ErrorType::TaskDead);
+        }
+
+        let charge = job.charge(Resource::IpcBytes, size_of::<IpcMessage>() + data.len())?;
+        let mut inline = [0; IPC_INLINE_SIZE];
+
+        inline[..data.len()].copy_from_slice(data);
+        self.produce(KernelMessage {
+            msg: IpcMessage::default(),
+            payload: Some(Payload::Inline {
+                data: inline,
+                len: data.len(),
+            }),
+            _charge: charge,
+        });
+
+        Ok(())
+    }
+
     pub async fn call(
         &self,
         client_msg_uptr: UserPtr<IpcMessage<'static>>,
+        deadline: Option<Duration>,
     ) -> Result<usize, ErrorType> {
         let reply_port = self.send_impl(client_msg_uptr).await?;
 
         reply_port
             .obj::<Self>()
             .unwrap()
-            .receive(client_msg_uptr)
+            .receive(client_msg_uptr, deadline)
             .await
     }
 
@@ -121,57 +415,336 @@ impl Port {
     ) -> Result<(), ErrorType> {
         let self_task = current_task();
         let mut self_table = self_task.handle_table().await?;
-        let reply_port = self_table
-            .find::<Self>(reply_port_handle, CapabilityMask::any())
-            .ok_or(ErrorType::InvalidHandle)?;
+        let reply_port =
+            self_table.find::<Self>(reply_port_handle, CapabilityMask::from(Capability::Send))?;
 
-        let task = reply_port.task.upgrade().ok_or(ErrorType::TaskDead)?;
-
-        self_table.remove(reply_port_handle);
+        // Caller is gone, nobody is going to receive the reply
+        let Some(task) = reply_port
+            .task
+            .upgrade()
+            .filter(|_| !reply_port.closed.load(Ordering::Acquire))
+        else {
+            self.end_call(&mut self_table, reply_port_handle, &reply_port);
+            return Err(ErrorType::TaskDead);
+        };
 
+        // Call stays pending until the reply is built, so malformed one can be retried
         let mut user_msg = copy_ipc_message_from_user(msg)?;
-        Self::transfer_handles_from_current(&self_table, &task, &mut user_msg).await?;
-        reply_port.produce(user_msg);
+        Self::transfer_handles_from_current(&mut self_table, &task, &mut user_msg).await?;
+
+        self.end_call(&mut self_table, reply_port_handle, &reply_port);
+
+        // Drop self lock before handing off to the caller
+        drop(self_table);
+
+        reply_port.send_message(user_msg);
         Ok(())
     }
 
+    /// Forgets call, which is replied to or whose caller is gone
+    fn end_call(
+        &self,
+        table: &mut HandleTable,
+        reply_port_handle: HandleBase,
+        reply_port: &Arc<Self>,
+    ) {
+        table.remove(reply_port_handle);
+        self.calls
+            .lock()
+            .retain(|x| !core::ptr::eq(x.as_ptr(), Arc::as_ptr(reply_port)));
+    }
+
+    /// Receives a message, blocking until it arrives or `deadline` passes (see
+    /// [`with_deadline`]). Fails with [`ErrorType::TaskDead`] if the peer goes away before that
     pub async fn receive(
         &self,
+        server_msg_uptr: UserPtr<IpcMessage<'static>>,
+        deadline: Option<Duration>,
+    ) -> Result<usize, ErrorType> {
+        let client_msg = match self.try_consume() {
+            Some(msg) => msg,
+            None if self.is_peer_closed() => return Err(ErrorType::TaskDead),
+            None => {
+                let receiver = Receiver::new(server_msg_uptr)?;
+                let _guard = ReceiverGuard::new(self, receiver.clone())?;
+                let mut direct = pin!(receiver.delivered.consume());
+                let mut consume = pin!(self.queue.consume());
+                let mut closed = pin!(self.wait_signal(Signal::PeerClosed.into()));
+
+                // Message wins if both are ready, so nothing that is already queued is lost
+                let res = with_deadline(
+                    deadline,
+                    poll_fn(|cx| {
+                        if let Poll::Ready(res) = direct.as_mut().poll(cx) {
+                            return Poll::Ready(res.map(Incoming::Direct));
+                        }
+
+                        match consume.as_mut().poll(cx) {
+                            Poll::Ready(msg) => Poll::Ready(msg.map(Incoming::Queued)),
+                            Poll::Pending => closed
+                                .as_mut()
+                                .poll(cx)
+                                .map(|res| res.and(Err(ErrorType::TaskDead))),
+                        }
+                    }),
+                )
+                .await;
+
+                match res {
+                    Ok(Incoming::Direct(res)) => return res,
+                    // Sender claimed the receiver before it stopped waiting, so the message is
+                    // being written into its buffers. Queued one goes to the next receiver
+                    _ if !receiver.leave() => {
+                        if let Ok(Incoming::Queued(msg)) = res {
+                            self.queue.produce_front(msg);
+                            self.signal_fire(Signal::MessageReady.into());
+                        }
+
+                        return receiver.delivered.consume().await?;
+                    }
+                    Ok(Incoming::Queued(msg)) => {
+                        self.signal_clear(Signal::MessageReady.into());
+                        msg
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+        };
+
+        self.deliver(server_msg_uptr, client_msg).await
+    }
+
+    /// Writes queued message into buffers of the receiver. If they can't take it, message is
+    /// queued back together with handles transferred with it, so the receiver can retry
+    async fn deliver(
+        &self,
+        server_msg_uptr: UserPtr<IpcMessage<'static>>,
+        client_msg: KernelMessage,
+    ) -> Result<usize, ErrorType> {
+        let res = Self::map_and_write(server_msg_uptr, &client_msg).await;
+
+        if res.is_err() {
+            self.queue.produce_front(client_msg);
+            self.signal_fire(Signal::MessageReady.into());
+        }
+
+        res
+    }
+
+    /// Maps large payload into the receiver and writes the message. Payload is unmapped again,
+    /// if the message can't be written
+    async fn map_and_write(
+        server_msg_uptr: UserPtr<IpcMessage<'static>>,
+        client_msg: &KernelMessage,
+    ) -> Result<usize, ErrorType> {
+        let Some(Payload::Pages { vmo, len }) = &client_msg.payload else {
+            return Self::write_message(server_msg_uptr, client_msg, None);
+        };
+
+        // Don't map anything for a message, which is going to be rejected anyway
+        Self::check_receive(&read_ipc_message(server_msg_uptr)?, client_msg)?;
+
+        let task = current_task();
+        let vms = task.vms();
+        let va = vms.vm_map_vmo(None, vmo.clone(), MappingType::DATA).await?;
+        let mapped = unsafe { core::slice::from_raw_parts(va.to_raw::<u8>(), *len) };
+
+        let res = Self::write_message(server_msg_uptr, client_msg, Some(mapped));
+
+        if res.is_err() {
+            vms.vm_free(va, vmo.size()).await?;
+        }
+
+        res
+    }
+
+    fn check_receive(server_msg: &IpcMessage, client_msg: &KernelMessage) -> Result<(), ErrorType> {
+        if server_msg.handles().len() + client_msg.msg.handles().len() > IPC_MAX_HANDLES {
+            Err(ErrorType::InvalidArgument)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Writes `client_msg` into buffers of the receiver. Address space of the receiver must be
+    /// active and large payload must be already mapped at `mapped`
+    fn write_message(
         mut server_msg_uptr: UserPtr<IpcMessage<'static>>,
+        client_msg: &KernelMessage,
+        mapped: Option<&'static [u8]>,
     ) -> Result<usize, ErrorType> {
-        let mut server_msg = server_msg_uptr.read().ok_or(ErrorType::Fault)?;
-        let mut client_msg = self.try_consume().ok_or(ErrorType::WouldBlock)?;
+        let mut server_msg = read_ipc_message(server_msg_uptr)?;
         let mut arena_len = 0;
 
-        // Copy arena data
-        if let Some(d) = server_msg.in_arena() {
-            let mut ud = UserPtr::new_array(d.as_ptr(), d.len());
+        Self::check_receive(&server_msg, client_msg)?;
 
-            if let Some(d1) = client_msg.out_arena() {
-                arena_len = d1.len();
-                ud.write_array(d1)?;
-                unsafe { drop(Box::from_raw(d1)) };
+        // Copy arena data
+        match &client_msg.payload {
+            Some(Payload::Inline { data, len }) if !server_msg.in_iovecs().is_empty() => {
+                arena_len = *len;
+                scatter_to_user(&data[..*len], server_msg.in_iovecs())?;
+            }
+            Some(Payload::Heap(data)) if !server_msg.in_iovecs().is_empty() => {
+                arena_len = data.len();
+                scatter_to_user(data, server_msg.in_iovecs())?;
             }
+            Some(Payload::Pages { len, .. }) => {
+                arena_len = *len;
+                server_msg.set_mapped_arena(mapped.ok_or(ErrorType::InternalError)?);
+            }
+            _ => {}
         }
 
         // Prepare message
-        server_msg.set_reply_port(client_msg.reply_port());
-        server_msg.add_handles(client_msg.handles());
+        server_msg.set_reply_port(client_msg.msg.reply_port());
+        server_msg.add_handles(client_msg.msg.handles());
 
         // Commit it to userspace
         server_msg_uptr.write(&server_msg)?;
         Ok(arena_len)
     }
 
-    fn try_consume(&self) -> Option<IpcMessage<'static>> {
+    /// Writes message right into buffers of a blocked receiver and switches to it. Gives the
+    /// message back if there is no such receiver, payload has to be mapped, which may sleep, or
+    /// the receiver can't take it
+    fn try_deliver_direct(&self, msg: KernelMessage) -> Option<KernelMessage> {
+        if matches!(msg.payload, Some(Payload::Pages { .. })) {
+            return Some(msg);
+        }
+
+        let receiver = {
+            let mut receivers = self.receivers.lock();
+
+            // The latest receiver is the most likely to be cache hot
+            match receivers.iter().rposition(|x| x.claim()) {
+                Some(pos) => receivers.remove(pos),
+                None => return Some(msg),
+            }
+        };
+
+        let mut res = Err(ErrorType::Fault);
+
+        receiver.thread.task().with_attached_task(|| {
+            res = Self::write_message(receiver.msg, &msg, None);
+        });
+
+        let failed = res.is_err();
+
+        receiver.delivered.produce(res);
+
+        // Buffers of the receiver can't take the message. It's queued together with handles
+        // transferred with it, so the receiver can retry with proper ones
+        if failed {
+            return Some(msg);
+        }
+
+        handoff(&receiver.thread);
+        None
+    }
+
+    /// Hands message sent by user-space to a blocked receiver or queues it
+    fn send_message(&self, msg: KernelMessage) {
+        if let Some(msg) = self.try_deliver_direct(msg) {
+            self.produce(msg);
+        }
+    }
+
+    fn is_peer_closed(&self) -> bool {
+        self.signals().contains(Signal::PeerClosed.into())
+    }
+
+    /// Remembers reply port of a call, so it can be failed if this port is closed
+    fn add_call(&self, reply_port: &Arc<Self>) -> Result<(), ErrorType> {
+        let mut calls = self.calls.lock();
+
+        if self.closed.load(Ordering::Acquire) {
+            return Err(ErrorType::TaskDead);
+        }
+
+        calls.retain(|x| x.strong_count() != 0);
+        calls.try_push(Arc::downgrade(reply_port))
+    }
+
+    /// Called once the last handle allowed to receive from the port is gone. Fails all pending
+    /// calls and notifies anyone waiting for the port.
+    fn close(&self) {
+        let calls = {
+            let mut calls = self.calls.lock();
+
+            self.closed.store(true, Ordering::Release);
+            core::mem::take(&mut *calls)
+        };
+
+        // Nobody is going to receive queued messages
+        while self.queue.try_consume().is_some() {}
+
+        for reply_port in calls.iter().filter_map(|x| x.upgrade()) {
+            reply_port.signal_fire(Signal::PeerClosed.into());
+        }
+
+        self.signal_fire(Signal::PeerClosed.into());
+    }
+
+    fn try_consume(&self) -> Option<KernelMessage> {
         let msg = self.queue.try_consume()?;
 
         self.signal_clear(Signal::MessageReady.into());
         Some(msg)
     }
 
-    fn produce(&self, message: IpcMessage<'static>) {
+    fn produce(&self, message: KernelMessage) {
         self.queue.produce(message);
         self.signal_fire(Signal::MessageReady.into());
+
+        // Receiver is blocked waiting for the message, so switch to it directly
+        if let Some(receiver) = self.receivers.lock().last() {
+            handoff(&receiver.thread);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+    use test_macros::*;
+
+    /// Same layout as [`IpcMessage`], but with counts that can be set to anything
+    #[repr(C)]
+    #[derive(Default)]
+    struct RawMessage {
+        handles: [usize; IPC_MAX_HANDLES],
+        num_handles: usize,
+        in_iov: [[usize; 2]; IPC_MAX_IOVECS],
+        num_in_iov: usize,
+        out_iov: [[usize; 2]; IPC_MAX_IOVECS],
+        num_out_iov: usize,
+        mapped: [usize; 2],
+        reply_port: usize,
+    }
+
+    fn read_raw(raw: &RawMessage) -> Result<IpcMessage<'static>, ErrorType> {
+        read_ipc_message(UserPtr::new(
+            raw as *const RawMessage as *const IpcMessage<'static>,
+        ))
+    }
+
+    #[kernel_test]
+    fn ipc_message_counts() {
+        let mut raw = RawMessage::default();
+        raw.num_in_iov = IPC_MAX_IOVECS;
+        raw.num_out_iov = IPC_MAX_IOVECS;
+        test_assert!(read_raw(&raw).is_ok());
+
+        raw.num_in_iov = IPC_MAX_IOVECS + 1;
+        test_assert!(matches!(read_raw(&raw), Err(ErrorType::InvalidArgument)));
+
+        raw.num_in_iov = 0;
+        raw.num_out_iov = usize::MAX;
+        test_assert!(matches!(read_raw(&raw), Err(ErrorType::InvalidArgument)));
+
+        raw.num_out_iov = 0;
+        raw.num_handles = IPC_MAX_HANDLES + 1;
+        test_assert!(matches!(read_raw(&raw), Err(ErrorType::InvalidArgument)));
     }
 }
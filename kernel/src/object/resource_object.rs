@@ -0,0 +1,36 @@
+use crate::object::KernelObjectBase;
+use crate::object::capabilities::{Capability, CapabilityMask};
+use alloc::sync::Arc;
+use rtl::signal::Signal;
+use spin::Lazy;
+
+/// Grants access to privileged kernel features. Which ones is defined by rights of the handle.
+/// The first task gets the only handle with all rights and hands narrowed copies out.
+pub struct ResourceObject {
+    base: KernelObjectBase,
+}
+
+crate::kernel_object!(ResourceObject, Signal::None.into());
+
+static ROOT_RESOURCE: Lazy<Arc<ResourceObject>> = Lazy::new(|| {
+    Arc::try_new(ResourceObject {
+        base: KernelObjectBase::new(),
+    })
+    .expect("No memory for root resource")
+});
+
+impl ResourceObject {
+    pub fn root() -> Arc<Self> {
+        ROOT_RESOURCE.clone()
+    }
+
+    pub fn full_caps() -> CapabilityMask {
+        CapabilityMask::from(
+            Capability::RealTime
+                | Capability::MemInfo
+                | Capability::MapPhys
+                | Capability::Duplicate
+                | Capability::Transfer,
+        )
+    }
+}
@@ -0,0 +1,36 @@
+//! Kernel random numbers. Not cryptographically secure, only good enough to make addresses hard
+//! to guess
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use loader_protocol::LoaderArg;
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+static STATE: AtomicU64 = AtomicU64::new(0);
+static ASLR: AtomicBool = AtomicBool::new(false);
+
+pub fn init(arg: &LoaderArg) {
+    STATE.store(arg.rng_seed, Ordering::Relaxed);
+    ASLR.store(arg.aslr, Ordering::Relaxed);
+}
+
+/// SplitMix64 step. State is advanced atomically, so it's safe to call from any CPU
+pub fn next_u64() -> u64 {
+    let mut z = STATE
+        .fetch_add(GOLDEN_GAMMA, Ordering::Relaxed)
+        .wrapping_add(GOLDEN_GAMMA);
+
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Random number in `0..bound`
+pub fn next_below(bound: usize) -> usize {
+    (next_u64() % bound as u64) as usize
+}
+
+/// Whether placement of user mappings should be randomized
+pub fn aslr_enabled() -> bool {
+    ASLR.load(Ordering::Relaxed)
+}
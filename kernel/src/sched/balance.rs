@@ -0,0 +1,104 @@
+//! Load balancing between CPUs
+//!
+//! Run queue is owned by its CPU, so threads are pushed to other CPUs instead of being pulled.
+//! Thread is moved only when it's preempted: it does not wait for anything then, so wakers
+//! left in the old run queue are stale and ignored.
+
+use super::runtime::run_queue::{RunQueue, TaskRef};
+use super::runtime::task::Task;
+use crate::arch::cpuid::current_cpu;
+use crate::sched::kick;
+use crate::smp::{num_cpus, online_cpus};
+use crate::sync::{Spinlock, spinlock::SpinlockGuard};
+use crate::tasks::thread::Thread;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use rtl::error::ErrorType;
+
+percpu_global! {
+    // Tasks pushed to the CPU by other ones
+    static INCOMING: Spinlock<Vec<Arc<Task>>> = Spinlock::new(Vec::new());
+}
+
+percpu_global! {
+    // Runnable tasks of the CPU at the last scheduling decision
+    static LOAD: AtomicUsize = AtomicUsize::new(0);
+}
+
+fn load(cpu: usize) -> usize {
+    // SAFETY: only atomic operation is performed on other CPU's variable
+    unsafe { LOAD.cpu(cpu) }.load(Ordering::Relaxed)
+}
+
+pub fn set_load(load: usize) {
+    LOAD.per_cpu_var_get().store(load, Ordering::Relaxed);
+}
+
+/// Least loaded online CPU from `mask`
+pub fn idlest_cpu(mask: usize) -> Option<usize> {
+    let mask = mask & online_cpus();
+
+    (0..num_cpus())
+        .filter(|cpu| mask & (1 << cpu) != 0)
+        .min_by_key(|&cpu| load(cpu))
+}
+
+/// CPU preempted `thread` should move to. Thread stays, unless it's not allowed to run here or
+/// the move evens out the load
+fn target_cpu(thread: &Thread, local_load: usize) -> Option<usize> {
+    let cpu = current_cpu();
+    let target = idlest_cpu(thread.affinity()).filter(|&target| target != cpu)?;
+
+    (thread.affinity() & (1 << cpu) == 0 || local_load > load(target) + 1).then_some(target)
+}
+
+/// Locks incoming queue of `cpu` with room for one more task
+fn reserve(cpu: usize) -> Result<SpinlockGuard<'static, Vec<Arc<Task>>>, ErrorType> {
+    // SAFETY: incoming queue is protected by the lock
+    let mut incoming = unsafe { INCOMING.cpu(cpu) }.lock();
+
+    incoming.try_reserve(1).map_err(|_| ErrorType::NoMemory)?;
+
+    // SAFETY: only atomic operation is performed on other CPU's variable
+    unsafe { LOAD.cpu(cpu) }.fetch_add(1, Ordering::Relaxed);
+    Ok(incoming)
+}
+
+/// Queues task to be run on `cpu`
+pub fn push_task(cpu: usize, task: Arc<Task>) -> Result<(), ErrorType> {
+    reserve(cpu)?.push(task);
+    kick(cpu);
+    Ok(())
+}
+
+/// Moves preempted task to other CPU, if needed
+pub fn balance(rq: &mut RunQueue, task: &TaskRef) {
+    let Some(target) = target_cpu(task.task.thread(), rq.runnable()) else {
+        return;
+    };
+
+    if let Ok(mut incoming) = reserve(target) {
+        incoming.push(rq.remove(task.key).unwrap());
+        kick(target);
+    }
+}
+
+/// Other CPUs pushed tasks to the current one
+pub fn has_incoming() -> bool {
+    !INCOMING.per_cpu_var_get().lock().is_empty()
+}
+
+/// Adds tasks pushed by other CPUs to the run queue
+pub fn receive(rq: &mut RunQueue) {
+    let mut incoming = INCOMING.per_cpu_var_get().lock();
+
+    while let Some(task) = incoming.last() {
+        // Rest is picked up next time
+        if rq.insert(task).is_err() {
+            break;
+        }
+
+        incoming.pop();
+    }
+}
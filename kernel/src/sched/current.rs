@@ -1,3 +1,4 @@
+use crate::mm::vmm::vms::Vms;
 use crate::smp::percpu_ready;
 use crate::tasks::thread::Thread;
 use alloc::sync::Arc;
@@ -6,6 +7,11 @@ percpu_global!(
     static CURRENT: Option<Arc<Thread>> = None;
 );
 
+percpu_global!(
+    // Address space of other task temporary switched to. See Task::with_attached_task
+    static ATTACHED_VMS: Option<Arc<Vms>> = None;
+);
+
 // Using lazy_static! here, since LazyCell cannot be shared between threads...
 lazy_static::lazy_static! {
     static ref DUMMY: Arc<Thread> = Thread::initial().unwrap();
@@ -26,3 +32,19 @@ pub fn get_current_raw() -> Option<*const Thread> {
 pub fn set_current(cur: Arc<Thread>) {
     *CURRENT.per_cpu_var_get_mut() = Some(cur);
 }
+
+/// Called once executor stops polling the current thread
+pub fn clear_current() {
+    // Thread may be dropped here, it must not be seen as current at that point
+    let old = CURRENT.per_cpu_var_get_mut().take();
+
+    drop(old);
+}
+
+pub fn get_attached_vms() -> Option<Arc<Vms>> {
+    ATTACHED_VMS.per_cpu_var_get().clone()
+}
+
+pub fn set_attached_vms(vms: Option<Arc<Vms>>) {
+    *ATTACHED_VMS.per_cpu_var_get_mut() = vms;
+}
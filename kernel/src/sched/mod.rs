@@ -1,14 +1,21 @@
-use crate::arch::regs::{Context, TrapReason};
+use crate::arch::cpuid::current_cpu;
+use crate::arch::irq::interrupts::{get_flags, set_flags, wait_for_interrupt};
+use crate::arch::regs::{Context, PageFault, TrapReason};
 use crate::drivers::irq::irq_dispatch;
+use crate::mm::vmm::vms::Vms;
+use crate::smp::ipi::{Ipi, send_ipi};
 use crate::syscalls::do_syscall;
 use crate::tasks::task::Task;
 use crate::tasks::thread::Thread;
-use aarch64_cpu::registers::{ELR_EL1, ESR_EL1, FAR_EL1, Readable};
+use aarch64_cpu::registers::{ESR_EL1, FAR_EL1, Readable};
 use alloc::sync::Arc;
 use core::cell::LazyCell;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering, fence};
+use hal::address::{Address, VirtAddr};
 use rtl::error::ErrorType;
 use runtime::executor::Executor;
 
+pub mod balance;
 pub mod current;
 pub mod runtime;
 pub mod ticks;
@@ -29,6 +36,11 @@ pub fn current_task() -> Arc<Task> {
     current().task()
 }
 
+/// Address space user addresses are resolved against on this CPU
+pub fn current_vms() -> Arc<Vms> {
+    crate::sched::current::get_attached_vms().unwrap_or_else(|| current_task().vms().clone())
+}
+
 pub struct Scheduler {
     rq: Executor,
 }
@@ -37,6 +49,21 @@ percpu_global!(
     pub static SCHEDULER: LazyCell<Scheduler> = LazyCell::new(Scheduler::new);
 );
 
+percpu_global! {
+    // Run queue key of the task to be polled next. 0 means no hand off is pending
+    static HANDOFF: AtomicU32 = AtomicU32::new(0);
+}
+
+percpu_global! {
+    // Highest level of threads woken up since the last preemption check
+    static PREEMPT: AtomicUsize = AtomicUsize::new(0);
+}
+
+percpu_global! {
+    // Executor of the CPU has nothing to run and waits for an interrupt
+    static IDLE: AtomicBool = AtomicBool::new(false);
+}
+
 impl Scheduler {
     pub fn new() -> Self {
         Self {
@@ -52,12 +79,76 @@ pub fn spawn<F: Future<Output = ()> + 'static>(
     SCHEDULER.per_cpu_var_get_mut().rq.add(future, thread)
 }
 
+/// Asks executor to run `thread` right after the current one. It's a hint: `thread` still has to
+/// be woken up as usual.
+pub fn handoff(thread: &Arc<Thread>) {
+    if let Some((cpu, key)) = thread.rq_slot()
+        && cpu == current_cpu()
+    {
+        HANDOFF.per_cpu_var_get().store(key, Ordering::Relaxed);
+    }
+}
+
+pub(crate) fn take_handoff() -> Option<u32> {
+    match HANDOFF.per_cpu_var_get().swap(0, Ordering::Relaxed) {
+        0 => None,
+        key => Some(key),
+    }
+}
+
+/// Asks `cpu` to preempt its current thread, if it runs at lower level than `level`
+pub(crate) fn request_preempt(cpu: usize, level: usize) {
+    // SAFETY: only atomic operation is performed on other CPU's variable
+    unsafe { PREEMPT.cpu(cpu) }.fetch_max(level, Ordering::Relaxed);
+
+    // Current CPU checks the request on the next trap
+    if cpu != current_cpu() {
+        send_ipi(cpu, Ipi::Reschedule);
+    }
+}
+
+/// Wakes `cpu` up, if it's idle. Called after work was queued for it
+pub(crate) fn kick(cpu: usize) {
+    // Pairs with the fence in idle()
+    fence(Ordering::SeqCst);
+
+    // SAFETY: only atomic operation is performed on other CPU's variable
+    if cpu != current_cpu() && unsafe { IDLE.cpu(cpu) }.load(Ordering::Relaxed) {
+        send_ipi(cpu, Ipi::Reschedule);
+    }
+}
+
+/// Waits for an interrupt, unless `has_work` finds something to run
+pub(crate) fn idle<F: FnOnce() -> bool>(has_work: F) {
+    let flags = get_flags();
+    let idle = IDLE.per_cpu_var_get();
+
+    // Interrupt, which arrives after the check, stays pending and ends the wait
+    arm_gic::irq_disable();
+    idle.store(true, Ordering::Relaxed);
+    fence(Ordering::SeqCst);
+
+    if !has_work() {
+        wait_for_interrupt();
+    }
+
+    idle.store(false, Ordering::Relaxed);
+    unsafe { set_flags(flags) };
+}
+
+fn take_preempt() -> usize {
+    PREEMPT.per_cpu_var_get().swap(0, Ordering::Relaxed)
+}
+
 pub fn run() {
     SCHEDULER.per_cpu_var_get_mut().rq.run();
 }
 
+/// Exit code of the task killed by unresolved page fault
+const USER_FAULT_EXIT_CODE: usize = usize::MAX;
+
 pub async fn userspace_loop(thread: Arc<Thread>) {
-    loop {
+    while !thread.is_dead() {
         // Wait for thread to become running
         let mut ctx = thread.context().await;
 
@@ -81,16 +172,54 @@ pub async fn userspace_loop(thread: Arc<Thread>) {
                 ctx.finish_syscall(res);
             }
             TrapReason::Irq => irq_dispatch(),
-            _ => todo!(
-                "{:?} 0x{:x} 0x{:x} 0x{:x}",
-                ctx,
-                ELR_EL1.get(),
-                ESR_EL1.get(),
-                FAR_EL1.get()
-            ),
+            TrapReason::PageFault => {
+                // Fault registers are overwritten, while waiting for the pager
+                let (esr, far) = (ESR_EL1.get(), FAR_EL1.get());
+                let res = match PageFault::current() {
+                    Some(fault) => {
+                        thread
+                            .task()
+                            .vms()
+                            .resolve_fault(fault.addr, fault.access)
+                            .await
+                    }
+                    None => Err(ErrorType::Fault),
+                };
+
+                if let Err(err) = res {
+                    let task = thread.task();
+
+                    if task.vms().is_guard(VirtAddr::from_bits(far as usize)) {
+                        error!(
+                            "Stack overflow in '{}' thread {}: ELR 0x{:x} FAR 0x{:x}\n",
+                            task.name(),
+                            thread.id(),
+                            ctx.elr,
+                            far
+                        );
+                    } else {
+                        error!(
+                            "Unhandled user fault in '{}': {:?} ELR 0x{:x} ESR 0x{:x} FAR 0x{:x}\n",
+                            task.name(),
+                            err,
+                            ctx.elr,
+                            esr,
+                            far
+                        );
+                    }
+
+                    // Faulting thread is killed by termination, so it won't return to user-space
+                    let _ = task.terminate(USER_FAULT_EXIT_CODE).await;
+                }
+            }
         }
 
         // Update context if needed
         thread.update_context(ctx);
+
+        // Real-time thread woken up by the trap should not wait for the end of time slice
+        if take_preempt() > thread.sched_level() {
+            thread.preempt();
+        }
     }
 }
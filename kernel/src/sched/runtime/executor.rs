@@ -1,5 +1,8 @@
 use super::run_queue::RunQueue;
 use super::task::Task;
+use crate::arch::cpuid::current_cpu;
+use crate::sched::balance::{balance, has_incoming, idlest_cpu, push_task, receive, set_load};
+use crate::sched::idle;
 use crate::tasks::thread::Thread;
 use alloc::sync::Arc;
 use core::task::{Context, Poll};
@@ -21,29 +24,65 @@ impl Executor {
         future: F,
         thread: Arc<Thread>,
     ) -> Result<(), ErrorType> {
-        self.rq.add(Task::new(future, thread)?)
+        let affinity = thread.affinity();
+        let task = Arc::try_new(Task::new(future, thread)?).map_err(|_| ErrorType::NoMemory)?;
+
+        // Thread is not allowed to run here
+        if affinity & (1 << current_cpu()) == 0
+            && let Some(cpu) = idlest_cpu(affinity)
+        {
+            return push_task(cpu, task);
+        }
+
+        self.rq.insert(&task)
     }
 
     pub fn run(&mut self) {
-        use crate::sched::current::set_current;
+        use crate::sched::current::{clear_current, set_current};
 
-        for task_ref in self.rq.tasks() {
+        loop {
+            receive(&mut self.rq);
+
+            let Some(task_ref) = self.rq.next() else {
+                set_load(0);
+                idle(|| self.rq.runnable() != 0 || has_incoming());
+                continue;
+            };
             let mut ctx = Context::from_waker(&task_ref.waker);
             let thread = task_ref.task.thread();
 
+            // Task being polled counts as well
+            set_load(self.rq.runnable() + 1);
+
             // info!("Switching to '{}'\n", thread.task().name());
+            // Killed thread must not return to the user-space
+            if !thread.enter_cpu(current_cpu()) {
+                task_ref.task.cancel();
+                self.rq.remove(task_ref.key);
+                continue;
+            }
+
             set_current(thread.clone());
+            thread.set_waker(task_ref.waker.clone());
             thread.task().vms().switch_to();
 
             match task_ref.task.poll(&mut ctx) {
-                Poll::Ready(_) => {}
+                Poll::Ready(_) => {
+                    self.rq.remove(task_ref.key);
+                }
                 Poll::Pending => {
-                    // Noting to do
+                    if thread.switched_out() {
+                        balance(&mut self.rq, &task_ref);
+                    }
                 }
             }
 
             // Thread should not return to executor with disabled preemption
             assert!(thread.is_preemtion_enabled());
+            thread.leave_cpu();
+
+            // Thread may run on other CPU from now on
+            clear_current();
         }
     }
 }
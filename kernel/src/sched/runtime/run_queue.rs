@@ -1,69 +1,141 @@
 use super::task::Task;
 use super::waker::WakerPage;
+use crate::arch::cpuid::current_cpu;
+use crate::sched::take_handoff;
 use adt::Vec;
 use alloc::sync::Arc;
-use core::num::NonZeroU32;
+use core::cmp::Reverse;
 use core::task::Waker;
 use rtl::error::ErrorType;
-use slab::{Key as SlabKey, Slab};
 
 pub struct RunQueue {
-    slab: Slab<Arc<Task>>,
+    // Indexed by task key. Key 0 is never used, so it could mean no task
+    tasks: Vec<Option<Arc<Task>>>,
     wakers: Vec<Arc<WakerPage>>,
+    // Key of the task polled last. Tasks of the same level are picked round-robin after it
+    last: u32,
 }
 
 pub struct TaskRef {
     pub task: Arc<Task>,
     pub waker: Waker,
+    pub key: u32,
 }
 
 impl RunQueue {
     pub fn new() -> Self {
         Self {
-            slab: Slab::new(),
+            tasks: Vec::new(),
             wakers: Vec::new(),
+            last: 0,
         }
     }
 
     pub fn add(&mut self, t: Task) -> Result<(), ErrorType> {
-        let new = Arc::try_new(t)
-            .map_err(|_| ErrorType::NoMemory)
-            .and_then(|x| self.slab.add(x).ok_or(ErrorType::NoMemory))?;
-        let key = RQKey(unsafe { new.into_inner().into() });
-
-        if key.0 as usize >= self.wakers.len() * WakerPage::num_entries() {
-            self.wakers
-                .try_push(Arc::try_new(WakerPage::new()).map_err(|_| ErrorType::NoMemory)?)?;
+        self.insert(&Arc::try_new(t).map_err(|_| ErrorType::NoMemory)?)
+    }
+
+    /// Inserts task, possibly coming from other CPU. It's polled as soon as possible
+    pub fn insert(&mut self, task: &Arc<Task>) -> Result<(), ErrorType> {
+        if self.tasks.is_empty() {
+            self.tasks.try_push(None)?;
+        }
+
+        let key = match self.tasks.iter().skip(1).position(Option::is_none) {
+            Some(pos) => pos + 1,
+            None => {
+                self.tasks.try_push(None)?;
+                self.tasks.len() - 1
+            }
+        };
+        let key = RQKey(key as u32);
+
+        if key.waker() as usize >= self.wakers.len() {
+            self.wakers.try_push(
+                Arc::try_new(WakerPage::new(current_cpu())).map_err(|_| ErrorType::NoMemory)?,
+            )?;
         }
 
+        task.thread().set_rq_slot(current_cpu(), key.task());
+        self.tasks[key.task() as usize] = Some(task.clone());
         self.wakers[key.waker() as usize].initialize(key.waker_index());
         Ok(())
     }
 
-    pub fn tasks(&mut self) -> impl Iterator<Item = TaskRef> {
-        core::iter::from_coroutine(
-            #[coroutine]
-            || loop {
-                for (i, page) in self.wakers.iter().enumerate() {
-                    for notified in page.notified() {
-                        let task_idx = unsafe {
-                            SlabKey::from_u32(
-                                NonZeroU32::new(
-                                    (i * WakerPage::num_entries() + notified as usize) as u32,
-                                )
-                                .unwrap(),
-                            )
-                        };
-
-                        let task = self.slab.get(&task_idx).unwrap();
-                        yield TaskRef {
-                            task: task.clone(),
-                            waker: page.waker(notified),
-                        };
-                    }
-                }
-            },
-        )
+    /// Removes task from the run queue. Wakers created for it are ignored from now on
+    pub fn remove(&mut self, key: u32) -> Option<Arc<Task>> {
+        let task = self.tasks.get_mut(key as usize)?.take()?;
+        let key = RQKey(key);
+
+        self.wakers[key.waker() as usize].take(key.waker_index());
+        task.thread().clear_rq_slot();
+        Some(task)
+    }
+
+    /// Number of notified tasks
+    pub fn runnable(&self) -> usize {
+        self.wakers
+            .iter()
+            .map(|page| page.notified().count_ones() as usize)
+            .sum()
+    }
+
+    fn task_ref(&self, key: u32) -> Option<TaskRef> {
+        let task = self.tasks.get(key as usize)?.clone()?;
+        let level = task.thread().sched_level();
+        let key = RQKey(key);
+
+        Some(TaskRef {
+            task,
+            waker: self.wakers[key.waker() as usize].waker(key.waker_index(), level),
+            key: key.task(),
+        })
+    }
+
+    /// Picks notified task of the highest level. Tasks of the same level are picked in
+    /// round-robin order
+    fn pick(&mut self) -> Option<u32> {
+        let total = (self.wakers.len() * WakerPage::num_entries()) as u32;
+        let notified = self.wakers.iter().enumerate().flat_map(|(i, page)| {
+            let mut mask = page.notified();
+
+            core::iter::from_fn(move || {
+                let bit = (mask != 0).then(|| mask.trailing_zeros())?;
+
+                mask &= mask - 1;
+                Some((i * WakerPage::num_entries()) as u32 + bit)
+            })
+        });
+        let (key, _) = notified
+            .filter_map(|key| {
+                let Some(task) = self.tasks.get(key as usize).and_then(Option::as_ref) else {
+                    // Stale waker of the removed task
+                    let key = RQKey(key);
+
+                    self.wakers[key.waker() as usize].take(key.waker_index());
+                    return None;
+                };
+
+                Some((key, task.thread().sched_level()))
+            })
+            .max_by_key(|&(key, level)| (level, Reverse((key + total - self.last - 1) % total)))?;
+        let key = RQKey(key);
+
+        self.wakers[key.waker() as usize].take(key.waker_index());
+        self.last = key.task();
+        Some(key.task())
+    }
+
+    /// Next task to poll. Task that just ran could hand the CPU off to another one, it's
+    /// switched to straight away instead of waiting for the next pick.
+    pub fn next(&mut self) -> Option<TaskRef> {
+        if let Some(task_ref) = take_handoff().and_then(|key| self.task_ref(key)) {
+            return Some(task_ref);
+        }
+
+        let key = self.pick()?;
+
+        self.task_ref(key)
     }
 }
 
@@ -8,7 +8,7 @@ use core::task::{Context, Poll};
 use rtl::error::ErrorType;
 
 pub struct Task {
-    future: Spinlock<Pin<Box<dyn Future<Output = ()>>>>,
+    future: Spinlock<Option<Pin<Box<dyn Future<Output = ()>>>>>,
     thread: Arc<Thread>,
 }
 
@@ -18,15 +18,34 @@ impl Task {
         thread: Arc<Thread>,
     ) -> Result<Self, ErrorType> {
         Ok(Self {
-            future: Spinlock::new(Box::into_pin(
+            future: Spinlock::new(Some(Box::into_pin(
                 Box::try_new(future).map_err(|_| ErrorType::NoMemory)?,
-            )),
+            ))),
             thread,
         })
     }
 
     pub fn poll(&self, ctx: &mut Context) -> Poll<()> {
-        self.future.lock().as_mut().poll(ctx)
+        let mut future = self.future.lock();
+
+        let res = match future.as_mut() {
+            Some(f) => f.as_mut().poll(ctx),
+            None => Poll::Ready(()),
+        };
+
+        // Future must not be polled after completion
+        if res.is_ready() {
+            *future = None;
+        }
+
+        res
+    }
+
+    /// Drops the future without polling it to completion
+    pub fn cancel(&self) {
+        let future = self.future.lock().take();
+
+        drop(future);
     }
 
     pub fn thread(&self) -> &Arc<Thread> {
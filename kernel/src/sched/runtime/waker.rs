@@ -1,6 +1,7 @@
 use core::sync::atomic::{AtomicU64, Ordering};
 use core::task::{RawWaker, RawWakerVTable, Waker as CoreWaker};
 
+use crate::sched::{kick, request_preempt};
 use alloc::sync::Arc;
 
 static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake_by_ref, drop_waker);
@@ -36,6 +37,8 @@ unsafe fn drop_waker(data: *const ()) {
 pub struct Waker {
     page: Arc<WakerPage>,
     index: u8,
+    // Scheduling level of the thread at the moment waker was created
+    level: usize,
 }
 
 impl Waker {
@@ -43,17 +46,27 @@ impl Waker {
         self.page
             .notified
             .fetch_or(1u64 << self.index, Ordering::Relaxed);
+
+        // Real-time thread should not wait until current one runs out of its time slice
+        if self.level != 0 {
+            request_preempt(self.page.cpu, self.level);
+        } else {
+            kick(self.page.cpu);
+        }
     }
 }
 
 pub struct WakerPage {
     notified: AtomicU64,
+    // CPU owning the run queue
+    cpu: usize,
 }
 
 impl WakerPage {
-    pub fn new() -> Self {
+    pub fn new(cpu: usize) -> Self {
         Self {
             notified: AtomicU64::new(0),
+            cpu,
         }
     }
 
@@ -70,30 +83,21 @@ impl WakerPage {
         64
     }
 
-    pub fn notified(&self) -> impl Iterator<Item = u8> {
-        let mask = self.notified.swap(0, Ordering::Acquire);
-        let mut bit = 0;
-
-        core::iter::from_fn(move || {
-            while bit < Self::num_entries() && mask & (1u64 << bit) == 0 {
-                bit += 1;
-            }
-
-            if bit < Self::num_entries() {
-                let res = bit;
+    /// Mask of notified tasks
+    pub fn notified(&self) -> u64 {
+        self.notified.load(Ordering::Acquire)
+    }
 
-                bit += 1;
-                Some(res as u8)
-            } else {
-                None
-            }
-        })
+    /// Clears notification of the task before it's polled
+    pub fn take(&self, task: u8) {
+        self.notified.fetch_and(!(1 << task), Ordering::Acquire);
     }
 
-    pub fn waker(self: &Arc<Self>, index: u8) -> CoreWaker {
+    pub fn waker(self: &Arc<Self>, index: u8, level: usize) -> CoreWaker {
         let arc = Arc::new(Waker {
             page: self.clone(),
             index,
+            level,
         });
 
         let raw = RawWaker::new(Arc::into_raw(arc) as *const _, &VTABLE);
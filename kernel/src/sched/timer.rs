@@ -1,10 +1,15 @@
 use super::ticks::SYSTEM_TICK;
 use super::ticks::{sched_ticks, SchedTicks};
+use crate::arch::cpuid::current_cpu;
 use crate::sync::Spinlock;
 use alloc::boxed::Box;
 use alloc::collections::LinkedList;
+use core::future::{Future, pending, poll_fn};
+use core::pin::{Pin, pin};
 use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{Context, Poll};
 use core::time::Duration;
+use rtl::error::ErrorType;
 
 struct Timer {
     cb: Box<dyn Fn() + Send>,
@@ -98,8 +103,9 @@ impl TimerQueue {
         let current_tick = sched_ticks();
         let mut cursor = self.queue.cursor_front_mut();
 
+        // Timer may be set by other CPU right before the tick, with deadline already passed
         while let Some(cur) = cursor.current()
-            && cur.dl == current_tick
+            && cur.dl <= current_tick
         {
             // SAFETY: cursor points to element because of while check
             let cur = unsafe { cursor.remove_current().unwrap_unchecked() };
@@ -119,7 +125,90 @@ pub fn set_timer(dl: Duration, cb: Box<dyn Fn() + Send>) -> TimerHandle {
     TIMER_QUEUE.lock_irqsave().set_timer(dl, cb)
 }
 
+/// Sleeps until `deadline` (time since start) is reached
+pub async fn sleep_until(deadline: Duration) -> Result<(), ErrorType> {
+    struct Sleep {
+        dl: Duration,
+        timer: Option<TimerHandle>,
+    }
+
+    impl Drop for Sleep {
+        fn drop(&mut self) {
+            if let Some(timer) = self.timer.take() {
+                timer.cancel();
+            }
+        }
+    }
+
+    impl Future for Sleep {
+        type Output = Result<(), ErrorType>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let now = time_since_start();
+
+            if now >= self.dl {
+                return Poll::Ready(Ok(()));
+            }
+
+            // Timer has tick granularity, so it may fire a bit early. Re-arm it for the rest
+            if let Some(timer) = self.timer.take() {
+                timer.cancel();
+            }
+
+            let waker = cx.waker().clone();
+            self.timer = Some(set_timer(
+                self.dl - now,
+                Box::try_new(move || waker.wake_by_ref()).map_err(|_| ErrorType::NoMemory)?,
+            ));
+
+            Poll::Pending
+        }
+    }
+
+    Sleep {
+        dl: deadline,
+        timer: None,
+    }
+    .await
+}
+
+/// Runs `fut` until `deadline` (time since start, `None` means no deadline). Fails with
+/// [`ErrorType::WouldBlock`] if `fut` is not ready and deadline has already passed, and with
+/// [`ErrorType::TimedOut`] if deadline has passed while waiting.
+pub async fn with_deadline<T, F: Future<Output = Result<T, ErrorType>>>(
+    deadline: Option<Duration>,
+    fut: F,
+) -> Result<T, ErrorType> {
+    let mut fut = pin!(fut);
+    let mut sleep = pin!(async {
+        match deadline {
+            Some(deadline) => sleep_until(deadline).await,
+            None => pending().await,
+        }
+    });
+    let mut first_poll = true;
+
+    poll_fn(|cx| {
+        if let Poll::Ready(res) = fut.as_mut().poll(cx) {
+            return Poll::Ready(res);
+        }
+
+        let first = core::mem::replace(&mut first_poll, false);
+
+        match sleep.as_mut().poll(cx) {
+            Poll::Ready(Ok(())) if first => Poll::Ready(Err(ErrorType::WouldBlock)),
+            Poll::Ready(res) => Poll::Ready(res.and(Err(ErrorType::TimedOut))),
+            Poll::Pending => Poll::Pending,
+        }
+    })
+    .await
+}
+
 pub fn sched_tick() {
     super::current().tick();
-    TIMER_QUEUE.lock_irqsave().on_sched_tick()
+
+    // Timers are fired by the boot CPU, which advances ticks
+    if current_cpu() == 0 {
+        TIMER_QUEUE.lock_irqsave().on_sched_tick()
+    }
 }
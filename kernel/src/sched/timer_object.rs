@@ -1,5 +1,6 @@
 use super::timer::{set_timer, TimerHandle};
 use crate::object::KernelObjectBase;
+use crate::object::capabilities::{Capability, CapabilityMask};
 use alloc::boxed::Box;
 use alloc::sync::Arc;
 use core::time::Duration;
@@ -25,6 +26,12 @@ impl TimerObject {
         Ok(res)
     }
 
+    pub fn full_caps() -> CapabilityMask {
+        CapabilityMask::from(
+            Capability::Wait | Capability::Write | Capability::Duplicate | Capability::Transfer,
+        )
+    }
+
     pub fn arm(self: Arc<Self>, dl: Duration) -> Result<(), ErrorType> {
         let clone = self.clone();
         let mut handle = self.handle.lock();
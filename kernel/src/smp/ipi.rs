@@ -0,0 +1,44 @@
+//! Inter-processor interrupts. Each kind of IPI is a separate SGI
+
+use crate::arch::mm::tlb::handle_shootdown;
+use crate::drivers::irq::{IntId, enable_private, register_handler, send_sgi};
+use rtl::error::ErrorType;
+use rtl::irq::IrqTrigger;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Ipi {
+    /// Wakes idle CPU up, and makes busy one check preemption requests. Interrupt itself does
+    /// the job, so handler is empty
+    Reschedule = 0,
+    /// Makes CPU serve pending TLB shootdown
+    TlbShootdown = 1,
+}
+
+const IPIS: [Ipi; 2] = [Ipi::Reschedule, Ipi::TlbShootdown];
+
+impl Ipi {
+    fn id(self) -> IntId {
+        IntId::sgi(self as u32)
+    }
+}
+
+/// Registers IPI handlers. SGIs are enabled on the current CPU
+pub fn init() -> Result<(), ErrorType> {
+    register_handler(Ipi::Reschedule.id(), |_| {}, IrqTrigger::Edge)?;
+    register_handler(
+        Ipi::TlbShootdown.id(),
+        |_| handle_shootdown(),
+        IrqTrigger::Edge,
+    )
+}
+
+/// Enables IPIs on secondary CPU
+pub fn init_secondary() {
+    for ipi in IPIS {
+        enable_private(ipi.id(), IrqTrigger::Edge);
+    }
+}
+
+pub fn send_ipi(cpu: usize, ipi: Ipi) {
+    send_sgi(cpu, ipi.id());
+}
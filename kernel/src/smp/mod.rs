@@ -1,16 +1,29 @@
 use crate::{
     arch::cpuid::current_cpu,
     mm::{pmm::page_alloc::page_allocator, vmm::layout::vmm_range},
+    sched::timer::time_since_start,
 };
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::time::Duration;
 
 use hal::address::*;
 use hal::arch::PAGE_SIZE;
+use loader_protocol::LoaderArg;
 use rtl::linker_var;
 use spin::Once;
 
-// TODO: W/A. it should be read from dtb
-const NUM_CPUS: usize = 2;
+pub mod ipi;
+
+/// Upper bound of CPU count. Sizes per-CPU arrays
+pub use loader_protocol::MAX_CPUS;
+
+const SECONDARY_STACK_SIZE: usize = 0x50000;
+const SECONDARY_START_TIMEOUT: Duration = Duration::from_secs(1);
+
+static NUM_CPUS: AtomicUsize = AtomicUsize::new(1);
+
+// CPUs running their executors, one bit per CPU
+static ONLINE: AtomicUsize = AtomicUsize::new(1);
 
 unsafe extern "C" {
     static sdatapercpu: usize;
@@ -122,7 +135,7 @@ impl<T> PerCpu<T> {
     // only by owner cpu. IOW caller takes care of synchronization and possible side-effects
     #[allow(dead_code)]
     pub unsafe fn for_each_cpu<F: Fn(&T)>(&self, visiter: F) {
-        for i in 0..NUM_CPUS {
+        for i in 0..num_cpus() {
             visiter(percpu_n!(self.data, i));
         }
     }
@@ -139,12 +152,27 @@ pub fn percpu_ready() -> bool {
     READY.load(Ordering::Relaxed)
 }
 
-pub fn init_percpu() -> Option<()> {
+/// Number of CPUs found in FDT
+pub fn num_cpus() -> usize {
+    NUM_CPUS.load(Ordering::Relaxed)
+}
+
+/// Mask of CPUs running their executors
+pub fn online_cpus() -> usize {
+    ONLINE.load(Ordering::Acquire)
+}
+
+pub fn set_online() {
+    ONLINE.fetch_or(1 << current_cpu(), Ordering::Release);
+}
+
+pub fn init_percpu(arg: &LoaderArg) -> Option<()> {
     let per_cpu_size = linker_var!(edatapercpu) - linker_var!(sdatapercpu);
 
     assert!(per_cpu_size % PAGE_SIZE == 0);
+    NUM_CPUS.store(arg.cpus.len().max(1), Ordering::Relaxed);
 
-    let pages = (per_cpu_size / PAGE_SIZE) * NUM_CPUS;
+    let pages = (per_cpu_size / PAGE_SIZE) * num_cpus();
     let pa = page_allocator()
         .alloc_contigious(pages)
         .expect("Failed to allocate memory for per-cpu");
@@ -156,7 +184,7 @@ pub fn init_percpu() -> Option<()> {
     let range = vmm_range(loader_protocol::VmmLayoutKind::PerCpu);
     debug_assert!(range.size() >= per_cpu_size);
 
-    for i in 0..NUM_CPUS {
+    for i in 0..num_cpus() {
         let p = pa + per_cpu_size * i;
 
         unsafe {
@@ -171,3 +199,39 @@ pub fn init_percpu() -> Option<()> {
     READY.store(true, Ordering::Relaxed);
     Some(())
 }
+
+/// Starts secondary CPUs. Failure to start one is not fatal, system runs on the rest
+pub fn start_secondaries(arg: &LoaderArg) {
+    ipi::init().expect("Failed to register IPI handlers");
+
+    for cpu in 1..num_cpus() {
+        let pages = SECONDARY_STACK_SIZE / PAGE_SIZE;
+        let Some(stack) = page_allocator().alloc_contigious(pages) else {
+            error!("No memory for stack of CPU {cpu}\n");
+            continue;
+        };
+        let boot = page_allocator()
+            .alloc_contigious(1)
+            .expect("Failed to allocate secondary boot page");
+        let stack_top = VirtAddr::from_bits(LinearAddr::from(stack).bits() + SECONDARY_STACK_SIZE);
+
+        if crate::arch::smp::start_cpu(arg, cpu, stack_top, LinearAddr::from(boot)).is_err() {
+            page_allocator().free_contig(stack, pages);
+            page_allocator().free_contig(boot, 1);
+            continue;
+        }
+
+        let deadline = time_since_start() + SECONDARY_START_TIMEOUT;
+
+        while online_cpus() & (1 << cpu) == 0 && time_since_start() < deadline {
+            core::hint::spin_loop();
+        }
+
+        // CPU which did not come up may still read boot page, so it's leaked
+        if online_cpus() & (1 << cpu) != 0 {
+            page_allocator().free_contig(boot, 1);
+        } else {
+            error!("CPU {cpu} did not come online\n");
+        }
+    }
+}
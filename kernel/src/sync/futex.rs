@@ -0,0 +1,208 @@
+//! Futex: wait queues keyed by physical address of a user-space word.
+//!
+//! Key is physical address, so tasks sharing memory can synchronize through the same word mapped
+//! at different virtual addresses.
+
+use super::{Event, Spinlock};
+use crate::mm::user_buffer::UserPtr;
+use crate::sched::current_task;
+use crate::sched::timer::with_deadline;
+use adt::Vec;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::time::Duration;
+use hal::address::{Address, LinearAddr, PhysAddr, VirtAddr, VirtualAddress};
+use hal::arch::PAGE_SIZE;
+use rtl::error::ErrorType;
+use rtl::vmm::MappingType;
+
+struct Futex {
+    // Waiters in order of arrival, which are not woken up yet. Each one has its own event, so
+    // wake up can't be taken by another waiter
+    waiters: Vec<Arc<Event>>,
+}
+
+impl Futex {
+    /// Wakes up the oldest waiter. Returns false, if there is none
+    fn wake_one(&mut self) -> bool {
+        if self.waiters.is_empty() {
+            return false;
+        }
+
+        self.waiters.remove(0).broadcast();
+        true
+    }
+
+    /// Removes waiter with `event`. Returns false, if it was woken up already
+    fn unregister(&mut self, event: &Arc<Event>) -> bool {
+        match self.waiters.iter().position(|x| Arc::ptr_eq(x, event)) {
+            Some(pos) => {
+                self.waiters.remove(pos);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Lock order is the map first, then the futex. Entry is removed only with both locks held, once
+// it has no waiters, so futex found in the map under both locks is never stale
+static FUTEXES: Spinlock<BTreeMap<usize, Arc<Spinlock<Futex>>>> = Spinlock::new(BTreeMap::new());
+
+async fn futex_key(va: VirtAddr) -> Result<usize, ErrorType> {
+    if va.bits() % core::mem::size_of::<u32>() != 0 {
+        return Err(ErrorType::InvalidArgument);
+    }
+
+    let task = current_task();
+    let vms = task.vms();
+
+    // Word may be never touched yet, so commit its page to get stable physical address. Write
+    // access breaks copy-on-write sharing, otherwise the page would change on the first write.
+    // Read-only mapping is never written through, so read access is enough there
+    match vms.resolve_fault(va, MappingType::DATA).await {
+        Err(ErrorType::AccessDenied) => vms.resolve_fault(va, MappingType::RODATA).await?,
+        res => res?,
+    }
+
+    let pa = vms.translate(va).await.ok_or(ErrorType::Fault)?;
+
+    Ok(pa.bits() + va.bits() % PAGE_SIZE)
+}
+
+/// Reads the word of `key` through the linear map, which never faults, so it can be done under
+/// a spinlock
+fn read_key(key: usize) -> u32 {
+    let addr = LinearAddr::from(PhysAddr::from(key));
+
+    unsafe { (*addr.to_raw::<AtomicU32>()).load(Ordering::Acquire) }
+}
+
+/// Removes entry of `key`, if it has no waiters left
+fn remove_unused(futexes: &mut BTreeMap<usize, Arc<Spinlock<Futex>>>, key: usize) {
+    let unused = futexes
+        .get(&key)
+        .is_some_and(|futex| futex.lock().waiters.is_empty());
+
+    if unused {
+        futexes.remove(&key);
+    }
+}
+
+/// Registered waiter. Unregisters itself, if it gives up waiting before being woken up, and
+/// passes the wake up on, if it gives up after it
+struct Waiter {
+    key: usize,
+    event: Arc<Event>,
+    done: bool,
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+
+        let mut futexes = FUTEXES.lock();
+
+        // Woken waiter could have been the last one, then there is nobody to pass wake up to
+        if let Some(futex) = futexes.get(&self.key) {
+            let mut futex = futex.lock();
+
+            // Wake up raced with timeout or cancellation
+            if !futex.unregister(&self.event) {
+                futex.wake_one();
+            }
+        }
+
+        remove_unused(&mut futexes, self.key);
+    }
+}
+
+/// Blocks current thread while word at `va` equals to `expected`. Fails with
+/// [`ErrorType::TryAgain`] if value is different. See [`with_deadline`] for `deadline` handling.
+pub async fn wait(
+    va: VirtAddr,
+    expected: u32,
+    deadline: Option<Duration>,
+) -> Result<(), ErrorType> {
+    let key = futex_key(va).await?;
+
+    // Read may fault, so it's done before any lock is taken
+    let value = UserPtr::new(va.to_raw::<u32>())
+        .read()
+        .ok_or(ErrorType::Fault)?;
+
+    if value != expected {
+        return Err(ErrorType::TryAgain);
+    }
+
+    let event = Arc::try_new(Event::new()).map_err(|_| ErrorType::NoMemory)?;
+    let res = {
+        let mut futexes = FUTEXES.lock();
+        let futex = match futexes.get(&key) {
+            Some(futex) => futex.clone(),
+            None => {
+                let futex = Futex {
+                    waiters: Vec::new(),
+                };
+                let futex = Arc::try_new(Spinlock::new(futex)).map_err(|_| ErrorType::NoMemory)?;
+
+                futexes.insert(key, futex.clone());
+                futex
+            }
+        };
+        let mut futex = futex.lock();
+
+        drop(futexes);
+
+        // Value is checked again under the futex lock, so wake up between check and sleep is
+        // not lost
+        if read_key(key) != expected {
+            Err(ErrorType::TryAgain)
+        } else {
+            futex.waiters.try_push(event.clone())
+        }
+    };
+
+    if let Err(err) = res {
+        remove_unused(&mut FUTEXES.lock(), key);
+        return Err(err);
+    }
+
+    let mut waiter = Waiter {
+        key,
+        event,
+        done: false,
+    };
+    let res = with_deadline(deadline, waiter.event.wait()).await;
+
+    waiter.done = res.is_ok();
+    res
+}
+
+/// Wakes up to `count` threads waiting on the word at `va`. Returns number of woken threads
+pub async fn wake(va: VirtAddr, count: usize) -> Result<usize, ErrorType> {
+    let key = futex_key(va).await?;
+    let mut futexes = FUTEXES.lock();
+
+    let Some(futex) = futexes.get(&key).cloned() else {
+        return Ok(0);
+    };
+
+    let mut woken = 0;
+
+    {
+        let mut futex = futex.lock();
+
+        while woken < count && futex.wake_one() {
+            woken += 1;
+        }
+    }
+
+    // Woken waiters hold their own events, so entry is not needed anymore
+    remove_unused(&mut futexes, key);
+
+    Ok(woken)
+}
@@ -5,5 +5,6 @@ pub use wait_queue::WaitQueue;
 
 pub mod async_mutex;
 pub mod event;
+pub mod futex;
 pub mod spinlock;
 pub mod wait_queue;
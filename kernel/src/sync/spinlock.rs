@@ -1,4 +1,5 @@
 use crate::arch::irq::interrupts::{get_flags, set_flags, IrqFlags};
+use crate::arch::mm::tlb::handle_shootdown;
 use crate::tasks::thread::Thread;
 use core::{
     cell::UnsafeCell,
@@ -65,9 +66,7 @@ impl<T> Spinlock<T> {
 
         let my = self.inner.next.fetch_add(1, Ordering::Acquire);
 
-        while self.inner.current.load(Ordering::Relaxed) != my {
-            core::hint::spin_loop();
-        }
+        self.inner.wait(my);
 
         if let Some(cur) = get_current_raw() {
             self.inner.owner.store(cur as *mut _, Ordering::Relaxed);
@@ -85,9 +84,7 @@ impl<T> Spinlock<T> {
         arm_gic::irq_disable();
 
         let my = self.inner.next.fetch_add(1, Ordering::Acquire);
-        while self.inner.current.load(Ordering::Relaxed) != my {
-            core::hint::spin_loop();
-        }
+        self.inner.wait(my);
 
         SpinlockGuard {
             lock: &self.inner,
@@ -98,6 +95,14 @@ impl<T> Spinlock<T> {
 }
 
 impl SpinLockInner {
+    fn wait(&self, my: u16) {
+        while self.current.load(Ordering::Relaxed) != my {
+            // Owner may wait for TLB shootdown, while IRQs of this CPU are masked
+            handle_shootdown();
+            core::hint::spin_loop();
+        }
+    }
+
     pub fn unlock(&self) {
         self.owner.store(core::ptr::null_mut(), Ordering::Relaxed);
 
@@ -3,13 +3,14 @@ use adt::Vec;
 use alloc::collections::LinkedList;
 use core::future::Future;
 use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
 use core::task::{Context, Poll, Waker};
 use rtl::error::ErrorType;
 
 // TODO: move this to lock-free queue. Allocating memory under spinlock is bad idea
 pub struct WaitQueue<T> {
     data: Spinlock<LinkedList<T>>,
-    waiters: Spinlock<Vec<Waker>>,
+    waiters: Spinlock<Vec<(u64, Waker)>>,
 }
 
 impl<T> WaitQueue<T> {
@@ -22,8 +23,19 @@ impl<T> WaitQueue<T> {
 
     pub fn produce(&self, data: T) {
         self.data.lock().push_back(data);
+        self.wake_one();
+    }
+
+    /// Puts `data` back in front of the queue, so it's consumed before anything queued already
+    pub fn produce_front(&self, data: T) {
+        self.data.lock().push_front(data);
+        self.wake_one();
+    }
+
+    fn wake_one(&self) {
+        let waiter = self.waiters.lock().pop();
 
-        if let Some(waiter) = self.waiters.lock().pop() {
+        if let Some((_, waiter)) = waiter {
             waiter.wake();
         }
     }
@@ -35,23 +47,112 @@ impl<T> WaitQueue<T> {
     pub async fn consume(&self) -> Result<T, ErrorType> {
         struct ConsumeFuture<'a, T> {
             wq: &'a WaitQueue<T>,
+            token: u64,
+            // Waker was registered since the last poll
+            registered: bool,
+        }
+
+        impl<T> ConsumeFuture<'_, T> {
+            /// Returns false, if the waker is not registered anymore, since it was woken up
+            fn unregister(&self) -> bool {
+                let mut waiters = self.wq.waiters.lock();
+                let len = waiters.len();
+
+                waiters.retain(|(token, _)| *token != self.token);
+                waiters.len() != len
+            }
+        }
+
+        // Consumer may go away without being woken up (i.e. it was cancelled). Its waker
+        // must not eat up wake up meant for someone else. If it was woken up already, but the
+        // item is still there, the wake up is passed to the next consumer.
+        impl<T> Drop for ConsumeFuture<'_, T> {
+            fn drop(&mut self) {
+                if self.registered && !self.unregister() && !self.wq.data.lock().is_empty() {
+                    self.wq.wake_one();
+                }
+            }
         }
 
         impl<'a, T> Future for ConsumeFuture<'a, T> {
             type Output = Result<T, ErrorType>;
 
-            fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
                 let mut data = self.wq.data.lock();
 
+                self.unregister();
+                self.registered = false;
+
                 if let Some(elem) = data.pop_front() {
                     Poll::Ready(Ok(elem))
                 } else {
-                    self.wq.waiters.lock().try_push(cx.waker().clone())?;
+                    self.wq
+                        .waiters
+                        .lock()
+                        .try_push((self.token, cx.waker().clone()))?;
+                    self.registered = true;
                     Poll::Pending
                 }
             }
         }
 
-        ConsumeFuture { wq: self }.await
+        static TOKEN: AtomicU64 = AtomicU64::new(0);
+
+        ConsumeFuture {
+            wq: self,
+            token: TOKEN.fetch_add(1, Ordering::Relaxed),
+            registered: false,
+        }
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+    use alloc::sync::Arc;
+    use alloc::task::Wake;
+    use core::pin::pin;
+    use core::sync::atomic::AtomicBool;
+    use test_macros::*;
+
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+
+    #[kernel_test]
+    fn wait_queue_dropped_consumer_passes_wake_up() {
+        let wq = WaitQueue::new();
+        let first_flag = Arc::try_new(Flag(AtomicBool::new(false))).unwrap();
+        let second_flag = Arc::try_new(Flag(AtomicBool::new(false))).unwrap();
+        let first_waker = Waker::from(first_flag.clone());
+        let second_waker = Waker::from(second_flag.clone());
+        let mut second_cx = Context::from_waker(&second_waker);
+        let mut second = pin!(wq.consume());
+
+        test_assert!(second.as_mut().poll(&mut second_cx).is_pending());
+
+        {
+            let mut first_cx = Context::from_waker(&first_waker);
+            let mut first = pin!(wq.consume());
+
+            test_assert!(first.as_mut().poll(&mut first_cx).is_pending());
+
+            wq.produce(1);
+            test_assert!(first_flag.0.load(Ordering::Relaxed));
+            test_assert!(!second_flag.0.load(Ordering::Relaxed));
+        }
+
+        // The first consumer went away without taking the item
+        test_assert!(second_flag.0.load(Ordering::Relaxed));
+        test_assert!(matches!(
+            second.as_mut().poll(&mut second_cx),
+            Poll::Ready(Ok(1))
+        ));
     }
 }
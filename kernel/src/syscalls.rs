@@ -4,26 +4,47 @@ use crate::object::{
     capabilities::{Capability, CapabilityMask},
     factory_object::Factory,
     handle::Handle,
+    job_object::Job,
     port_object::Port,
+    resource_object::ResourceObject,
     {wait_many, WaitManyArg},
 };
 use crate::{
     irq::IrqObject,
     mm::{
+        allocators::slab::slab_info,
+        pmm::page_alloc::{cached_pages, page_allocator},
         user_buffer::UserPtr,
         vmm::{vmo::VmObject, vms::Vms},
     },
-    sched::{current_task, timer_object::TimerObject},
-    tasks::{task::Task, thread::Thread},
+    sched::{
+        current, current_task,
+        timer::{time_since_start, with_deadline},
+        timer_object::TimerObject,
+    },
+    sync::futex,
+    tasks::{
+        task::{Task, tasks},
+        thread::Thread,
+    },
 };
 use adt::vec::Vec;
 use alloc::string::String;
 use alloc::string::ToString;
+use core::time::Duration;
 use hal::address::*;
+use rtl::capabilities::SAME_RIGHTS;
 use rtl::handle::{HandleBase, HANDLE_INVALID};
+use rtl::job::JobLimits;
+use rtl::meminfo::{MemInfo, TASK_NAME_LEN, TaskMemInfo};
+use rtl::sched::SchedClass;
 use rtl::signal::{Signal, Signals, WaitEntry};
 use rtl::vmm::MappingType;
-use rtl::{error::ErrorType, ipc::IpcMessage, syscalls::SyscallList};
+use rtl::{
+    error::ErrorType,
+    ipc::IpcMessage,
+    syscalls::{DEADLINE_INFINITE, SyscallList},
+};
 
 #[derive(Debug)]
 pub struct SyscallArgs {
@@ -51,6 +72,14 @@ impl SyscallArgs {
         self.args[n].try_into()
     }
 
+    /// Decodes deadline of a blocking syscall
+    pub fn deadline(&self, n: usize) -> Option<Duration> {
+        match self.args[n] {
+            DEADLINE_INFINITE => None,
+            ns => Some(Duration::from_nanos(ns as u64)),
+        }
+    }
+
     pub fn args(&self) -> [usize; 7] {
         self.args
     }
@@ -82,26 +111,41 @@ pub async fn do_syscall(args: SyscallArgs) -> Result<usize, ErrorType> {
             let name = read_user_string(args.arg(1), args.arg(2))?;
             let mut table = task.handle_table().await?;
 
-            let factory = table
-                .find::<Factory>(args.arg(0), CapabilityMask::any())
-                .ok_or(ErrorType::InvalidHandle)?;
+            let factory =
+                table.find::<Factory>(args.arg(0), CapabilityMask::from(Capability::Create))?;
+            let job = match args.arg::<HandleBase>(3) {
+                HANDLE_INVALID => None,
+                job => Some(table.find::<Job>(job, CapabilityMask::from(Capability::Manage))?),
+            };
+
+            table.add(factory.create_task(name.as_str(), job)?)
+        }
+        SyscallList::CreateJob => {
+            let limits = UserPtr::new(args.arg::<usize>(2) as *const JobLimits)
+                .read()
+                .ok_or(ErrorType::Fault)?;
+            let mut table = task.handle_table().await?;
+
+            let factory =
+                table.find::<Factory>(args.arg(0), CapabilityMask::from(Capability::Create))?;
+            let parent = match args.arg::<HandleBase>(1) {
+                HANDLE_INVALID => None,
+                job => Some(table.find::<Job>(job, CapabilityMask::from(Capability::Manage))?),
+            };
 
-            Ok(table.add(factory.create_task(name.as_str())?))
+            table.add(factory.create_job(parent, &limits)?)
         }
         SyscallList::CreatePort => {
             let mut table = task.handle_table().await?;
-            let factory = table
-                .find::<Factory>(args.arg(0), CapabilityMask::any())
-                .ok_or(ErrorType::InvalidHandle)?;
+            let factory =
+                table.find::<Factory>(args.arg(0), CapabilityMask::from(Capability::Create))?;
 
             let handle = factory.create_port()?;
-            Ok(table.add(handle))
+            table.add(handle)
         }
         SyscallList::VmAllocate => {
             let table = task.handle_table().await?;
-            let vms = table
-                .find::<Vms>(args.arg(0), CapabilityMask::any())
-                .ok_or(ErrorType::InvalidHandle)?;
+            let vms = table.find::<Vms>(args.arg(0), CapabilityMask::from(Capability::Map))?;
 
             vms.vm_allocate(
                 args.arg(1),
@@ -113,56 +157,95 @@ pub async fn do_syscall(args: SyscallArgs) -> Result<usize, ErrorType> {
         }
         SyscallList::VmFree => {
             let table = task.handle_table().await?;
-            let vms = table
-                .find::<Vms>(args.arg(0), CapabilityMask::any())
-                .ok_or(ErrorType::InvalidHandle)?;
+            let vms = table.find::<Vms>(args.arg(0), CapabilityMask::from(Capability::Map))?;
 
             vms.vm_free(args.arg(1), args.arg(2)).await.map(|_| 0)
         }
+        SyscallList::VmProtect => {
+            let table = task.handle_table().await?;
+            let vms = table.find::<Vms>(args.arg(0), CapabilityMask::from(Capability::Map))?;
+
+            vms.vm_protect(
+                MemRange::new(args.arg(1), args.arg(2)),
+                args.try_arg(3).map_err(|_| ErrorType::InvalidArgument)?,
+            )
+            .await
+            .map(|_| 0)
+        }
         SyscallList::CreateVmo => {
             let mut table = task.handle_table().await?;
-            let factory = table
-                .find::<Factory>(args.arg(0), CapabilityMask::any())
-                .ok_or(ErrorType::InvalidHandle)?;
+            let factory =
+                table.find::<Factory>(args.arg(0), CapabilityMask::from(Capability::Create))?;
 
-            Ok(table.add(factory.create_vmo(
+            table.add(factory.create_vmo(
                 args.arg(1),
                 args.try_arg(2).map_err(|_| ErrorType::InvalidArgument)?,
-            )?))
+            )?)
         }
         SyscallList::CreateVmoContig => {
             let mut table = task.handle_table().await?;
-            let factory = table
-                .find::<Factory>(args.arg(0), CapabilityMask::any())
-                .ok_or(ErrorType::InvalidHandle)?;
+            let factory =
+                table.find::<Factory>(args.arg(0), CapabilityMask::from(Capability::Create))?;
 
-            Ok(table.add(factory.create_vmo_contig(
+            table.add(factory.create_vmo_contig(
                 args.arg(1),
                 args.try_arg(2).map_err(|_| ErrorType::InvalidArgument)?,
-            )?))
+            )?)
         }
         SyscallList::VmoGetPhysInfo => {
             let table = task.handle_table().await?;
             let vmo = table
-                .find::<VmObject>(args.arg(0), CapabilityMask::from(Capability::GetPhysInfo))
-                .ok_or(ErrorType::InvalidHandle)?;
+                .find::<VmObject>(args.arg(0), CapabilityMask::from(Capability::GetPhysInfo))?;
 
             vmo.get_phys_info()
                 .map(|pa| pa.bits())
                 .ok_or(ErrorType::InvalidArgument)
         }
+        SyscallList::VmoCreateChild => {
+            let mut table = task.handle_table().await?;
+            let parent = table
+                .find_handle::<VmObject>(args.arg(0), CapabilityMask::from(Capability::Read))?;
+            let kind = args.try_arg(1).map_err(|_| ErrorType::InvalidArgument)?;
+            let vmo = parent.obj::<VmObject>().unwrap();
+            let child = vmo.create_child(kind, args.arg(2), args.arg(3))?;
+
+            // Child can't be used in ways parent can't, except that writes go to its own pages.
+            // Physical info of the parent does not describe them
+            let rights =
+                (parent.rights().bits() | Capability::Write) & VmObject::full_caps().bits();
+
+            table.add(Handle::new(child, CapabilityMask::from(rights)))
+        }
+        SyscallList::CreatePagerVmo => {
+            let mut table = task.handle_table().await?;
+            let factory =
+                table.find::<Factory>(args.arg(0), CapabilityMask::from(Capability::Create))?;
+            let port = table.find::<Port>(args.arg(3), CapabilityMask::from(Capability::Send))?;
+
+            table.add(factory.create_pager_vmo(
+                args.arg(1),
+                args.try_arg(2).map_err(|_| ErrorType::InvalidArgument)?,
+                port,
+                args.arg(4),
+            )?)
+        }
+        SyscallList::VmoSupplyPages => {
+            let table = task.handle_table().await?;
+            let vmo =
+                table.find::<VmObject>(args.arg(0), CapabilityMask::from(Capability::Write))?;
+
+            vmo.supply_pages(args.arg(1), args.arg::<usize>(2) as *const u8, args.arg(3))
+                .map(|_| 0)
+        }
         SyscallList::MapVmo => {
             let table = task.handle_table().await?;
-            let vms = table
-                .find::<Vms>(args.arg(0), CapabilityMask::any())
-                .ok_or(ErrorType::InvalidHandle)?;
-            let vmo = table
-                .find::<VmObject>(args.arg(1), CapabilityMask::any())
-                .ok_or(ErrorType::InvalidHandle)?;
+            let vms = table.find::<Vms>(args.arg(0), CapabilityMask::from(Capability::Map))?;
             let to: VirtAddr = args.arg(2);
             let tp: MappingType = args.try_arg(3).map_err(|_| ErrorType::InvalidArgument)?;
+            let vmo = table.find::<VmObject>(args.arg(1), CapabilityMask::for_mapping(tp))?;
 
-            if tp.is_greater(vmo.mapping_type()) {
+            // Memory type is chosen, when VMO is created
+            if tp.is_greater(vmo.mapping_type()) || tp.cache() != vmo.mapping_type().cache() {
                 return Err(ErrorType::InvalidArgument);
             }
 
@@ -176,9 +259,10 @@ pub async fn do_syscall(args: SyscallArgs) -> Result<usize, ErrorType> {
         }
         SyscallList::MapPhys => {
             let table = task.handle_table().await?;
-            let vms = table
-                .find::<Vms>(args.arg(0), CapabilityMask::from(Capability::MapPhys))
-                .ok_or(ErrorType::InvalidHandle)?;
+            let vms = table.find::<Vms>(args.arg(0), CapabilityMask::from(Capability::Map))?;
+
+            // Device memory is not accounted anywhere and may alias memory of other tasks
+            table.find::<ResourceObject>(args.arg(3), CapabilityMask::from(Capability::MapPhys))?;
 
             vms.map_phys(args.arg(1), args.arg(2))
                 .await
@@ -189,52 +273,49 @@ pub async fn do_syscall(args: SyscallArgs) -> Result<usize, ErrorType> {
             Ok(0)
         }
         SyscallList::TaskStart => {
-            let table = task.handle_table().await?;
-            let task = table
-                .find::<Task>(args.arg(0), CapabilityMask::any())
-                .ok_or(ErrorType::InvalidHandle)?;
-
-            let obj = if args.arg::<HandleBase>(1) != HANDLE_INVALID {
-                Some(
-                    table
-                        .find_raw_handle(args.arg(2))
-                        .ok_or(ErrorType::InvalidHandle)?,
-                )
-            } else {
-                None
+            let mut table = task.handle_table().await?;
+            let task = table.find::<Task>(args.arg(0), CapabilityMask::from(Capability::Manage))?;
+
+            let mut transfer = |h: HandleBase| {
+                if h != HANDLE_INVALID {
+                    let rights = CapabilityMask::from(Capability::Transfer);
+
+                    table.derive(h, rights, None).map(Some)
+                } else {
+                    Ok(None)
+                }
             };
+            let obj = transfer(args.arg(2))?;
+            let resource = transfer(args.arg(3))?;
 
-            task.start(args.arg(1), obj).await.map(|_| 0)
+            task.start(args.arg(1), obj, resource).await.map(|_| 0)
         }
         SyscallList::TaskGetVms => {
             let mut table = task.handle_table().await?;
-            let task = table
-                .find::<Task>(args.arg(0), CapabilityMask::any())
-                .ok_or(ErrorType::InvalidHandle)?;
+            let task = table.find::<Task>(args.arg(0), CapabilityMask::from(Capability::Manage))?;
             let vms = task.vms();
 
-            Ok(table.add(Handle::new(vms.clone(), CapabilityMask::any())))
+            table.add(Handle::new(vms.clone(), Vms::full_caps()))
         }
         SyscallList::PortCall => {
             let port = {
                 let table = task.handle_table().await?;
 
-                table
-                    .find::<Port>(args.arg(0), CapabilityMask::from(Capability::Call))
-                    .ok_or(ErrorType::InvalidHandle)?
+                table.find::<Port>(args.arg(0), CapabilityMask::from(Capability::Call))?
             };
 
-            port.call(UserPtr::new(args.arg::<usize>(1) as *mut IpcMessage))
-                .await
+            port.call(
+                UserPtr::new(args.arg::<usize>(1) as *mut IpcMessage),
+                args.deadline(2),
+            )
+            .await
         }
         SyscallList::PortReply => {
             let msg = UserPtr::new(args.arg::<usize>(2) as *mut IpcMessage);
             let port = {
                 let table = task.handle_table().await?;
 
-                table
-                    .find::<Port>(args.arg(0), CapabilityMask::from(Capability::Send))
-                    .ok_or(ErrorType::InvalidHandle)?
+                table.find::<Port>(args.arg(0), CapabilityMask::from(Capability::Receive))?
             };
 
             port.reply(args.arg(1), msg).await.map(|_| 0)
@@ -244,21 +325,33 @@ pub async fn do_syscall(args: SyscallArgs) -> Result<usize, ErrorType> {
             let port = {
                 let table = task.handle_table().await?;
 
-                table
-                    .find::<Port>(args.arg(0), CapabilityMask::from(Capability::Receive))
-                    .ok_or(ErrorType::InvalidHandle)?
+                table.find::<Port>(args.arg(0), CapabilityMask::from(Capability::Receive))?
             };
 
-            port.receive(in_msg).await
+            port.receive(in_msg, args.deadline(2)).await
+        }
+        SyscallList::PortReplyWait => {
+            let out_msg = UserPtr::new(args.arg::<usize>(2) as *mut IpcMessage);
+            let in_msg = UserPtr::new(args.arg::<usize>(3) as *mut IpcMessage);
+            let port = {
+                let table = task.handle_table().await?;
+
+                table.find::<Port>(args.arg(0), CapabilityMask::from(Capability::Receive))?
+            };
+
+            // First iteration of the server loop has nothing to reply to
+            if args.arg::<HandleBase>(1) != HANDLE_INVALID {
+                port.reply(args.arg(1), out_msg).await?;
+            }
+
+            port.receive(in_msg, args.deadline(4)).await
         }
         SyscallList::PortSend => {
             let in_msg = UserPtr::new(args.arg::<usize>(1) as *mut IpcMessage);
             let port = {
                 let table = task.handle_table().await?;
 
-                table
-                    .find::<Port>(args.arg(0), CapabilityMask::from(Capability::Receive))
-                    .ok_or(ErrorType::InvalidHandle)?
+                table.find::<Port>(args.arg(0), CapabilityMask::from(Capability::Send))?
             };
 
             port.send(in_msg).await.map(|_| 0)
@@ -274,11 +367,23 @@ pub async fn do_syscall(args: SyscallArgs) -> Result<usize, ErrorType> {
         }
         SyscallList::CloneHandle => {
             let mut table = task.handle_table().await?;
-            let obj = table
-                .find_raw_handle(args.arg(0))
-                .ok_or(ErrorType::InvalidHandle)?;
+            let rights = if args.arg::<usize>(1) == SAME_RIGHTS {
+                None
+            } else {
+                Some(args.try_arg(1)?)
+            };
+            let handle = table.derive(
+                args.arg(0),
+                CapabilityMask::from(Capability::Duplicate),
+                rights,
+            )?;
 
-            Ok(table.add(obj))
+            table.add(handle)
+        }
+        SyscallList::RevokeHandle => {
+            let table = task.handle_table().await?;
+
+            table.revoke(args.arg(0)).map(|_| 0)
         }
         SyscallList::MapFdt => {
             let fdt_pa: PhysAddr = fdt().base.into();
@@ -294,12 +399,12 @@ pub async fn do_syscall(args: SyscallArgs) -> Result<usize, ErrorType> {
             let obj = {
                 let table = task.handle_table().await?;
 
-                table
-                    .find_poly(args.arg(0), CapabilityMask::from(Capability::Wait))
-                    .ok_or(ErrorType::InvalidHandle)?
+                table.find_poly(args.arg(0), CapabilityMask::from(Capability::Wait))?
             };
 
-            obj.wait_signal(sig).await.map(|_| 0)
+            with_deadline(args.deadline(2), obj.wait_signal(sig))
+                .await
+                .map(|_| 0)
         }
         SyscallList::WaitObjectMany => {
             let mut user_ptr =
@@ -312,9 +417,7 @@ pub async fn do_syscall(args: SyscallArgs) -> Result<usize, ErrorType> {
 
                 for e in user_wait_entries.iter().map(|x| {
                     Ok(WaitManyArg {
-                        obj: table
-                            .find_poly(x.handle, CapabilityMask::from(Capability::Wait))
-                            .ok_or(ErrorType::InvalidHandle)?,
+                        obj: table.find_poly(x.handle, CapabilityMask::from(Capability::Wait))?,
                         waitfor: x.waitfor,
                         pending: Signal::None.into(),
                     })
@@ -323,7 +426,7 @@ pub async fn do_syscall(args: SyscallArgs) -> Result<usize, ErrorType> {
                 }
             }
 
-            wait_many(&mut wait_entries).await?;
+            with_deadline(args.deadline(2), wait_many(&mut wait_entries)).await?;
 
             for (user, kernel) in core::iter::zip(user_wait_entries.iter_mut(), wait_entries.iter())
             {
@@ -335,29 +438,25 @@ pub async fn do_syscall(args: SyscallArgs) -> Result<usize, ErrorType> {
         }
         SyscallList::CreateIrq => {
             let mut table = task.handle_table().await?;
-            let factory = table
-                .find::<Factory>(args.arg(0), CapabilityMask::any())
-                .ok_or(ErrorType::InvalidHandle)?;
+            let factory =
+                table.find::<Factory>(args.arg(0), CapabilityMask::from(Capability::Create))?;
 
             let trigger = args.try_arg(2).map_err(|_| ErrorType::InvalidArgument)?;
 
-            Ok(table.add(factory.create_irq(args.arg(1), trigger)?))
+            table.add(factory.create_irq(args.arg(1), trigger)?)
         }
         SyscallList::CreateTimer => {
             let mut table = task.handle_table().await?;
-            let factory = table
-                .find::<Factory>(args.arg(0), CapabilityMask::any())
-                .ok_or(ErrorType::InvalidHandle)?;
+            let factory =
+                table.find::<Factory>(args.arg(0), CapabilityMask::from(Capability::Create))?;
 
-            Ok(table.add(factory.create_timer()?))
+            table.add(factory.create_timer()?)
         }
         SyscallList::TimerArm => {
             let timer = {
                 let table = task.handle_table().await?;
 
-                table
-                    .find::<TimerObject>(args.arg(0), CapabilityMask::from(Capability::Wait))
-                    .ok_or(ErrorType::InvalidHandle)?
+                table.find::<TimerObject>(args.arg(0), CapabilityMask::from(Capability::Write))?
             };
             let deadline = core::time::Duration::from_nanos(args.arg::<usize>(1) as u64);
 
@@ -367,19 +466,193 @@ pub async fn do_syscall(args: SyscallArgs) -> Result<usize, ErrorType> {
             let irq = {
                 let table = task.handle_table().await?;
 
-                table
-                    .find::<IrqObject>(args.arg(0), CapabilityMask::any())
-                    .ok_or(ErrorType::InvalidHandle)?
+                table.find::<IrqObject>(args.arg(0), CapabilityMask::from(Capability::Write))?
             };
 
             irq.ack().map(|_| 0)
         }
+        SyscallList::Exit => task.terminate(args.arg(0)).await.map(|_| 0),
+        SyscallList::TaskKill => {
+            let task = {
+                let table = task.handle_table().await?;
+
+                table.find::<Task>(args.arg(0), CapabilityMask::from(Capability::Manage))?
+            };
+
+            task.terminate(args.arg(1)).await.map(|_| 0)
+        }
+        SyscallList::TaskExitCode => {
+            let table = task.handle_table().await?;
+            let task = table.find::<Task>(args.arg(0), CapabilityMask::from(Capability::Wait))?;
+            let code = task.exit_code().ok_or(ErrorType::WouldBlock)?;
+
+            // Full usize range does not fit into the return value, which is isize
+            UserPtr::new(args.arg::<usize>(1) as *mut usize)
+                .write(&code)
+                .map(|_| 0)
+        }
+        SyscallList::CreateThread => {
+            let mut table = task.handle_table().await?;
+            let target = if args.arg::<HandleBase>(0) != HANDLE_INVALID {
+                table.find::<Task>(args.arg(0), CapabilityMask::from(Capability::Manage))?
+            } else {
+                task.clone()
+            };
+            let thread = target.create_thread().await?;
+
+            table.add(Handle::new(thread, Thread::full_caps()))
+        }
+        SyscallList::ThreadStart => {
+            let thread = {
+                let table = task.handle_table().await?;
+
+                table.find::<Thread>(args.arg(0), CapabilityMask::from(Capability::Manage))?
+            };
+
+            thread.start_user(args.arg(1), args.arg(2)).await.map(|_| 0)
+        }
+        SyscallList::ThreadExit => task.exit_thread(&current()).await.map(|_| 0),
+        SyscallList::ThreadSetPriority => {
+            let thread = if args.arg::<HandleBase>(0) != HANDLE_INVALID {
+                let table = task.handle_table().await?;
+
+                table.find::<Thread>(args.arg(0), CapabilityMask::from(Capability::Manage))?
+            } else {
+                current()
+            };
+
+            let class = args.try_arg(1).map_err(|_| ErrorType::InvalidArgument)?;
+
+            // Real-time threads starve everything else, so it's a privilege
+            if class == SchedClass::RealTime {
+                let table = task.handle_table().await?;
+
+                table.find::<ResourceObject>(
+                    args.arg(3),
+                    CapabilityMask::from(Capability::RealTime),
+                )?;
+            }
+
+            thread.set_priority(class, args.arg(2)).map(|_| 0)
+        }
+        SyscallList::ThreadSetAffinity => {
+            let thread = if args.arg::<HandleBase>(0) != HANDLE_INVALID {
+                let table = task.handle_table().await?;
+
+                table.find::<Thread>(args.arg(0), CapabilityMask::from(Capability::Manage))?
+            } else {
+                current()
+            };
+
+            thread.set_affinity(args.arg(1)).map(|_| 0)
+        }
+        SyscallList::ClockGet => Ok(time_since_start().as_nanos() as usize),
+        SyscallList::CreateEvent => {
+            let mut table = task.handle_table().await?;
+            let factory =
+                table.find::<Factory>(args.arg(0), CapabilityMask::from(Capability::Create))?;
+
+            table.add(factory.create_event()?)
+        }
+        SyscallList::CreateEventPair => {
+            let mut out = UserPtr::new_array(args.arg::<usize>(1) as *mut HandleBase, 2);
+            let mut table = task.handle_table().await?;
+            let factory =
+                table.find::<Factory>(args.arg(0), CapabilityMask::from(Capability::Create))?;
+            let (first, second) = factory.create_event_pair()?;
+            let first = table.add(first)?;
+            let second = match table.add(second) {
+                Ok(second) => second,
+                Err(err) => {
+                    table.remove(first);
+                    return Err(err);
+                }
+            };
+
+            out.write_array(&[first, second]).inspect_err(|_| {
+                table.remove(first);
+                table.remove(second);
+            })?;
+            Ok(0)
+        }
+        SyscallList::FutexWait => {
+            futex::wait(args.arg(0), args.arg::<usize>(1) as u32, args.deadline(2))
+                .await
+                .map(|_| 0)
+        }
+        SyscallList::FutexWake => futex::wake(args.arg(0), args.arg(1)).await,
+        SyscallList::SignalObject => {
+            let set: Signals = args.try_arg(1)?;
+            let clear: Signals = args.try_arg(2)?;
+            let obj = {
+                let table = task.handle_table().await?;
+
+                table.find_poly(args.arg(0), CapabilityMask::from(Capability::Signal))?
+            };
+
+            obj.signal_user(set, clear).map(|_| 0)
+        }
+        SyscallList::MemInfo => {
+            // Statistics reveal every task in the system
+            task.handle_table()
+                .await?
+                .find::<ResourceObject>(args.arg(0), CapabilityMask::from(Capability::MemInfo))?;
+
+            let mut info = UserPtr::new(args.arg::<usize>(1) as *mut MemInfo);
+            let mut out = UserPtr::new_array(args.arg::<usize>(2) as *mut TaskMemInfo, args.arg(3));
+            let mut tasks = tasks()?;
+            let mut entries = Vec::new();
+
+            tasks.retain(|x| !x.is_terminated());
+            info.write(&mem_info())?;
+
+            for task in tasks.iter().take(out.len()) {
+                entries.try_push(task_mem_info(task)?)?;
+            }
+
+            out.write_array(&entries)?;
+            Ok(tasks.len())
+        }
     };
 
     // info!("{} <-- {:?}\n", task.name(), args.number());
     res
 }
 
+fn mem_info() -> MemInfo {
+    // Per-CPU caches are locked before the page allocator, so they must not nest here
+    let cached = cached_pages();
+    let (total, free) = {
+        let allocator = page_allocator();
+
+        (allocator.total_pages(), allocator.free_pages())
+    };
+
+    MemInfo {
+        total_pages: total,
+        free_pages: free + cached,
+        cached_pages: cached,
+        slabs: slab_info(),
+    }
+}
+
+fn task_mem_info(task: &Task) -> Result<TaskMemInfo, ErrorType> {
+    let mut info = TaskMemInfo {
+        id: task.id(),
+        pages: task.vms().committed_pages()?,
+        ..Default::default()
+    };
+    let mut len = task.name().len().min(TASK_NAME_LEN);
+
+    while !task.name().is_char_boundary(len) {
+        len -= 1;
+    }
+
+    info.name[..len].copy_from_slice(&task.name().as_bytes()[..len]);
+    info.name_len = len;
+    Ok(info)
+}
+
 fn do_write(string: &[u8]) {
     let str = unsafe { core::str::from_utf8_unchecked(string) };
     // let str = alloc::format!("{} --> {str}\n", current_task().name());
@@ -36,18 +36,18 @@ pub async fn prepare_initial_task(
         virt_range.align_page();
 
         let perms = if seg.p_flags == PF_W | PF_R {
-            MappingType::Data
+            MappingType::DATA
         } else if seg.p_flags == PF_X | PF_R {
-            MappingType::Text
+            MappingType::TEXT
         } else if seg.p_flags == PF_R {
-            MappingType::RoData
+            MappingType::RODATA
         } else {
             panic!("Unknown elf permissions");
         };
 
         vms.vm_allocate(
             virt_range.size(),
-            MappingType::Data,
+            MappingType::DATA,
             Some(virt_range.start()),
         )
         .await?;
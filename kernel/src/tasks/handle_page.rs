@@ -29,7 +29,7 @@ impl HandlePage {
 
     pub async fn push(&mut self, handle: Handle, name: HandleName) -> Result<(), ErrorType> {
         let mut table = self.task.handle_table().await?;
-        let raw = table.add(handle);
+        let raw = table.add(handle)?;
 
         // TODO: maybe add checks for collisions?
         self.pairs.try_push((name, raw))
@@ -42,7 +42,7 @@ impl HandlePage {
         let mut ptr = self
             .task
             .vms()
-            .vm_allocate(full_size, MappingType::Data, None)
+            .vm_allocate(full_size, MappingType::DATA, None)
             .await?;
 
         self.task.with_attached_task(|| unsafe {
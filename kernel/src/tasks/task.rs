@@ -1,15 +1,19 @@
 use super::handle_page::{HandleName, HandlePage};
 use crate::mm::vmm::vms::Vms;
 use crate::object::KernelObjectBase;
-use crate::object::capabilities::CapabilityMask;
-use crate::object::factory_object::FACTORY;
+use crate::object::capabilities::{Capability, CapabilityMask};
+use crate::object::factory_object::{FACTORY, Factory};
 use crate::object::handle::Handle;
 use crate::object::handle_table::HandleTable;
+use crate::object::job_object::{Job, Resource};
+use crate::sched::current::set_attached_vms;
 use crate::sched::{current, current_task};
 use crate::sync::{Mutex, Spinlock, async_mutex::MutexGuard};
 use crate::tasks::thread::Thread;
-use alloc::collections::LinkedList;
-use alloc::sync::Arc;
+use adt::Vec;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use core::sync::atomic::{AtomicU32, Ordering};
 use hal::address::VirtAddr;
 use heapless::String;
 use rtl::error::ErrorType;
@@ -18,31 +22,51 @@ use rtl::signal::Signal;
 use spin::Once;
 
 pub struct TaskInner {
-    threads: LinkedList<Arc<Thread>>,
+    threads: Vec<Arc<Thread>>,
 }
 
 static INIT_TASK: Once<Arc<Task>> = Once::new();
 static KERNEL_TASK: Once<Arc<Task>> = Once::new();
 
+// User tasks by id. Dead entries are pruned, when new task is added
+static TASKS: Spinlock<BTreeMap<u32, Weak<Task>>> = Spinlock::new(BTreeMap::new());
+
 impl TaskInner {
     pub fn new_user() -> Self {
         Self {
-            threads: LinkedList::new(),
+            threads: Vec::new(),
         }
     }
 
-    pub fn add_thread(&mut self, t: Arc<Thread>) {
-        self.threads.push_back(t);
+    pub fn add_thread(&mut self, t: Arc<Thread>) -> Result<(), ErrorType> {
+        self.threads.try_push(t)
     }
 
-    pub fn start(&mut self) -> Result<(), ErrorType> {
-        self.threads.front().unwrap().start()
+    /// Detaches thread from the task. Returns whether thread was attached and whether it was
+    /// the last one
+    pub fn remove_thread(&mut self, t: &Arc<Thread>) -> (bool, bool) {
+        let len = self.threads.len();
+
+        self.threads.retain(|x| !Arc::ptr_eq(x, t));
+        (self.threads.len() != len, self.threads.is_empty())
+    }
+
+    /// Kills all threads except the current one and detaches them from the task
+    pub fn kill_threads(&mut self) -> Vec<Arc<Thread>> {
+        let cur = current();
+
+        for t in self.threads.iter().filter(|t| !Arc::ptr_eq(t, &cur)) {
+            t.kill();
+        }
+
+        core::mem::take(&mut self.threads)
     }
 }
 
 pub fn init_task() -> Arc<Task> {
     INIT_TASK.call_once(|| {
-        Task::new(TaskName::try_from("init").unwrap()).expect("No memory for initial task")
+        Task::new(TaskName::try_from("init").unwrap(), Job::root())
+            .expect("No memory for initial task")
     });
     INIT_TASK.get().unwrap().clone()
 }
@@ -52,6 +76,17 @@ pub fn kernel_task() -> Arc<Task> {
     KERNEL_TASK.get().unwrap().clone()
 }
 
+/// Snapshot of user tasks, which are still referenced
+pub fn tasks() -> Result<Vec<Arc<Task>>, ErrorType> {
+    let mut res = Vec::new();
+
+    for task in TASKS.lock().values().filter_map(Weak::upgrade) {
+        res.try_push(task)?;
+    }
+
+    Ok(res)
+}
+
 pub type TaskName = String<100>;
 
 pub struct Task {
@@ -60,10 +95,12 @@ pub struct Task {
     id: u32,
     vms: Arc<Vms>,
     handles: Mutex<HandleTable>,
+    exit_code: Once<usize>,
+    job: Arc<Job>,
     base: KernelObjectBase,
 }
 
-crate::kernel_object!(Task, Signal::None.into());
+crate::kernel_object!(Task, Signal::TaskTerminated.into());
 
 impl Task {
     pub fn new_kernel() -> Option<Arc<Task>> {
@@ -73,31 +110,109 @@ impl Task {
             id: 0,
             vms: Vms::new_kernel()?,
             handles: Mutex::new(HandleTable::new()),
+            exit_code: Once::new(),
+            job: Job::root(),
             base: KernelObjectBase::new(),
         })
         .ok()
     }
 
-    pub fn new(name: TaskName) -> Option<Arc<Task>> {
-        Arc::try_new(Self {
+    /// Creates new task, which resources are charged to `job`
+    pub fn new(name: TaskName, job: Arc<Job>) -> Option<Arc<Task>> {
+        static ID_TASK: AtomicU32 = AtomicU32::new(1);
+
+        let task = Arc::try_new(Self {
             inner: Spinlock::new(TaskInner::new_user()),
             name,
-            id: 0,
+            id: ID_TASK.fetch_add(1, Ordering::Relaxed),
             vms: Vms::new_user()?,
-            handles: Mutex::new(HandleTable::new()),
+            handles: Mutex::new(HandleTable::new_charged(job.clone())),
+            exit_code: Once::new(),
+            job,
             base: KernelObjectBase::new(),
         })
-        .ok()
+        .ok()?;
+        let mut tasks = TASKS.lock();
+
+        tasks.retain(|_, task| task.strong_count() != 0);
+        tasks.insert(task.id, Arc::downgrade(&task));
+        drop(tasks);
+
+        Some(task)
+    }
+
+    pub fn full_caps() -> CapabilityMask {
+        CapabilityMask::from(
+            Capability::Manage | Capability::Wait | Capability::Duplicate | Capability::Transfer,
+        )
     }
 
     pub fn id(&self) -> u32 {
         self.id
     }
 
+    pub fn job(&self) -> &Arc<Job> {
+        &self.job
+    }
+
     pub async fn handle_table<'a>(&'a self) -> Result<MutexGuard<'a, HandleTable>, ErrorType> {
+        if self.is_terminated() {
+            return Err(ErrorType::TaskDead);
+        }
+
         self.handles.lock().await
     }
 
+    pub fn is_terminated(&self) -> bool {
+        self.exit_code.is_completed()
+    }
+
+    /// Exit code of the task. None if task is still alive
+    pub fn exit_code(&self) -> Option<usize> {
+        self.exit_code.get().copied()
+    }
+
+    /// Terminates the task. All threads are killed, address space and handle table are
+    /// released. If task is terminated by one of its own threads, this thread must not
+    /// return to the user-space
+    pub async fn terminate(&self, code: usize) -> Result<(), ErrorType> {
+        let mut first = false;
+
+        self.exit_code.call_once(|| {
+            first = true;
+            code
+        });
+
+        if !first {
+            return Err(ErrorType::TaskDead);
+        }
+
+        let threads = self.inner.lock().kill_threads();
+        self.job.uncharge(Resource::Threads, threads.len());
+
+        // Killed threads may still run on other CPUs, and must leave them before their address
+        // space goes away
+        let cur = current();
+
+        for t in threads.iter().filter(|t| !Arc::ptr_eq(t, &cur)) {
+            t.wait_off_cpu();
+        }
+
+        // Drop handles outside of the lock, since one of them can reference the task itself
+        let handles = core::mem::replace(&mut *self.handles.lock().await?, HandleTable::new());
+        drop(handles);
+
+        self.vms.destroy().await?;
+
+        // Current thread is killed last, since it's the one doing the cleanup
+        for t in threads {
+            t.kill();
+        }
+
+        self.signal_fire(Signal::TaskTerminated.into());
+        Ok(())
+    }
+
     pub fn vms(&self) -> &Arc<Vms> {
         &self.vms
     }
@@ -106,26 +221,52 @@ impl Task {
         &self.name
     }
 
-    fn add_thread(&self, t: Arc<Thread>) {
-        self.inner.lock().add_thread(t);
+    /// Creates new thread inside the task. Thread is not started
+    pub async fn create_thread(self: &Arc<Self>) -> Result<Arc<Thread>, ErrorType> {
+        use core::sync::atomic::{AtomicU16, Ordering};
+
+        static ID_THREAD: AtomicU16 = AtomicU16::new(1);
+
+        if self.is_terminated() {
+            return Err(ErrorType::TaskDead);
+        }
+
+        self.job.try_charge(Resource::Threads, 1)?;
+
+        Thread::new_user(self.clone(), ID_THREAD.fetch_add(1, Ordering::Relaxed))
+            .await
+            .and_then(|thread| {
+                self.inner.lock().add_thread(thread.clone())?;
+                Ok(thread)
+            })
+            .inspect_err(|_| self.job.uncharge(Resource::Threads, 1))
     }
 
-    fn start_inner(&self) -> Result<(), ErrorType> {
-        self.inner.lock().start()
+    /// Terminates the thread. Task is terminated with the last thread
+    pub async fn exit_thread(&self, thread: &Arc<Thread>) -> Result<(), ErrorType> {
+        let (removed, last) = self.inner.lock().remove_thread(thread);
+
+        if removed {
+            self.job.uncharge(Resource::Threads, 1);
+        }
+
+        let res = if last {
+            self.terminate(0).await
+        } else {
+            Ok(())
+        };
+
+        thread.kill();
+        res
     }
 
     pub async fn start(
         self: Arc<Self>,
         ep: VirtAddr,
         obj: Option<Handle>,
+        resource: Option<Handle>,
     ) -> Result<(), ErrorType> {
-        use core::sync::atomic::{AtomicU16, Ordering};
-
-        static ID_THREAD: AtomicU16 = AtomicU16::new(1);
-
-        let init_thread = Thread::new_user(self.clone(), ID_THREAD.fetch_add(1, Ordering::Relaxed))
-            .await
-            .ok_or(ErrorType::NoMemory)?;
+        let init_thread = self.create_thread().await?;
         let mut handle_page = HandlePage::new(self.clone());
 
         handle_page
@@ -137,7 +278,7 @@ impl Task {
 
         handle_page
             .push(
-                Handle::new(FACTORY.clone(), CapabilityMask::any()),
+                Handle::new(FACTORY.clone(), Factory::full_caps()),
                 HandleName::try_from("FACTORY").unwrap(),
             )
             .await?;
@@ -148,11 +289,16 @@ impl Task {
                 .await?;
         }
 
+        if let Some(resource) = resource {
+            handle_page
+                .push(resource, HandleName::try_from("RESOURCE").unwrap())
+                .await?;
+        }
+
         init_thread
             .init_user(ep, Some(handle_page.into_ptr().await? as usize))
-            .await;
-        self.inner.lock().add_thread(init_thread);
-        self.start_inner()
+            .await?;
+        init_thread.start()
     }
 
     pub fn with_attached_task<F: FnOnce()>(self: &Arc<Self>, f: F) {
@@ -160,16 +306,17 @@ impl Task {
             let cur_task = current_task();
 
             // TODO: disallow nested switching
+            set_attached_vms(Some(self.vms().clone()));
             self.vms().switch_to();
             f();
             cur_task.vms().switch_to();
+            set_attached_vms(None);
         });
     }
 
     pub async fn vms_handle(&self) -> Result<HandleBase, ErrorType> {
-        Ok(self
-            .handle_table()
+        self.handle_table()
             .await?
-            .add(Handle::new(self.vms().clone(), Vms::full_caps())))
+            .add(Handle::new(self.vms().clone(), Vms::full_caps()))
     }
 }
@@ -1,26 +1,33 @@
 use super::task::Task;
+use crate::arch::mm::tlb::handle_shootdown;
 use crate::arch::regs::Context;
 use crate::object::KernelObjectBase;
+use crate::object::capabilities::{Capability, CapabilityMask};
 use crate::sched::spawn;
+use crate::smp::ipi::{Ipi, send_ipi};
+use crate::smp::online_cpus;
 use crate::sync::Spinlock;
 use crate::tasks::task::kernel_task;
 use alloc::boxed::Box;
 use alloc::sync::Arc;
 use alloc::sync::Weak;
 use core::pin::Pin;
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering, fence};
 use core::task::{Context as PollContext, Poll, Waker};
 use core::time::Duration;
 use hal::address::*;
 use hal::arch::PAGE_SIZE;
 use rtl::error::ErrorType;
 use rtl::linker_var;
+use rtl::sched::{PRIORITY_DEFAULT, PRIORITY_LEVELS, SchedClass};
 use rtl::signal::Signal;
 use rtl::vmm::MappingType;
 
 const USER_THREAD_STACK_PAGES: usize = 2000;
+const USER_STACK_GUARD_PAGES: usize = 16;
 const KERNEL_STACK_PAGES: usize = 100;
 const RR_TICKS: usize = 10;
+const NO_CPU: usize = usize::MAX;
 
 #[derive(PartialEq, Copy, Clone, Debug)]
 pub enum ThreadType {
@@ -74,6 +81,9 @@ pub enum ThreadState {
     Running = 1,
     Sleeping = 2,
     NeedResched = 3,
+    Dead = 4,
+    // Start is requested, but the thread is not running yet
+    Starting = 5,
 }
 
 #[derive(PartialEq, Copy, Clone, Debug)]
@@ -104,9 +114,21 @@ pub struct Thread {
     state: AtomicUsize,
     pub ticks: AtomicUsize,
     preemtion_counter: AtomicUsize,
+    // Position in the run queue: (cpu, key). Used to switch directly to the thread
+    rq_slot: Spinlock<Option<(usize, u32)>>,
+    // Scheduling class and priority packed by sched_raw()
+    sched: AtomicUsize,
+    // Real-time thread used up its time slice and is scheduled as a fair one until it blocks
+    throttled: AtomicBool,
+    // Thread left the CPU on reschedule request, not because it blocked
+    preempted: AtomicBool,
+    // Mask of CPUs thread is allowed to run on
+    affinity: AtomicUsize,
+    // CPU, which executor polls the thread right now, or NO_CPU
+    cpu: AtomicUsize,
 }
 
-crate::kernel_object!(Thread, Signal::None.into());
+crate::kernel_object!(Thread, Signal::ThreadTerminated.into());
 
 impl ThreadRawState {
     fn get_state(&self) -> ThreadState {
@@ -122,13 +144,17 @@ impl ThreadRawState {
     }
 }
 
+fn sched_raw(class: SchedClass, priority: usize) -> usize {
+    (class as usize) << 16 | priority
+}
+
 impl Thread {
-    pub async fn new_user(task: Arc<Task>, id: u16) -> Option<Arc<Thread>> {
+    pub async fn new_user(task: Arc<Task>, id: u16) -> Result<Arc<Thread>, ErrorType> {
         let kernel_stack = kernel_task()
             .vms()
-            .vm_allocate(KERNEL_STACK_PAGES * PAGE_SIZE, MappingType::Data, None)
+            .vm_allocate(KERNEL_STACK_PAGES * PAGE_SIZE, MappingType::DATA, None)
             .await
-            .expect("Failed to allocate kernel stack");
+            .map_err(|_| ErrorType::NoMemory)?;
 
         Arc::try_new(Self {
             id,
@@ -145,8 +171,14 @@ impl Thread {
             ),
             base: KernelObjectBase::new(),
             preemtion_counter: 0.into(),
+            rq_slot: Spinlock::new(None),
+            sched: sched_raw(SchedClass::Fair, PRIORITY_DEFAULT).into(),
+            throttled: false.into(),
+            preempted: false.into(),
+            affinity: usize::MAX.into(),
+            cpu: NO_CPU.into(),
         })
-        .ok()
+        .map_err(|_| ErrorType::NoMemory)
     }
 
     pub fn initial() -> Option<Arc<Thread>> {
@@ -168,10 +200,22 @@ impl Thread {
             ),
             base: KernelObjectBase::new(),
             preemtion_counter: 0.into(),
+            rq_slot: Spinlock::new(None),
+            sched: sched_raw(SchedClass::Fair, PRIORITY_DEFAULT).into(),
+            throttled: false.into(),
+            preempted: false.into(),
+            affinity: usize::MAX.into(),
+            cpu: NO_CPU.into(),
         })
         .ok()
     }
 
+    pub fn full_caps() -> CapabilityMask {
+        CapabilityMask::from(
+            Capability::Manage | Capability::Wait | Capability::Duplicate | Capability::Transfer,
+        )
+    }
+
     pub fn id(&self) -> u16 {
         self.id
     }
@@ -180,13 +224,20 @@ impl Thread {
         self.task.upgrade().unwrap()
     }
 
-    pub async fn init_user(self: &Arc<Thread>, ep: VirtAddr, args: Option<usize>) {
-        let task = self.task.upgrade().unwrap();
+    pub async fn init_user(
+        self: &Arc<Thread>,
+        ep: VirtAddr,
+        args: Option<usize>,
+    ) -> Result<(), ErrorType> {
+        let task = self.task.upgrade().ok_or(ErrorType::TaskDead)?;
         let vms = task.vms();
         let user_stack = vms
-            .vm_allocate(USER_THREAD_STACK_PAGES * PAGE_SIZE, MappingType::Data, None)
+            .vm_allocate_stack(
+                USER_THREAD_STACK_PAGES * PAGE_SIZE,
+                USER_STACK_GUARD_PAGES * PAGE_SIZE,
+            )
             .await
-            .expect("Failed to allocate user stack");
+            .map_err(|_| ErrorType::NoMemory)?;
 
         let mut inner = self.inner.lock();
 
@@ -195,6 +246,7 @@ impl Thread {
             VirtAddr::from(user_stack.bits() + USER_THREAD_STACK_PAGES * PAGE_SIZE),
             args.unwrap_or(0),
         );
+        Ok(())
     }
 
     fn set_state(self: &Arc<Self>, state: ThreadState, sleep: ThreadSleepReason) {
@@ -208,6 +260,75 @@ impl Thread {
         self.set_state(ThreadState::Running, ThreadSleepReason::None);
     }
 
+    pub fn set_rq_slot(&self, cpu: usize, key: u32) {
+        *self.rq_slot.lock() = Some((cpu, key));
+    }
+
+    pub fn rq_slot(&self) -> Option<(usize, u32)> {
+        *self.rq_slot.lock()
+    }
+
+    pub fn clear_rq_slot(&self) {
+        *self.rq_slot.lock() = None;
+    }
+
+    pub fn set_waker(&self, waker: Waker) {
+        self.inner.lock().set_waker(waker);
+    }
+
+    /// Marks thread as dead. Executor drops the thread once it's polled next time
+    pub fn kill(self: &Arc<Self>) {
+        self.set_state(ThreadState::Dead, ThreadSleepReason::None);
+        self.inner.lock().wake();
+        self.signal_fire(Signal::ThreadTerminated.into());
+    }
+
+    pub fn is_dead(self: &Arc<Self>) -> bool {
+        self.state() == ThreadState::Dead
+    }
+
+    /// Called by executor before polling the thread on `cpu`. Returns false, if the thread is
+    /// dead and must not run anymore
+    pub fn enter_cpu(self: &Arc<Self>, cpu: usize) -> bool {
+        self.cpu.store(cpu, Ordering::Relaxed);
+
+        // Pairs with the fence in wait_off_cpu(), so either the thread is seen dead here or
+        // its CPU is seen by the killer
+        fence(Ordering::SeqCst);
+
+        if self.is_dead() {
+            self.leave_cpu();
+            return false;
+        }
+
+        true
+    }
+
+    /// Called by executor once it stops polling the thread
+    pub fn leave_cpu(&self) {
+        self.cpu.store(NO_CPU, Ordering::Release);
+    }
+
+    /// Waits until killed thread leaves CPU it runs on. Must not be called by the thread itself
+    pub fn wait_off_cpu(&self) {
+        fence(Ordering::SeqCst);
+
+        let cpu = self.cpu.load(Ordering::Relaxed);
+
+        if cpu == NO_CPU {
+            return;
+        }
+
+        // Interrupt makes thread trap out of the user-space and notice it's dead
+        send_ipi(cpu, Ipi::Reschedule);
+
+        while self.cpu.load(Ordering::Acquire) != NO_CPU {
+            // Other CPU may wait for TLB shootdown, before it gets to the interrupt
+            handle_shootdown();
+            core::hint::spin_loop();
+        }
+    }
+
     pub fn start(self: &Arc<Self>) -> Result<(), ErrorType> {
         use crate::sched::userspace_loop;
 
@@ -220,11 +341,117 @@ impl Thread {
         )
     }
 
+    /// Starts user thread which was created, but not started yet
+    pub async fn start_user(self: &Arc<Self>, ep: VirtAddr, arg: usize) -> Result<(), ErrorType> {
+        let initialized: usize =
+            ThreadRawState::from_raw_parts(ThreadState::Initialized, ThreadSleepReason::None)
+                .into();
+        let starting: usize =
+            ThreadRawState::from_raw_parts(ThreadState::Starting, ThreadSleepReason::None).into();
+
+        // Only one of concurrent start requests wins
+        self.state
+            .compare_exchange(initialized, starting, Ordering::AcqRel, Ordering::Relaxed)
+            .map_err(|_| ErrorType::InvalidArgument)?;
+
+        if let Err(err) = self.init_user(ep, Some(arg)).await {
+            // Thread could be killed meanwhile, then it must stay dead
+            let _ = self.state.compare_exchange(
+                starting,
+                initialized,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            );
+            return Err(err);
+        }
+
+        self.start()
+    }
+
     fn request_resched(self: &Arc<Self>) {
         self.set_state(ThreadState::NeedResched, ThreadSleepReason::None);
+    }
 
-        // TODO: better move to scheduler
-        self.ticks.store(RR_TICKS, Ordering::Relaxed);
+    pub fn set_priority(&self, class: SchedClass, priority: usize) -> Result<(), ErrorType> {
+        if priority >= PRIORITY_LEVELS {
+            return Err(ErrorType::InvalidArgument);
+        }
+
+        self.sched
+            .store(sched_raw(class, priority), Ordering::Relaxed);
+        self.throttled.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn priority(&self) -> (SchedClass, usize) {
+        let raw = self.sched.load(Ordering::Relaxed);
+        let class = SchedClass::try_from(raw >> 16).unwrap();
+
+        (class, raw & 0xFFFF)
+    }
+
+    /// Level run queue picks threads by. All fair threads are at level 0, real-time ones are
+    /// above them
+    pub fn sched_level(&self) -> usize {
+        match self.priority() {
+            (SchedClass::RealTime, priority) if !self.throttled.load(Ordering::Relaxed) => {
+                priority + 1
+            }
+            _ => 0,
+        }
+    }
+
+    /// Ticks thread runs before it's rescheduled. Fair threads with default priority get
+    /// [`RR_TICKS`]
+    fn time_slice(&self) -> usize {
+        match self.priority() {
+            (SchedClass::Fair, priority) => {
+                (RR_TICKS * (priority + 1)).div_ceil(PRIORITY_DEFAULT + 1)
+            }
+            (SchedClass::RealTime, _) => RR_TICKS,
+        }
+    }
+
+    /// Reschedules thread on next return to user-space
+    pub fn preempt(self: &Arc<Self>) {
+        if self.is_preemtion_enabled() {
+            self.request_resched();
+        }
+    }
+
+    /// Called by executor once thread stops running. Returns `true`, if thread was preempted.
+    /// Blocked thread is not throttled anymore
+    pub fn switched_out(&self) -> bool {
+        let preempted = self.preempted.swap(false, Ordering::Relaxed);
+
+        if !preempted {
+            self.throttled.store(false, Ordering::Relaxed);
+        }
+
+        preempted
+    }
+
+    pub fn affinity(&self) -> usize {
+        self.affinity.load(Ordering::Relaxed)
+    }
+
+    /// Restricts thread to CPUs from `mask`. Thread running on other CPU moves on its next
+    /// preemption
+    pub fn set_affinity(self: &Arc<Self>, mask: usize) -> Result<(), ErrorType> {
+        if mask & online_cpus() == 0 {
+            return Err(ErrorType::InvalidArgument);
+        }
+
+        self.affinity.store(mask, Ordering::Relaxed);
+
+        if let Some((cpu, _)) = self.rq_slot()
+            && mask & (1 << cpu) == 0
+            && self.state() == ThreadState::Running
+        {
+            self.preempt();
+        }
+
+        Ok(())
     }
 
     fn disable_preemtion(self: &Arc<Self>) {
@@ -263,6 +490,7 @@ impl Thread {
                         Poll::Ready(self.thread.inner.lock().take_context().unwrap())
                     }
                     ThreadState::NeedResched => {
+                        self.thread.preempted.store(true, Ordering::Relaxed);
                         self.thread.set_running();
                         cx.waker().wake_by_ref();
                         Poll::Pending
@@ -297,9 +525,15 @@ impl Thread {
                 if x == 0 { None } else { Some(x - 1) }
             });
 
+        if old.is_err() && self.priority().0 == SchedClass::RealTime {
+            self.throttled.store(true, Ordering::Relaxed);
+        }
+
         // old.is_err() means thread run out of quantum. When it will be re-enabled thread
-        // will be punished by force reschedule
+        // will be punished by force reschedule. Slice is refilled only here, so yielding does not
+        // let real-time thread escape throttling
         if old.is_err() && self.is_preemtion_enabled() {
+            self.ticks.store(self.time_slice(), Ordering::Relaxed);
             self.request_resched();
         }
     }
@@ -313,42 +547,8 @@ impl Thread {
     }
 
     pub async fn sleep_for(dl: Duration) -> Result<(), ErrorType> {
-        use crate::sched::timer::{set_timer, time_since_start};
+        use crate::sched::timer::{sleep_until, time_since_start};
 
-        struct Sleep {
-            dl: Duration,
-            diff: Duration,
-            polled: bool,
-        }
-
-        impl Future for Sleep {
-            type Output = Result<(), ErrorType>;
-
-            fn poll(mut self: Pin<&mut Self>, cx: &mut PollContext) -> Poll<Self::Output> {
-                let waker = cx.waker().clone();
-
-                if time_since_start() >= self.dl {
-                    Poll::Ready(Ok(()))
-                } else if !self.polled {
-                    set_timer(
-                        self.diff,
-                        alloc::boxed::Box::try_new(move || waker.wake_by_ref())
-                            .map_err(|_| ErrorType::NoMemory)?,
-                    );
-
-                    self.polled = true;
-                    Poll::Pending
-                } else {
-                    Poll::Pending
-                }
-            }
-        }
-
-        Sleep {
-            dl: time_since_start() + dl,
-            diff: dl,
-            polled: false,
-        }
-        .await
+        sleep_until(time_since_start() + dl).await
     }
 }
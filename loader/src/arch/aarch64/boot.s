@@ -74,3 +74,32 @@ crt0:
 	b	main
 
 	ret
+
+// Secondary CPUs are started by the kernel via PSCI and enter here with MMU off.
+// x0 -- physical address of struct SecondaryBoot. Loader text is identity mapped in the kernel
+// page table, so MMU can be enabled right here
+.section ".text"
+.global secondary_entry
+secondary_entry:
+	ldp	x1, x2, [x0]
+	ldp	x3, x4, [x0, #16]
+	ldp	x5, x6, [x0, #32]
+	ldr	x7, [x0, #48]
+
+	msr	TTBR0_EL1, x1
+	msr	TTBR1_EL1, x1
+	msr	TCR_EL1, x2
+	msr	MAIR_EL1, x3
+	isb
+
+	tlbi	vmalle1
+	ic	iallu
+	dsb	nsh
+	isb
+
+	msr	SCTLR_EL1, x4
+	isb
+
+	mov	sp, x6
+	mov	x0, x7
+	br	x5
@@ -54,6 +54,11 @@ impl Pte {
         Self(TABLE_VALID | next.bits())
     }
 
+    pub fn make_block(pa: PhysAddr, perms: PagePerms, kind: PageKind) -> Self {
+        // Blocks differ from pages only by descriptor type
+        Self(Self::make(pa, perms, kind).0 & !0b10)
+    }
+
     pub fn make(pa: PhysAddr, perms: PagePerms, kind: PageKind) -> Self {
         const fn ap(perms: usize) -> usize {
             (perms << 6) as usize
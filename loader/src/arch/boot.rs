@@ -18,7 +18,10 @@ pub fn boot(ep: usize, arg0: usize, tt: usize) {
 
     MAIR_EL1.modify(
         MAIR_EL1::Attr0_Normal_Inner::WriteBack_NonTransient_ReadWriteAlloc
-            + MAIR_EL1::Attr1_Device::nonGathering_nonReordering_noEarlyWriteAck,
+            + MAIR_EL1::Attr1_Device::nonGathering_nonReordering_noEarlyWriteAck
+            + MAIR_EL1::Attr2_Normal_Outer::NonCacheable
+            + MAIR_EL1::Attr2_Normal_Inner::NonCacheable
+            + MAIR_EL1::Attr3_Device::nonGathering_nonReordering_EarlyWriteAck,
     );
 
     TTBR1_EL1.set(tt as u64);
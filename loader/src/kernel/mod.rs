@@ -4,11 +4,15 @@ use crate::mm::{
 };
 use elf::{
     ElfBytes,
-    abi::{PF_R, PF_W, PF_X, PT_LOAD},
+    abi::{DT_RELA, DT_RELASZ, PF_R, PF_W, PF_X, PT_DYNAMIC, PT_LOAD, R_AARCH64_RELATIVE},
+    dynamic::DynIterator,
     endian::LittleEndian,
+    relocation::RelaIterator,
 };
 use hal::address::{Address, MemRange, PhysAddr, VirtAddr};
 use hal::arch::PAGE_SIZE;
+use heapless::Vec;
+use loader_protocol::{LoaderArg, VmmLayoutKind};
 
 #[repr(align(0x1000))]
 struct Aligned;
@@ -16,43 +20,63 @@ struct Aligned;
 static KERNEL_BIN: &[u8] = rtl::include_bytes_align_as!(Aligned, env!("KERNEL_PATH"));
 static mut KERNEL_EP: Option<usize> = None;
 
-// Maps kernel and returns address of page table base
-pub fn map_kernel(tt: &mut PageTable) {
+const MAX_SEGMENTS: usize = 8;
+
+/// Loaded segment: link address range and physical address of its copy
+struct Segment {
+    va: MemRange<VirtAddr>,
+    pa: PhysAddr,
+}
+
+/// Maps kernel into the Image region. Kernel is position independent, so it's copied into fresh
+/// pages and relocated to the randomized base
+pub fn map_kernel(tt: &mut PageTable, arg: &LoaderArg) {
     let elf =
         ElfBytes::<LittleEndian>::minimal_parse(KERNEL_BIN).expect("Failed to parse kernel elf");
-    let phys_base = KERNEL_BIN.as_ptr();
+    let (image_base, image_size) = arg.get_vmm_base(VmmLayoutKind::Image).unwrap();
+    let loads = || {
+        elf.segments()
+            .unwrap()
+            .into_iter()
+            .filter(|phdr| phdr.p_type == PT_LOAD)
+    };
 
-    unsafe { KERNEL_EP = Some(elf.ehdr.e_entry as usize) };
+    let link_base = loads().map(|x| x.p_vaddr as usize).min().unwrap() & !(PAGE_SIZE - 1);
+    let link_end = loads()
+        .map(|x| (x.p_vaddr + x.p_memsz) as usize)
+        .max()
+        .unwrap();
+    let slide = image_base.bits().wrapping_sub(link_base);
 
-    // TODO: Check that KERNEL_EP is indeed lies in Image range
+    assert!(link_end - link_base <= image_size);
 
-    for seg in elf
-        .segments()
-        .unwrap()
-        .into_iter()
-        .filter(|phdr| phdr.p_type == PT_LOAD)
-    {
-        let base = seg.p_vaddr;
-        let size = seg.p_memsz;
-        let mut virt_range = MemRange::new(VirtAddr::from_bits(base as usize), size as usize);
+    let mut segments = Vec::<Segment, MAX_SEGMENTS>::new();
 
-        virt_range.align_page();
+    for seg in loads() {
+        let mut va = MemRange::new(
+            VirtAddr::from_bits(seg.p_vaddr as usize),
+            seg.p_memsz as usize,
+        );
 
-        let mut phys_range = if seg.p_filesz != 0 {
-            MemRange::new(
-                PhysAddr::from_bits(unsafe { phys_base.add(seg.p_offset as usize) } as usize),
-                seg.p_filesz as usize,
-            )
-        } else {
-            MemRange::new(
-                alloc_pages(seg.p_memsz as usize / PAGE_SIZE).unwrap(),
-                seg.p_memsz as usize,
-            )
-        };
+        va.align_page();
 
-        phys_range.align_page();
+        let pages = va.size() / PAGE_SIZE;
+        let pa = alloc_pages(pages).expect("Failed to allocate memory for the kernel");
+        let data = &KERNEL_BIN[seg.p_offset as usize..(seg.p_offset + seg.p_filesz) as usize];
 
-        let perms = if seg.p_flags == PF_W | PF_R {
+        // MMU is off, so physical memory is accessed directly
+        unsafe {
+            core::ptr::write_bytes(pa.bits() as *mut u8, 0, va.size());
+            core::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                (pa.bits() + seg.p_vaddr as usize - va.start().bits()) as *mut u8,
+                data.len(),
+            );
+        }
+
+        let perms = if seg.p_flags & PF_W != 0 && seg.p_flags & PF_X != 0 {
+            panic!("Kernel segment at {:x} violates W^X", seg.p_vaddr);
+        } else if seg.p_flags == PF_W | PF_R {
             PagePerms::ReadWrite
         } else if seg.p_flags == PF_X | PF_R {
             PagePerms::Execute
@@ -62,10 +86,93 @@ pub fn map_kernel(tt: &mut PageTable) {
             panic!("Unknown elf permissions");
         };
 
-        tt.map_pages(virt_range, phys_range, perms, PageKind::Normal);
+        tt.map_pages(
+            MemRange::new(VirtAddr::from_bits(va.start() + slide), va.size()),
+            MemRange::new(pa, va.size()),
+            perms,
+            PageKind::Normal,
+        );
+
+        segments
+            .push(Segment { va, pa })
+            .unwrap_or_else(|_| panic!("Too many kernel segments"));
+    }
+
+    relocate(&elf, &segments, slide);
+
+    unsafe { KERNEL_EP = Some(elf.ehdr.e_entry as usize + slide) };
+
+    info!("Mapped kernel image at {:x}\n", image_base);
+}
+
+/// Bytes of the kernel file at link address `va`
+fn file_bytes(elf: &ElfBytes<LittleEndian>, va: usize, size: usize) -> &'static [u8] {
+    let seg = elf
+        .segments()
+        .unwrap()
+        .into_iter()
+        .filter(|x| x.p_type == PT_LOAD)
+        .find(|x| va >= x.p_vaddr as usize && va + size <= x.p_vaddr as usize + x.p_filesz as usize)
+        .expect("Address is outside of the kernel file");
+    let offset = seg.p_offset as usize + va - seg.p_vaddr as usize;
+
+    &KERNEL_BIN[offset..offset + size]
+}
+
+/// Applies relative relocations to the loaded copy of the kernel. They are found through
+/// `PT_DYNAMIC`, since section headers are not needed to load the kernel and may be stripped
+fn relocate(elf: &ElfBytes<LittleEndian>, segments: &[Segment], slide: usize) {
+    let mut rela = None;
+    let mut rela_size = 0;
+
+    if let Some(dynamic) = elf
+        .segments()
+        .unwrap()
+        .into_iter()
+        .find(|x| x.p_type == PT_DYNAMIC)
+    {
+        let start = dynamic.p_offset as usize;
+        let data = &KERNEL_BIN[start..start + dynamic.p_filesz as usize];
+
+        for entry in DynIterator::new(LittleEndian, elf.ehdr.class, data) {
+            match entry.d_tag {
+                DT_RELA => rela = Some(entry.d_ptr() as usize),
+                DT_RELASZ => rela_size = entry.d_val() as usize,
+                _ => {}
+            }
+        }
     }
 
-    info!("Mapped kernel image\n");
+    let Some(rela) = rela.filter(|_| rela_size != 0) else {
+        // Absolute addresses would point to the link address
+        assert!(
+            slide == 0,
+            "Kernel has no relocations, so it can't be moved"
+        );
+        return;
+    };
+
+    let relas = RelaIterator::new(
+        LittleEndian,
+        elf.ehdr.class,
+        file_bytes(elf, rela, rela_size),
+    );
+
+    for rela in relas {
+        assert_eq!(
+            rela.r_type, R_AARCH64_RELATIVE,
+            "Unsupported kernel relocation"
+        );
+
+        let target = VirtAddr::from_bits(rela.r_offset as usize);
+        let seg = segments
+            .iter()
+            .find(|x| x.va.contains_addr(target))
+            .expect("Relocation outside of the kernel image");
+        let pa = seg.pa.bits() + (target.bits() - seg.va.start().bits());
+
+        unsafe { (pa as *mut usize).write_unaligned((rela.r_addend as usize).wrapping_add(slide)) };
+    }
 }
 
 pub fn kernel_ep() -> VirtAddr {
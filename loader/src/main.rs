@@ -14,6 +14,8 @@ mod drivers;
 mod kernel;
 mod mm;
 mod protocol;
+mod random;
+mod smp;
 
 #[macro_use]
 extern crate log as log_other;
@@ -26,13 +28,25 @@ extern "C" fn main(fdt_base: PhysAddr) {
     drivers::uart::probe(&fdt);
     log::init();
 
-    let mut tt = mm::init(&fdt, fdt_base);
-    kernel::map_kernel(&mut tt);
+    let seed = random::seed(&fdt);
+    let kaslr = random::kaslr_enabled(&fdt);
 
-    mm::layout::init_layout(&mut protocol);
+    if kaslr && seed.is_none() {
+        warn!("No rng-seed or kaslr-seed in /chosen, KASLR is seeded from the timer\n");
+    }
+
+    let mut rng = random::Rng::new(seed.unwrap_or_else(random::timer_seed));
+
+    mm::layout::init_layout(&mut protocol, kaslr.then_some(&mut rng));
+    protocol.rng_seed = rng.next_u64();
+    protocol.aslr = random::aslr_enabled(&fdt);
+
+    let mut tt = mm::init(&fdt, fdt_base, &protocol);
+    kernel::map_kernel(&mut tt, &protocol);
     mm::linear_map::map_linear(&mut tt, &protocol);
 
     drivers::map(&fdt, &mut protocol);
+    smp::probe(&fdt, &mut protocol);
     let arg0 = protocol::prepare(fdt_base, &fdt, protocol, &mut tt);
 
     arch::boot::boot(kernel::kernel_ep().bits(), arg0.bits(), tt.base().bits());
@@ -1,7 +1,9 @@
 /// Virtual layout for the kernel
+use crate::random::Rng;
 use loader_protocol::{LoaderArg, VmmLayoutEntry, VmmLayoutKind};
 
-pub static KERNEL_LAYOUT: [VmmLayoutEntry; VmmLayoutKind::Count as usize] = [
+/// Layout used when randomization is disabled
+static KERNEL_LAYOUT: [VmmLayoutEntry; VmmLayoutKind::Count as usize] = [
     VmmLayoutEntry {
         base: 0xFFFF700000000000,
         size: 0x100000000000,
@@ -49,6 +51,51 @@ pub static KERNEL_LAYOUT: [VmmLayoutEntry; VmmLayoutKind::Count as usize] = [
     },
 ];
 
-pub fn init_layout(arg: &mut LoaderArg) {
-    arg.vmm_layout.extend(KERNEL_LAYOUT.clone());
+/// Kernel half of the address space is split into slots, each kernel region gets random one
+const KERNEL_HALF: usize = 0xFFFF000000000000;
+const SLOT_SIZE: usize = 0x100000000000;
+const SLOT_COUNT: usize = 16;
+
+/// Randomized regions are smaller than slots to leave room for the offset
+const REGION_SIZE: usize = SLOT_SIZE / 2;
+const REGION_ALIGN: usize = 1 << 30;
+
+/// Alignment of the kernel image within its region, so it can be mapped with blocks
+const IMAGE_ALIGN: usize = 1 << 21;
+
+/// Fills layout of the kernel address space. With `rng` all regions except user one are
+/// placed randomly
+pub fn init_layout(arg: &mut LoaderArg, rng: Option<&mut Rng>) {
+    let Some(rng) = rng else {
+        arg.vmm_layout.extend(KERNEL_LAYOUT.clone());
+        return;
+    };
+
+    let mut slots: [usize; SLOT_COUNT] = core::array::from_fn(|i| i);
+
+    // Fisher-Yates shuffle
+    for i in (1..SLOT_COUNT).rev() {
+        slots.swap(i, rng.next_below(i + 1));
+    }
+
+    // Layout is indexed by kind, so order must be preserved
+    for (entry, slot) in KERNEL_LAYOUT.iter().zip(slots) {
+        let align = match entry.kind {
+            VmmLayoutKind::User => {
+                arg.vmm_layout.push(entry.clone()).unwrap();
+                continue;
+            }
+            VmmLayoutKind::Image => IMAGE_ALIGN,
+            _ => REGION_ALIGN,
+        };
+        let offset = rng.next_below(REGION_SIZE / align) * align;
+
+        arg.vmm_layout
+            .push(VmmLayoutEntry {
+                base: KERNEL_HALF + slot * SLOT_SIZE + offset,
+                size: REGION_SIZE,
+                kind: entry.kind.clone(),
+            })
+            .unwrap();
+    }
 }
@@ -10,7 +10,7 @@ pub fn map_linear(table: &mut PageTable, prot: &LoaderArg) {
 
     assert!(size >= res.count * PAGE_SIZE);
 
-    table.map_pages(
+    table.map_blocks(
         MemRange::new(
             VirtAddr::from_bits(base.bits() + res.start.bits()),
             res.count * PAGE_SIZE,
@@ -1,5 +1,6 @@
 use fdt::Fdt;
 use hal::address::{Address, MemRange, PhysAddr, VirtAddr};
+use loader_protocol::LoaderArg;
 use page_table::{PageKind, PagePerms, PageTable};
 use rtl::linker_var;
 
@@ -10,7 +11,7 @@ pub mod page_array;
 pub mod page_table;
 pub mod regions;
 
-pub fn init(fdt: &Fdt, fdt_base: PhysAddr) -> PageTable {
+pub fn init(fdt: &Fdt, fdt_base: PhysAddr, arg: &LoaderArg) -> PageTable {
     regions::init(fdt, fdt_base);
 
     let mut table = PageTable::new().expect("Failed to create a page table");
@@ -20,7 +21,7 @@ pub fn init(fdt: &Fdt, fdt_base: PhysAddr) -> PageTable {
     map_self_stack(&mut table);
 
     // Prepare page array
-    page_array::init(&mut table);
+    page_array::init(&mut table, arg);
     table
 }
 
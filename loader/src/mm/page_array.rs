@@ -1,27 +1,22 @@
 use super::MemRange;
-use super::layout::KERNEL_LAYOUT;
 use super::page_table::{PageKind, PagePerms, PageTable};
 use super::regions::whole_ram;
 use core::mem::size_of;
-use hal::address::{Address, VirtAddr};
 use hal::arch::PAGE_SIZE;
 use hal::page::Page;
-use loader_protocol::VmmLayoutKind;
+use loader_protocol::{LoaderArg, VmmLayoutKind};
 
-pub fn init(tt: &mut PageTable) {
+pub fn init(tt: &mut PageTable, arg: &LoaderArg) {
     let ram = whole_ram();
     let pages_to_alloc = (size_of::<Page>() * ram.count).next_multiple_of(PAGE_SIZE) / PAGE_SIZE;
 
-    let regions = &KERNEL_LAYOUT[VmmLayoutKind::PageArray as usize];
+    let (base, size) = arg.get_vmm_base(VmmLayoutKind::PageArray).unwrap();
     let pages =
         super::alloc::alloc_pages(pages_to_alloc).expect("Failed to allocate pages for page array");
 
-    assert!(regions.size >= pages_to_alloc * PAGE_SIZE);
+    assert!(size >= pages_to_alloc * PAGE_SIZE);
 
-    let va_range = MemRange::new(
-        VirtAddr::from_bits(regions.base),
-        pages_to_alloc * PAGE_SIZE,
-    );
+    let va_range = MemRange::new(base, pages_to_alloc * PAGE_SIZE);
     let pa_range = MemRange::new(pages, pages_to_alloc * PAGE_SIZE);
 
     tt.map_pages(va_range, pa_range, PagePerms::ReadWrite, PageKind::Normal);
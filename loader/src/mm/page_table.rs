@@ -34,6 +34,7 @@ impl PageTable {
         perms: PagePerms,
         kind: PageKind,
         lvl: usize,
+        blocks: bool,
     ) {
         let order = lvl_to_order(lvl);
         let size = 1 << order;
@@ -41,18 +42,30 @@ impl PageTable {
         while {
             let idx = va_to_index(va.start(), lvl);
             let pte = unsafe { base.add(idx).read() };
+            // Level 0 entries can't be blocks
+            let block = blocks
+                && lvl != 0
+                && va.start().is_aligned(order)
+                && pa.start().is_aligned(order)
+                && va.size() >= size;
 
-            if lvl != PAGE_TABLE_LAST_LVL {
+            if lvl != PAGE_TABLE_LAST_LVL && block && !pte.is_valid() {
+                let pte = Pte::make_block(pa.start(), perms, kind);
+
+                unsafe { base.add(idx).write(pte) };
+                pa.truncate(size);
+                va.truncate(size);
+            } else if lvl != PAGE_TABLE_LAST_LVL {
                 if pte.is_valid() {
                     let next = pte.pa().bits() as *mut _;
-                    Self::map_lvl(next, va, pa, perms, kind, lvl + 1);
+                    Self::map_lvl(next, va, pa, perms, kind, lvl + 1, blocks);
                 } else {
                     let next = alloc_pages(1).expect("Failed to allocate memory for page table");
                     let next_pte = Pte::new_non_leaf(next);
 
                     unsafe { base.add(idx).write(next_pte) };
 
-                    Self::map_lvl(next.bits() as *mut _, va, pa, perms, kind, lvl + 1);
+                    Self::map_lvl(next.bits() as *mut _, va, pa, perms, kind, lvl + 1, blocks);
                 }
             } else {
                 if pte.is_valid() {
@@ -73,11 +86,33 @@ impl PageTable {
     }
 
     pub fn map_pages(
+        &mut self,
+        va: MemRange<VirtAddr>,
+        pa: MemRange<PhysAddr>,
+        perms: PagePerms,
+        kind: PageKind,
+    ) {
+        self.map(va, pa, perms, kind, false)
+    }
+
+    /// Same as [`Self::map_pages`], but uses 2 MiB and 1 GiB blocks, where alignment allows
+    pub fn map_blocks(
+        &mut self,
+        va: MemRange<VirtAddr>,
+        pa: MemRange<PhysAddr>,
+        perms: PagePerms,
+        kind: PageKind,
+    ) {
+        self.map(va, pa, perms, kind, true)
+    }
+
+    fn map(
         &mut self,
         mut va: MemRange<VirtAddr>,
         mut pa: MemRange<PhysAddr>,
         perms: PagePerms,
         kind: PageKind,
+        blocks: bool,
     ) {
         debug_assert_eq!(va.size(), pa.size());
         debug_assert_eq!(va.size().next_multiple_of(PAGE_SIZE), va.size());
@@ -85,7 +120,7 @@ impl PageTable {
         debug_assert!(va.start().is_page_aligned());
         debug_assert!(pa.start().is_page_aligned());
 
-        Self::map_lvl(self.base, &mut va, &mut pa, perms, kind, 0)
+        Self::map_lvl(self.base, &mut va, &mut pa, perms, kind, 0, blocks)
     }
 
     pub fn base(&self) -> PhysAddr {
@@ -0,0 +1,69 @@
+//! Boot time entropy
+
+use aarch64_cpu::registers::{CNTPCT_EL0, Readable};
+use fdt::Fdt;
+
+/// Seed passed by the bootloader in `/chosen`
+pub fn seed(fdt: &Fdt) -> Option<u64> {
+    let chosen = fdt.find_node("/chosen")?;
+
+    ["rng-seed", "kaslr-seed"]
+        .into_iter()
+        .filter_map(|name| chosen.property(name))
+        .flat_map(|prop| prop.value.chunks(8))
+        .fold(None, |seed: Option<u64>, chunk| {
+            let mut bytes = [0; 8];
+
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            Some(seed.unwrap_or(0).rotate_left(17) ^ u64::from_be_bytes(bytes))
+        })
+}
+
+/// Fallback seed for boards without bootloader one. Timer counter at boot is easy to guess
+pub fn timer_seed() -> u64 {
+    CNTPCT_EL0.get()
+}
+
+/// Checks if `arg` is passed in `bootargs`
+fn has_arg(fdt: &Fdt, arg: &str) -> bool {
+    let bootargs = fdt
+        .find_node("/chosen")
+        .and_then(|chosen| chosen.property("bootargs"))
+        .and_then(|prop| prop.as_str());
+
+    bootargs.is_some_and(|args| args.split_whitespace().any(|x| x == arg))
+}
+
+/// Randomization of user mappings is on, unless `noaslr` is passed on the command line
+pub fn aslr_enabled(fdt: &Fdt) -> bool {
+    !has_arg(fdt, "noaslr")
+}
+
+/// Randomization of kernel layout is on, unless `nokaslr` is passed on the command line
+pub fn kaslr_enabled(fdt: &Fdt) -> bool {
+    !has_arg(fdt, "nokaslr")
+}
+
+/// SplitMix64 generator
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.0;
+
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Random number in `0..bound`
+    pub fn next_below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+}
@@ -0,0 +1,44 @@
+//! CPUs described by FDT
+
+use aarch64_cpu::registers::{MPIDR_EL1, Readable};
+use fdt::Fdt;
+use loader_protocol::{LoaderArg, MAX_CPUS, PsciMethod};
+
+const MPIDR_HWID_MASK: usize = 0xff00ffffff;
+
+unsafe extern "C" {
+    fn secondary_entry();
+}
+
+/// Fills MPIDRs of CPUs with the boot one first, so kernel numbers it as CPU 0
+pub fn probe(fdt: &Fdt, arg: &mut LoaderArg) {
+    let boot = MPIDR_EL1.get() as usize & MPIDR_HWID_MASK;
+
+    arg.cpus.push(boot).unwrap();
+
+    for cpu in fdt.cpus() {
+        let mpidr = cpu.ids().first() & MPIDR_HWID_MASK;
+
+        if mpidr == boot {
+            continue;
+        }
+
+        if arg.cpus.push(mpidr).is_err() {
+            warn!("Only {MAX_CPUS} CPUs are supported\n");
+            break;
+        }
+    }
+
+    arg.psci = match fdt
+        .find_node("/psci")
+        .and_then(|psci| psci.property("method"))
+        .and_then(|prop| prop.as_str())
+    {
+        Some("hvc") => PsciMethod::Hvc,
+        Some("smc") => PsciMethod::Smc,
+        _ => PsciMethod::None,
+    };
+    arg.secondary_entry = secondary_entry as usize;
+
+    info!("Found {} CPUs, PSCI {:?}\n", arg.cpus.len(), arg.psci);
+}
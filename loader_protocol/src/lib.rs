@@ -6,6 +6,7 @@ use heapless::Vec;
 pub const MAX_DEVICES: usize = 10;
 pub const MAX_VMM_REGIONS: usize = 10;
 pub const MAX_PMM_REGIONS: usize = 10;
+pub const MAX_CPUS: usize = 8;
 
 #[derive(Debug, PartialEq)]
 pub enum DeviceKind {
@@ -36,6 +37,29 @@ pub struct DeviceMapping {
     pub kind: DeviceKind,
 }
 
+/// Firmware call used to start secondary CPUs
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum PsciMethod {
+    #[default]
+    None,
+    Hvc,
+    Smc,
+}
+
+/// Passed to secondary CPU entry as PSCI context. Secondary CPU loads system registers from it,
+/// enables MMU and jumps to `entry` with `arg` in x0
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct SecondaryBoot {
+    pub ttbr: u64,
+    pub tcr: u64,
+    pub mair: u64,
+    pub sctlr: u64,
+    pub entry: u64,
+    pub stack: u64,
+    pub arg: u64,
+}
+
 #[derive(Debug, Clone)]
 pub struct VmmLayoutEntry {
     pub base: usize,
@@ -52,6 +76,16 @@ pub struct LoaderArg {
     pub devices: Vec<DeviceMapping, MAX_DEVICES>,
     pub vmm_layout: Vec<VmmLayoutEntry, MAX_VMM_REGIONS>,
     pub pmm_layout: Vec<MemRange<PhysAddr>, MAX_PMM_REGIONS>,
+    /// Boot entropy from the FDT or the timer counter
+    pub rng_seed: u64,
+    /// Randomize placement of user mappings
+    pub aslr: bool,
+    /// MPIDRs of CPUs found in `/cpus`. Boot CPU is the first one
+    pub cpus: Vec<usize, MAX_CPUS>,
+    pub psci: PsciMethod,
+    /// Physical address of secondary CPU entry. It expects physical address of
+    /// [`SecondaryBoot`] in x0
+    pub secondary_entry: usize,
 }
 
 impl LoaderArg {
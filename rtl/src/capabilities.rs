@@ -0,0 +1,66 @@
+use super::error::ErrorType;
+use bitmask::bitmask;
+
+bitmask! {
+    pub mask CapabilityBits: u32 where flags Capability {
+        None = 0,
+
+        // Ports
+        Send = (1 << 0),
+        Receive = (1 << 1),
+        Call = (1 << 2),
+
+        // Wait
+        Wait = (1 << 4),
+
+        // VMO
+        GetPhysInfo = (1 << 5),
+
+        // Generic object access
+        Read = (1 << 6),
+        Write = (1 << 7),
+        Execute = (1 << 8),
+        Map = (1 << 9),
+        Signal = (1 << 10),
+
+        // Factory
+        Create = (1 << 11),
+
+        // Task
+        Manage = (1 << 12),
+
+        // Handle operations
+        Transfer = (1 << 13),
+        Duplicate = (1 << 14),
+
+        // Resource
+        RealTime = (1 << 15),
+        MemInfo = (1 << 16),
+        MapPhys = (1 << 3),
+    }
+}
+
+/// Passed to CloneHandle to keep rights of the original handle
+pub const SAME_RIGHTS: usize = usize::MAX;
+
+const MAX_CAPABILITY_BIT: usize = 17;
+
+impl CapabilityBits {
+    pub fn bits(&self) -> usize {
+        self.mask as usize
+    }
+}
+
+impl TryFrom<usize> for CapabilityBits {
+    type Error = ErrorType;
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        let max_set = core::mem::size_of::<usize>() * 8 - value.leading_zeros() as usize;
+
+        if max_set > MAX_CAPABILITY_BIT {
+            Err(ErrorType::InvalidArgument)
+        } else {
+            Ok(CapabilityBits { mask: value as u32 })
+        }
+    }
+}
@@ -15,6 +15,8 @@ pub enum ErrorType {
     BufferTooSmall = 12,
     BufferTooBig = 13,
     WouldBlock = 14,
+    AccessDenied = 15,
+    TimedOut = 16,
 }
 
 impl From<ErrorType> for &str {
@@ -26,6 +28,9 @@ impl From<ErrorType> for &str {
             ErrorType::AlreadyExists => "already exists",
             ErrorType::NotFound => "not found",
             ErrorType::InvalidArgument => "invalid argument",
+            ErrorType::AccessDenied => "access denied",
+            ErrorType::TaskDead => "peer is dead",
+            ErrorType::TimedOut => "timed out",
             _ => todo!(),
         }
     }
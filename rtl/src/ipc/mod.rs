@@ -2,6 +2,17 @@ use crate::handle::*;
 
 pub const IPC_MAX_HANDLES: usize = 5;
 
+/// Payloads up to this size are copied without heap allocation in the kernel
+pub const IPC_INLINE_SIZE: usize = 64;
+
+/// Maximum number of data segments in each direction
+pub const IPC_MAX_IOVECS: usize = 4;
+
+/// Payloads above this size are copied into fresh pages, which are mapped into the receiver
+/// instead of being written to the receive buffers. Receiver finds them in
+/// [`IpcMessage::mapped_arena`] and must unmap them when done.
+pub const IPC_MAP_THRESHOLD: usize = 16 * 1024;
+
 // Note: I want to keep Copy marker here, so I had to lie about
 // mutablity of arena slices.
 #[derive(Debug, Clone, Copy)]
@@ -9,8 +20,11 @@ pub const IPC_MAX_HANDLES: usize = 5;
 pub struct IpcMessage<'a> {
     handles: [Handle; IPC_MAX_HANDLES],
     num_handles: usize,
-    pub in_data: Option<&'a [u8]>,
-    pub out_data: Option<&'a [u8]>,
+    in_iov: [&'a [u8]; IPC_MAX_IOVECS],
+    num_in_iov: usize,
+    out_iov: [&'a [u8]; IPC_MAX_IOVECS],
+    num_out_iov: usize,
+    mapped: Option<&'a [u8]>,
     reply_port: Handle,
 }
 
@@ -18,8 +32,11 @@ impl<'a> IpcMessage<'a> {
     const DEFAULT: Self = Self {
         handles: [HANDLE_INVALID; IPC_MAX_HANDLES],
         num_handles: 0,
-        in_data: None,
-        out_data: None,
+        in_iov: [&[]; IPC_MAX_IOVECS],
+        num_in_iov: 0,
+        out_iov: [&[]; IPC_MAX_IOVECS],
+        num_out_iov: 0,
+        mapped: None,
         reply_port: HANDLE_INVALID,
     };
 
@@ -27,6 +44,13 @@ impl<'a> IpcMessage<'a> {
         Self::DEFAULT
     }
 
+    /// Checks counts, which may come from untrusted memory, before anything is sliced by them
+    pub fn is_valid(&self) -> bool {
+        self.num_handles <= IPC_MAX_HANDLES
+            && self.num_in_iov <= IPC_MAX_IOVECS
+            && self.num_out_iov <= IPC_MAX_IOVECS
+    }
+
     pub fn handles(&self) -> &[HandleBase] {
         &self.handles[..self.num_handles]
     }
@@ -35,18 +59,28 @@ impl<'a> IpcMessage<'a> {
         &mut self.handles[..self.num_handles]
     }
 
+    /// First receive buffer
     pub fn in_arena(&self) -> Option<&[u8]> {
-        self.in_data
+        self.in_iovecs().first().copied()
     }
 
-    pub fn out_arena(&mut self) -> Option<&mut [u8]> {
-        if let Some(d) = self.out_data {
-            Some(unsafe {
-                core::slice::from_raw_parts_mut(d.as_ptr() as usize as *mut u8, d.len())
-            })
-        } else {
-            None
-        }
+    /// Receive buffers. Payload is scattered across them in order
+    pub fn in_iovecs(&self) -> &[&'a [u8]] {
+        &self.in_iov[..self.num_in_iov]
+    }
+
+    /// Send buffers. Payload is gathered from them in order
+    pub fn out_iovecs(&self) -> &[&'a [u8]] {
+        &self.out_iov[..self.num_out_iov]
+    }
+
+    /// Large payload mapped into the address space by the kernel
+    pub fn mapped_arena(&self) -> Option<&'a [u8]> {
+        self.mapped
+    }
+
+    pub fn set_mapped_arena(&mut self, data: &'a [u8]) {
+        self.mapped = Some(data);
     }
 
     pub fn reply_port(&self) -> HandleBase {
@@ -58,12 +92,29 @@ impl<'a> IpcMessage<'a> {
     }
 
     pub fn set_in_arena(&mut self, data: &'a [u8]) {
-        // assert!(self.in_data.is_none());
-        self.in_data = Some(data);
+        self.num_in_iov = 0;
+        self.add_in_iovec(data);
     }
 
     pub fn set_out_arena(&mut self, data: &'a [u8]) {
-        self.out_data = Some(data);
+        self.num_out_iov = 0;
+        self.add_out_iovec(data);
+    }
+
+    pub fn add_in_iovec(&mut self, data: &'a [u8]) {
+        // TODO: that API should not cause a crash
+        assert!(self.num_in_iov != IPC_MAX_IOVECS);
+
+        self.in_iov[self.num_in_iov] = data;
+        self.num_in_iov += 1;
+    }
+
+    pub fn add_out_iovec(&mut self, data: &'a [u8]) {
+        // TODO: that API should not cause a crash
+        assert!(self.num_out_iov != IPC_MAX_IOVECS);
+
+        self.out_iov[self.num_out_iov] = data;
+        self.num_out_iov += 1;
     }
 
     pub fn add_handle(&mut self, h: Handle) -> usize {
@@ -77,9 +128,6 @@ impl<'a> IpcMessage<'a> {
     }
 
     pub fn add_handles(&mut self, h: &[Handle]) {
-        // TODO: that API should not cause a crash
-        assert!(self.num_handles != IPC_MAX_HANDLES);
-
         for i in h {
             self.add_handle(*i);
         }
@@ -0,0 +1,36 @@
+/// Value of a [`JobLimits`] field, which means "no limit"
+pub const JOB_UNLIMITED: usize = usize::MAX;
+
+/// Resource limits of a job. Usage of a job is also charged to all of its ancestors, so the
+/// effective limit is the smallest one along the chain.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct JobLimits {
+    /// Committed physical pages
+    pub pages: usize,
+    /// Handles in handle tables of job's tasks
+    pub handles: usize,
+    /// Bytes of sent, but not yet received IPC messages
+    pub ipc_bytes: usize,
+    pub threads: usize,
+    /// Handles in the handle table of each task of the job
+    pub task_handles: usize,
+}
+
+impl JobLimits {
+    pub const fn unlimited() -> Self {
+        Self {
+            pages: JOB_UNLIMITED,
+            handles: JOB_UNLIMITED,
+            ipc_bytes: JOB_UNLIMITED,
+            threads: JOB_UNLIMITED,
+            task_handles: JOB_UNLIMITED,
+        }
+    }
+}
+
+impl Default for JobLimits {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
@@ -5,12 +5,16 @@
 
 extern crate static_assertions;
 
+pub mod capabilities;
 pub mod error;
 pub mod irq;
 pub mod handle;
 pub mod ipc;
+pub mod job;
 pub mod locking;
+pub mod meminfo;
 pub mod misc;
+pub mod sched;
 pub mod signal;
 pub mod syscalls;
 pub mod vmm;
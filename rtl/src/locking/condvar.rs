@@ -0,0 +1,65 @@
+use super::futex::Futex;
+use super::mutex::MutexGuard;
+use crate::error::ErrorType;
+use core::{
+    marker::PhantomData,
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+/// Condition variable to be used together with [`super::mutex::Mutex`]
+pub struct Condvar<F: Futex> {
+    // Bumped on each notification, so waiter can detect that it missed one
+    seq: AtomicU32,
+    _futex: PhantomData<F>,
+}
+
+impl<F: Futex> Condvar<F> {
+    pub const fn new() -> Self {
+        Self {
+            seq: AtomicU32::new(0),
+            _futex: PhantomData,
+        }
+    }
+
+    /// Atomically releases the mutex and blocks until notified. Spurious wake ups are
+    /// possible, so condition must be rechecked by the caller.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T, F>) -> MutexGuard<'a, T, F> {
+        self.wait_timeout(guard, None).0
+    }
+
+    /// Same as [`Self::wait`], but gives up after `timeout`. Second value is `true` if
+    /// timeout has expired.
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T, F>,
+        timeout: Option<Duration>,
+    ) -> (MutexGuard<'a, T, F>, bool) {
+        let seq = self.seq.load(Ordering::Relaxed);
+        let mutex = guard.mutex();
+
+        drop(guard);
+        let res = F::wait(&self.seq, seq, timeout);
+
+        (mutex.lock(), matches!(res, Err(ErrorType::TimedOut)))
+    }
+
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        let _ = F::wake(&self.seq, 1);
+    }
+
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        let _ = F::wake(&self.seq, usize::MAX);
+    }
+}
+
+impl<F: Futex> Default for Condvar<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<F: Futex> Send for Condvar<F> {}
+unsafe impl<F: Futex> Sync for Condvar<F> {}
@@ -0,0 +1,14 @@
+use crate::error::ErrorType;
+use core::sync::atomic::AtomicU32;
+use core::time::Duration;
+
+/// Wait/wake primitives blocking locks are built on. Implemented by whoever can issue
+/// syscalls, since rtl itself can't.
+pub trait Futex {
+    /// Blocks while `word` equals to `expected`. Returns [`ErrorType::TryAgain`] if value has
+    /// already changed and [`ErrorType::TimedOut`] if `timeout` has expired.
+    fn wait(word: &AtomicU32, expected: u32, timeout: Option<Duration>) -> Result<(), ErrorType>;
+
+    /// Wakes up to `count` threads blocked on `word`. Returns number of woken threads.
+    fn wake(word: &AtomicU32, count: usize) -> Result<usize, ErrorType>;
+}
@@ -1,2 +1,5 @@
+pub mod condvar;
 pub mod fakelock;
+pub mod futex;
+pub mod mutex;
 pub mod spinlock;
@@ -0,0 +1,94 @@
+use super::futex::Futex;
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+// Locked and somebody may sleep in the kernel waiting for it
+const CONTENDED: u32 = 2;
+
+/// Blocking mutex. Uncontended lock/unlock never enter the kernel.
+pub struct Mutex<T, F: Futex> {
+    state: AtomicU32,
+    val: UnsafeCell<T>,
+    _futex: PhantomData<F>,
+}
+
+pub struct MutexGuard<'a, T, F: Futex> {
+    lock: &'a Mutex<T, F>,
+}
+
+impl<T, F: Futex> Mutex<T, F> {
+    pub const fn new(val: T) -> Self {
+        Self {
+            state: AtomicU32::new(UNLOCKED),
+            val: UnsafeCell::new(val),
+            _futex: PhantomData,
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T, F> {
+        if self
+            .state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            self.lock_contended();
+        }
+
+        MutexGuard { lock: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T, F>> {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { lock: self })
+    }
+
+    fn lock_contended(&self) {
+        // Once we have been waiting, others may wait too, so lock is taken as contended
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            let _ = F::wait(&self.state, CONTENDED, None);
+        }
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            let _ = F::wake(&self.state, 1);
+        }
+    }
+}
+
+impl<'a, T, F: Futex> MutexGuard<'a, T, F> {
+    pub(super) fn mutex(&self) -> &'a Mutex<T, F> {
+        self.lock
+    }
+}
+
+impl<'a, T, F: Futex> Deref for MutexGuard<'a, T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.val.get() }
+    }
+}
+
+impl<'a, T, F: Futex> DerefMut for MutexGuard<'a, T, F> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.val.get() }
+    }
+}
+
+impl<'a, T, F: Futex> Drop for MutexGuard<'a, T, F> {
+    fn drop(&mut self) {
+        self.lock.unlock();
+    }
+}
+
+unsafe impl<T: Send, F: Futex> Send for Mutex<T, F> {}
+unsafe impl<T: Send, F: Futex> Sync for Mutex<T, F> {}
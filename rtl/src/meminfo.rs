@@ -0,0 +1,45 @@
+//! Memory statistics reported by `MemInfo` syscall
+
+/// Number of kernel slab size classes
+pub const SLAB_CLASSES: usize = 12;
+
+/// Task names longer than this are truncated
+pub const TASK_NAME_LEN: usize = 32;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlabInfo {
+    /// Object size of the class
+    pub size: usize,
+    /// Objects in use
+    pub used: usize,
+    /// Pages backing the class
+    pub pages: usize,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemInfo {
+    /// Pages managed by the page allocator
+    pub total_pages: usize,
+    pub free_pages: usize,
+    /// Free pages cached by CPUs. Included into `free_pages`
+    pub cached_pages: usize,
+    pub slabs: [SlabInfo; SLAB_CLASSES],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TaskMemInfo {
+    pub id: u32,
+    pub name: [u8; TASK_NAME_LEN],
+    pub name_len: usize,
+    /// Pages committed to VMOs mapped by the task. Shared pages are counted by every task
+    pub pages: usize,
+}
+
+impl TaskMemInfo {
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("?")
+    }
+}
@@ -0,0 +1,29 @@
+//! Scheduling parameters of threads set by `ThreadSetPriority`
+
+/// Number of priorities in each class. Higher value means higher priority
+pub const PRIORITY_LEVELS: usize = 32;
+
+/// Priority of newly created threads
+pub const PRIORITY_DEFAULT: usize = 16;
+
+#[repr(usize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SchedClass {
+    /// Threads share CPU round-robin. Priority defines length of the time slice
+    Fair = 0,
+    /// Threads run before any fair thread, higher priority first. Thread which uses up its
+    /// time slice without blocking is scheduled as a fair one, until it blocks
+    RealTime = 1,
+}
+
+impl TryFrom<usize> for SchedClass {
+    type Error = ();
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        match value {
+            _ if value == Self::Fair as usize => Ok(Self::Fair),
+            _ if value == Self::RealTime as usize => Ok(Self::RealTime),
+            _ => Err(()),
+        }
+    }
+}
@@ -3,24 +3,40 @@ use super::handle::Handle;
 use bitmask::bitmask;
 
 bitmask! {
-    pub mask Signals: u8 where flags Signal {
+    pub mask Signals: u16 where flags Signal {
         None = 0,
         MessageReady = (1 << 0),
         TimerReady = (1 << 1),
         IrqReady = (1 << 2),
+        TaskTerminated = (1 << 3),
+        ThreadTerminated = (1 << 4),
+        PeerClosed = (1 << 5),
+
+        // Meaning is up to user space. Can be set and cleared on events with SignalObject
+        User0 = (1 << 8),
+        User1 = (1 << 9),
+        User2 = (1 << 10),
+        User3 = (1 << 11),
+        User4 = (1 << 12),
+        User5 = (1 << 13),
+        User6 = (1 << 14),
+        User7 = (1 << 15),
     }
 }
 
+/// All user-defined signals
+pub const USER_SIGNALS: Signals = Signals { mask: 0xff00 };
+
 impl TryFrom<usize> for Signals {
     type Error = ErrorType;
 
     fn try_from(value: usize) -> Result<Self, Self::Error> {
         let max_set = core::mem::size_of::<usize>() * 8 - value.leading_zeros() as usize;
 
-        if max_set > 3 {
+        if max_set > 16 {
             Err(ErrorType::InvalidArgument)
         } else {
-            Ok(Signals { mask: value as u8 })
+            Ok(Signals { mask: value as u16 })
         }
     }
 }
@@ -31,6 +47,8 @@ impl Default for Signals {
     }
 }
 
+/// Entry of `WaitObjectMany`. Wait on the entry completes once any of `waitfor` signals is set,
+/// not all of them. Signals found set are reported in `pendind`
 #[repr(C)]
 pub struct WaitEntry {
     pub handle: Handle,
@@ -1,5 +1,9 @@
 use core::mem::variant_count;
 
+/// Deadline argument of blocking syscalls, which never expires. Other values are absolute
+/// time since boot in nanoseconds, same clock as `ClockGet`.
+pub const DEADLINE_INFINITE: usize = usize::MAX;
+
 #[repr(usize)]
 #[derive(Debug, Copy, Clone)]
 pub enum SyscallList {
@@ -29,6 +33,28 @@ pub enum SyscallList {
     AckIrq = 23,
     CreateTimer = 24,
     TimerArm = 25,
+    RevokeHandle = 26,
+    Exit = 27,
+    TaskKill = 28,
+    TaskExitCode = 29,
+    CreateThread = 30,
+    ThreadStart = 31,
+    ThreadExit = 32,
+    PortReplyWait = 33,
+    ClockGet = 34,
+    CreateEvent = 35,
+    CreateEventPair = 36,
+    SignalObject = 37,
+    FutexWait = 38,
+    FutexWake = 39,
+    CreateJob = 40,
+    VmoCreateChild = 41,
+    CreatePagerVmo = 42,
+    VmoSupplyPages = 43,
+    VmProtect = 44,
+    MemInfo = 45,
+    ThreadSetPriority = 46,
+    ThreadSetAffinity = 47,
 }
 
 impl TryFrom<usize> for SyscallList {
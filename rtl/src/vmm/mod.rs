@@ -1,58 +1,144 @@
+/// Memory type of a mapping
 #[repr(usize)]
-#[derive(Debug, Copy, Clone)]
-pub enum MappingType {
-    None = 0,
-    Data = 1,
-    RoData = 2,
-    Text = 3,
-    Rwx = 4,
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CacheAttr {
+    /// Normal cacheable memory
+    WriteBack = 0,
+    /// Normal non-cacheable memory, which allows merging writes
+    WriteCombining = 1,
+    /// Normal non-cacheable memory
+    Uncached = 2,
+    /// Device memory, which allows early write acknowledgement
+    DeviceNGnRE = 3,
+    /// Strongly ordered device memory
+    DeviceNGnRnE = 4,
+}
+
+impl TryFrom<usize> for CacheAttr {
+    type Error = ();
 
-    // TODO: add more granular params
-    Device = 5,
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        match value {
+            _ if value == Self::WriteBack as usize => Ok(Self::WriteBack),
+            _ if value == Self::WriteCombining as usize => Ok(Self::WriteCombining),
+            _ if value == Self::Uncached as usize => Ok(Self::Uncached),
+            _ if value == Self::DeviceNGnRE as usize => Ok(Self::DeviceNGnRE),
+            _ if value == Self::DeviceNGnRnE as usize => Ok(Self::DeviceNGnRnE),
+            _ => Err(()),
+        }
+    }
 }
 
+/// Access permissions and memory type of a mapping. Permission bits are stored in the low bits
+/// and [`CacheAttr`] above them, so the value is passed to syscalls as is
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MappingType(usize);
+
 impl MappingType {
-    pub fn is_greater(&self, other: MappingType) -> bool {
-        #[repr(usize)]
-        enum Flags {
-            Read = 1,
-            Write = 2,
-            Execute = 4,
-        }
+    const PERMS_MASK: usize = 0b111;
+    const CACHE_SHIFT: usize = 3;
+
+    pub const NONE: Self = Self(0);
+    pub const READ: Self = Self(1 << 0);
+    pub const WRITE: Self = Self(1 << 1);
+    pub const EXECUTE: Self = Self(1 << 2);
+
+    pub const RODATA: Self = Self::READ;
+    pub const DATA: Self = Self::READ.union(Self::WRITE);
+    pub const TEXT: Self = Self::READ.union(Self::EXECUTE);
+    pub const RWX: Self = Self::DATA.union(Self::EXECUTE);
+    pub const DEVICE: Self = Self::DATA.with_cache(CacheAttr::DeviceNGnRnE);
 
-        fn into(tp: MappingType) -> usize {
-            match tp {
-                MappingType::None => 0,
-                MappingType::Data | MappingType::Device => {
-                    Flags::Read as usize | Flags::Write as usize
-                }
-                MappingType::RoData => Flags::Read as usize,
-                MappingType::Text => Flags::Read as usize | Flags::Execute as usize,
-                MappingType::Rwx => {
-                    Flags::Read as usize | Flags::Execute as usize | Flags::Write as usize
-                }
-            }
+    /// Permissions of both types. Memory type is taken from `self`
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | (other.0 & Self::PERMS_MASK))
+    }
+
+    /// Same permissions with memory type `cache`
+    pub const fn with_cache(self, cache: CacheAttr) -> Self {
+        Self((self.0 & Self::PERMS_MASK) | (cache as usize) << Self::CACHE_SHIFT)
+    }
+
+    pub const fn cache(&self) -> CacheAttr {
+        match self.0 >> Self::CACHE_SHIFT {
+            1 => CacheAttr::WriteCombining,
+            2 => CacheAttr::Uncached,
+            3 => CacheAttr::DeviceNGnRE,
+            4 => CacheAttr::DeviceNGnRnE,
+            _ => CacheAttr::WriteBack,
         }
+    }
+
+    /// Returns true if all permissions of `perms` are set
+    pub const fn contains(&self, perms: Self) -> bool {
+        self.0 & perms.0 & Self::PERMS_MASK == perms.0 & Self::PERMS_MASK
+    }
+
+    /// Returns true if no permissions are set
+    pub const fn is_none(&self) -> bool {
+        self.0 & Self::PERMS_MASK == 0
+    }
+
+    pub const fn bits(&self) -> usize {
+        self.0
+    }
 
-        let current = into(*self);
-        let other = into(other);
+    /// Returns true if `self` has permissions, which `other` lacks
+    pub fn is_greater(&self, other: MappingType) -> bool {
+        self.0 & !other.0 & Self::PERMS_MASK != 0
+    }
 
-        (current ^ other) & current != 0
+    /// Same mapping type without write permission
+    pub fn read_only(&self) -> Self {
+        Self(self.0 & !Self::WRITE.0)
+    }
+}
+
+impl core::ops::BitOr for MappingType {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        self.union(rhs)
     }
 }
 
 impl TryFrom<usize> for MappingType {
     type Error = ();
 
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        let cache = CacheAttr::try_from(value >> Self::CACHE_SHIFT)?;
+
+        Ok(Self(value & Self::PERMS_MASK).with_cache(cache))
+    }
+}
+
+/// Kind of the child created by `VmoCreateChild`
+#[repr(usize)]
+#[derive(Debug, Copy, Clone)]
+pub enum VmoChildKind {
+    /// Snapshot of the parent. Pages are shared until either side writes to them
+    Cow = 0,
+}
+
+impl TryFrom<usize> for VmoChildKind {
+    type Error = ();
+
     fn try_from(value: usize) -> Result<Self, Self::Error> {
         match value {
-            _ if value == Self::None as usize => Ok(Self::None),
-            _ if value == Self::Data as usize => Ok(Self::Data),
-            _ if value == Self::Text as usize => Ok(Self::Text),
-            _ if value == Self::RoData as usize => Ok(Self::RoData),
-            _ if value == Self::Rwx as usize => Ok(Self::Rwx),
-            _ if value == Self::Device as usize => Ok(Self::Device),
+            _ if value == Self::Cow as usize => Ok(Self::Cow),
             _ => Err(()),
         }
     }
 }
+
+/// Message sent by the kernel to the port of pager VMO, when non-resident page is accessed.
+/// Pager answers it by `VmoSupplyPages`
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct PagerRequest {
+    /// Key passed to `CreatePagerVmo`
+    pub key: usize,
+    /// Offset of the requested range inside the VMO
+    pub offset: usize,
+    pub size: usize,
+}
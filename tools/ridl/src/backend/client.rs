@@ -28,13 +28,21 @@ impl<'a, W: Write> InterfaceCompiler<'a, W> {
         let mut _message = IpcMessage::new();
         let data = Tx{iface_name}::{name}({wire_name_tx} {{ {} }});
         let data_vec = to_allocvec(&data).unwrap();
-        let mut receive_buffer = alloc::vec![0; RxMessage{iface_name}::POSTCARD_MAX_SIZE];
+        let mut receive_buffer = alloc::vec![0; RxMessage{iface_name}::POSTCARD_MAX_SIZE.min(IPC_MAP_THRESHOLD)];
 
         _message.set_out_arena(data_vec.as_slice());
         _message.set_in_arena(receive_buffer.as_mut_slice());
 
         let size = self.port.call(&mut _message).await?;
-        let res: RxMessage{iface_name} = from_bytes(&_message.in_data.unwrap()[..size]).unwrap();
+        let payload = match _message.mapped_arena() {{
+            Some(arena) => arena,
+            None => &_message.in_arena().unwrap()[..size],
+        }};
+        let res: Result<RxMessage{iface_name}, _> = from_bytes(payload);
+
+        Port::release_mapped_arena(&_message)?;
+
+        let res = res.unwrap();
 
         let wire: {name}RxWire = match res {{
             RxMessage{iface_name}::Ok(e) => Ok::<{name}RxWire, ErrorType>(e.try_into().unwrap()),
@@ -28,12 +28,20 @@ impl<'a, W: Write> InterfaceCompiler<'a, W> {
                 let mut receive_buffer: Vec<u8> = Vec::new();
                 let mut in_msg = IpcMessage::new();
 
-                receive_buffer.resize(Tx{iface_name}::POSTCARD_MAX_SIZE, 0);
+                receive_buffer.resize(Tx{iface_name}::POSTCARD_MAX_SIZE.min(IPC_MAP_THRESHOLD), 0);
 
                 in_msg.set_in_arena(receive_buffer.as_mut_slice());
                 let size = self.port.receive(&mut in_msg).await?;
 
-                let payload: Tx{iface_name} = from_bytes(&in_msg.in_arena().unwrap()[..size]).unwrap();
+                let payload = match in_msg.mapped_arena() {{
+                    Some(arena) => arena,
+                    None => &in_msg.in_arena().unwrap()[..size],
+                }};
+                let payload: Result<Tx{iface_name}, _> = from_bytes(payload);
+
+                Port::release_mapped_arena(&in_msg)?;
+
+                let payload = payload.unwrap();
                 let public = payload.to_public(&in_msg, &self.port)?;
 
                 let port = self.port.clone();
@@ -50,7 +50,7 @@ pub fn end_mod<W: Write>(buf: &mut W) {
 
 pub fn includes<W: Write>(buf: &mut W) {
     writeln!(buf, "use libc::handle::Handle;").unwrap();
-    writeln!(buf, "use rtl::ipc::IpcMessage;").unwrap();
+    writeln!(buf, "use rtl::ipc::{{IpcMessage, IPC_MAP_THRESHOLD}};").unwrap();
     writeln!(buf, "use rtl::error::ErrorType;").unwrap();
     writeln!(buf, "use serde::{{Deserialize, Serialize}};").unwrap();
     writeln!(buf, "use alloc::boxed::Box;").unwrap();
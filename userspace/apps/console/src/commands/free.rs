@@ -0,0 +1,58 @@
+use super::{COMMANDS, Command, Enviroment};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use hal::arch::PAGE_SIZE;
+use libc::resource::resource;
+use libc::syscalls::Syscall;
+use rtl::error::ErrorType;
+
+struct Free;
+
+fn kib(pages: usize) -> usize {
+    pages * PAGE_SIZE / 1024
+}
+
+#[async_trait::async_trait]
+impl Command for Free {
+    fn name(&self) -> &str {
+        "free"
+    }
+
+    async fn run(
+        &self,
+        _args: Vec<&str>,
+        _env: Enviroment<'async_trait>,
+    ) -> Result<String, String> {
+        let (info, _) = resource()
+            .ok_or(ErrorType::AccessDenied)
+            .and_then(|resource| Syscall::mem_info(resource, &mut []))
+            .map_err(|err| {
+                let s: &str = err.into();
+
+                String::from(s)
+            })?;
+        let mut res = String::new();
+
+        writeln!(
+            res,
+            "total: {} KiB\nused: {} KiB\nfree: {} KiB\ncached: {} KiB\n",
+            kib(info.total_pages),
+            kib(info.total_pages - info.free_pages),
+            kib(info.free_pages),
+            kib(info.cached_pages),
+        )
+        .unwrap();
+        writeln!(res, "{:>8} {:>8} {:>8}", "slab", "objects", "pages").unwrap();
+
+        for slab in info.slabs.iter().filter(|x| x.size != 0) {
+            writeln!(res, "{:>8} {:>8} {:>8}", slab.size, slab.used, slab.pages).unwrap();
+        }
+
+        Ok(res)
+    }
+}
+
+#[linkme::distributed_slice(COMMANDS)]
+static FREE: &dyn Command = &Free;
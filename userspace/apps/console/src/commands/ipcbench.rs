@@ -0,0 +1,136 @@
+use super::{COMMANDS, Command, Enviroment};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::time::Duration;
+use libc::handle::Handle;
+use libc::port::Port;
+use libc::syscalls::Syscall;
+use libc::task::Thread;
+use rtl::error::ErrorType;
+use rtl::ipc::{IPC_INLINE_SIZE, IPC_MAP_THRESHOLD, IpcMessage};
+
+const DEFAULT_ITERATIONS: usize = 1000;
+
+// Does not fit into inline buffer, so kernel has to go through the heap
+const HEAP_PAYLOAD_SIZE: usize = 1024;
+
+// Above the threshold, so kernel moves it by mapping pages into the server
+const MAPPED_PAYLOAD_SIZE: usize = 4 * IPC_MAP_THRESHOLD;
+
+struct IpcBench;
+
+/// How server waits for the next call
+#[derive(Clone, Copy)]
+enum Path {
+    /// Server is blocked in receive, so kernel writes the call right into its buffers
+    Direct,
+    /// Server waits for the port to become readable first, so every call is queued
+    Queued,
+}
+
+impl IpcBench {
+    /// Serves exactly `count` calls and exits
+    fn server(port: Port, count: usize, path: Path) -> Result<(), ErrorType> {
+        let buf = vec![0u8; HEAP_PAYLOAD_SIZE];
+        let reply = IpcMessage::new();
+        let mut reply_port = None;
+
+        for _ in 0..count {
+            let mut msg = IpcMessage::new();
+
+            msg.set_in_arena(&buf);
+
+            match path {
+                Path::Direct => {
+                    port.reply_wait(reply_port.take(), &reply, &mut msg)?;
+                }
+                Path::Queued => {
+                    if let Some(reply_port) = reply_port.take() {
+                        port.reply(reply_port, &reply)?;
+                    }
+
+                    port.wait_message();
+                    port.receive(&mut msg)?;
+                }
+            }
+
+            reply_port = Some(Handle::new(msg.reply_port()));
+            Port::release_mapped_arena(&msg)?;
+        }
+
+        match reply_port {
+            Some(reply_port) => port.reply(reply_port, &reply),
+            None => Ok(()),
+        }
+    }
+
+    fn round_trip(port: &Port, payload: &[u8], iterations: usize) -> Result<Duration, ErrorType> {
+        let start = Syscall::clock_get();
+
+        for _ in 0..iterations {
+            let mut msg = IpcMessage::new();
+
+            msg.set_out_arena(payload);
+            port.call(&mut msg)?;
+        }
+
+        Ok((Syscall::clock_get() - start) / iterations as u32)
+    }
+
+    fn run_internal(&self, args: Vec<&str>) -> Result<String, ErrorType> {
+        let iterations = match args.first() {
+            Some(arg) => arg.parse().map_err(|_| ErrorType::InvalidArgument)?,
+            None => DEFAULT_ITERATIONS,
+        };
+
+        if iterations == 0 {
+            return Err(ErrorType::InvalidArgument);
+        }
+
+        let mut report = alloc::format!("{iterations} round trips, direct / queued");
+
+        for (name, size) in [
+            ("inline", IPC_INLINE_SIZE),
+            ("heap", HEAP_PAYLOAD_SIZE),
+            ("mapped", MAPPED_PAYLOAD_SIZE),
+        ] {
+            let payload = vec![0; size];
+            let direct = Self::bench(&payload, iterations, Path::Direct)?;
+            let queued = Self::bench(&payload, iterations, Path::Queued)?;
+
+            report += &alloc::format!("\n{name} ({size} bytes): {direct:?} / {queued:?}");
+        }
+
+        Ok(report)
+    }
+
+    fn bench(payload: &[u8], iterations: usize, path: Path) -> Result<Duration, ErrorType> {
+        let port = Port::create()?;
+        let client = port.send_only()?;
+        let server = Thread::spawn(move || Self::server(port, iterations, path).unwrap())?;
+        let res = Self::round_trip(&client, payload, iterations)?;
+
+        server.join()?;
+        Ok(res)
+    }
+}
+
+#[async_trait::async_trait]
+impl Command for IpcBench {
+    fn name(&self) -> &str {
+        "ipcbench"
+    }
+
+    async fn run(&self, args: Vec<&str>, _env: Enviroment<'async_trait>) -> Result<String, String> {
+        self.run_internal(args).map_err(|err| {
+            let s: &str = err.into();
+
+            String::from(s)
+        })
+    }
+}
+
+#[linkme::distributed_slice(COMMANDS)]
+static IPCBENCH: &dyn Command = &IpcBench;
@@ -6,10 +6,13 @@ use alloc::vec::Vec;
 mod cat;
 mod cd;
 mod echo;
+mod free;
 mod help;
+mod ipcbench;
 mod ls;
 mod mkdir;
 mod ping;
+mod top;
 mod touch;
 mod write;
 
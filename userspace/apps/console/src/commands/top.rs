@@ -0,0 +1,75 @@
+use super::{COMMANDS, Command, Enviroment};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use hal::arch::PAGE_SIZE;
+use libc::resource::resource;
+use libc::syscalls::Syscall;
+use rtl::error::ErrorType;
+use rtl::meminfo::TaskMemInfo;
+
+struct Top;
+
+impl Top {
+    fn tasks() -> Result<Vec<TaskMemInfo>, ErrorType> {
+        let resource = resource().ok_or(ErrorType::AccessDenied)?;
+        let mut tasks = Vec::new();
+
+        // Tasks may be created between the calls, so retry until the buffer is large enough
+        loop {
+            let (_, count) = Syscall::mem_info(resource, &mut tasks)?;
+
+            if count <= tasks.len() {
+                tasks.truncate(count);
+                return Ok(tasks);
+            }
+
+            tasks = vec![TaskMemInfo::default(); count + 4];
+        }
+    }
+
+    fn run_internal(&self) -> Result<String, ErrorType> {
+        let mut tasks = Self::tasks()?;
+        let mut res = String::new();
+
+        tasks.sort_by(|a, b| b.pages.cmp(&a.pages));
+        writeln!(res, "{:>5} {:>10} name", "id", "KiB").unwrap();
+
+        for task in &tasks {
+            writeln!(
+                res,
+                "{:>5} {:>10} {}",
+                task.id,
+                task.pages * PAGE_SIZE / 1024,
+                task.name()
+            )
+            .unwrap();
+        }
+
+        Ok(res)
+    }
+}
+
+#[async_trait::async_trait]
+impl Command for Top {
+    fn name(&self) -> &str {
+        "top"
+    }
+
+    async fn run(
+        &self,
+        _args: Vec<&str>,
+        _env: Enviroment<'async_trait>,
+    ) -> Result<String, String> {
+        self.run_internal().map_err(|err| {
+            let s: &str = err.into();
+
+            String::from(s)
+        })
+    }
+}
+
+#[linkme::distributed_slice(COMMANDS)]
+static TOP: &dyn Command = &Top;
@@ -8,7 +8,7 @@ use libc::factory::factory;
 use libc::vmm::vm_object::VmObject;
 use libc::vmm::vms::vms;
 use rtl::error::ErrorType;
-use rtl::vmm::MappingType;
+use rtl::vmm::{CacheAttr, MappingType};
 
 pub struct DmaBuffer<T: Copy> {
     va: MemRange<VirtAddr>,
@@ -18,11 +18,19 @@ pub struct DmaBuffer<T: Copy> {
 }
 
 impl<T: Copy> DmaBuffer<T> {
+    /// Allocates buffer mapped as uncached normal memory
     pub fn new(entries: usize) -> Result<Self, ErrorType> {
+        Self::with_cache(entries, CacheAttr::Uncached)
+    }
+
+    /// Allocates buffer mapped with memory type `cache`. Buffer must not be cacheable, since
+    /// cache maintenance is not done
+    pub fn with_cache(entries: usize, cache: CacheAttr) -> Result<Self, ErrorType> {
         let num_bytes = size_of::<T>() * entries;
+        let tp = MappingType::DATA.with_cache(cache);
 
-        let vmo = factory().create_vm_object_contig(num_bytes, MappingType::Device)?;
-        let va = vms().map_vm_object(&vmo, None, MappingType::Device)?;
+        let vmo = factory().create_vm_object_contig(num_bytes, tp)?;
+        let va = vms().map_vm_object(&vmo, None, tp)?;
         let pa = vmo.get_phys_info()?;
 
         Ok(Self {
@@ -44,8 +52,8 @@ impl<T: Copy> DmaBuffer<T> {
     pub fn read(&mut self, idx: usize) -> T {
         assert!(idx < self.va.size() / size_of::<T>());
 
-        // TODO: here we rely on kernel mapping VMO as uncached NeReGe memory. In future it would be
-        // better to do cache maintance in the user-space
+        // TODO: here we rely on buffer being mapped uncached. In future it would be better to do
+        // cache maintance in the user-space
         unsafe {
             core::arch::asm!("dsb sy");
             self.va.start().to_raw_mut::<T>().add(idx).read_volatile()
@@ -78,8 +86,8 @@ impl<T: Copy> DmaBuffer<T> {
     pub fn write(&mut self, idx: usize, val: T) {
         assert!(idx < self.va.size() / size_of::<T>());
 
-        // TODO: here we rely on kernel mapping VMO as uncached NeReGe memory. In future it would be
-        // better to do cache maintance in the user-space
+        // TODO: here we rely on buffer being mapped uncached. In future it would be better to do
+        // cache maintance in the user-space
         unsafe {
             self.va
                 .start()
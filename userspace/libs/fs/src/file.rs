@@ -2,9 +2,12 @@
 
 use crate::bindings_Vfs::File as BindingFile;
 use alloc::vec::Vec;
+use core::ops::Deref;
 use hal::address::VirtualAddress;
+use hal::arch::PAGE_SIZE;
 use libc::factory::factory;
 use libc::handle::Handle;
+use libc::vmm::vm_object::VmObject;
 use libc::vmm::vms::vms;
 use rokio::port::Port;
 use rtl::error::ErrorType;
@@ -14,6 +17,33 @@ pub struct File {
     file: BindingFile,
 }
 
+/// Read-only mapping of file contents created by [`File::map`]
+pub struct FileMapping {
+    data: *const u8,
+    size: usize,
+}
+
+unsafe impl Send for FileMapping {}
+unsafe impl Sync for FileMapping {}
+
+impl Deref for FileMapping {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { core::slice::from_raw_parts(self.data, self.size) }
+    }
+}
+
+impl Drop for FileMapping {
+    fn drop(&mut self) {
+        if self.size != 0 {
+            vms()
+                .vm_free(self.data as *mut u8, self.size.next_multiple_of(PAGE_SIZE))
+                .unwrap();
+        }
+    }
+}
+
 impl File {
     const READ_CHUNK_SIZE: usize = 1 << 12;
 
@@ -28,16 +58,37 @@ impl File {
     }
 
     pub async fn read(&self, data: &mut [u8]) -> Result<usize, ErrorType> {
-        let vmo = factory().create_vm_object(data.len(), MappingType::Data)?;
+        let vmo = factory().create_vm_object(data.len(), MappingType::DATA)?;
 
         let read_len = self.file.Read(data.len(), vmo.handle()).await?.read;
-        let buf = vms().map_vm_object(&vmo, None, MappingType::Data)?;
+        let buf = vms().map_vm_object(&vmo, None, MappingType::DATA)?;
         let buf = unsafe { buf.as_slice(read_len) };
 
         data[..read_len].copy_from_slice(buf);
         Ok(read_len)
     }
 
+    /// Maps contents of the file read-only. Pages are read by vfs when they are touched for the
+    /// first time. File is unmapped once returned mapping is dropped
+    pub async fn map(&self) -> Result<FileMapping, ErrorType> {
+        let res = self.file.Map().await?;
+
+        if res.size == 0 {
+            return Ok(FileMapping {
+                data: core::ptr::NonNull::dangling().as_ptr(),
+                size: 0,
+            });
+        }
+
+        let vmo = unsafe { VmObject::new(res.vmo) };
+        let buf = vms().map_vm_object(&vmo, None, MappingType::RODATA)?;
+
+        Ok(FileMapping {
+            data: buf.to_raw::<u8>(),
+            size: res.size,
+        })
+    }
+
     pub async fn read_to_end(&self) -> Result<Vec<u8>, ErrorType> {
         let mut result = Vec::new();
         let mut chunk = [0; Self::READ_CHUNK_SIZE];
@@ -53,9 +104,9 @@ impl File {
     }
 
     pub async fn write(&self, data: &[u8]) -> Result<usize, ErrorType> {
-        let vmo = factory().create_vm_object(data.len(), MappingType::Data)?;
+        let vmo = factory().create_vm_object(data.len(), MappingType::DATA)?;
 
-        let mut buf = vms().map_vm_object(&vmo, None, MappingType::Data)?;
+        let mut buf = vms().map_vm_object(&vmo, None, MappingType::DATA)?;
         let buf = unsafe { buf.as_slice_mut(data.len()) };
 
         buf.copy_from_slice(data);
@@ -27,13 +27,13 @@ pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
         #[no_mangle]
         pub extern "C" fn _start(handle_page: *const u8) {
             main(libc::handle::parse_handle_table(handle_page).unwrap());
-            loop { libc::syscalls::Syscall::sys_yield() }
+            libc::task::exit(0)
         }
 
         #[panic_handler]
         fn panic(info: &core::panic::PanicInfo) -> ! {
             println!("PANIC!!! {}", info);
-            loop { libc::syscalls::Syscall::sys_yield() }
+            libc::task::exit(usize::MAX)
         }
 
         #[macro_use]
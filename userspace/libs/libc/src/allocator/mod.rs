@@ -1,14 +1,14 @@
+use crate::sync::Mutex;
 use crate::vmm::vms::vms;
 use alloc::alloc::GlobalAlloc;
 use alloc::alloc::Layout;
 use dlmalloc::{Allocator as AllocatorApi, Dlmalloc};
-use rtl::locking::spinlock::Spinlock;
 use rtl::vmm::MappingType;
 
-struct Allocator(Spinlock<Dlmalloc<PageAllocator>>);
+struct Allocator(Mutex<Dlmalloc<PageAllocator>>);
 
 #[global_allocator]
-static ALLOCATOR: Allocator = Allocator(Spinlock::new(Dlmalloc::new_with_allocator(PageAllocator)));
+static ALLOCATOR: Allocator = Allocator(Mutex::new(Dlmalloc::new_with_allocator(PageAllocator)));
 
 unsafe impl GlobalAlloc for Allocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
@@ -31,7 +31,7 @@ unsafe impl AllocatorApi for PageAllocator {
         };
 
         let ptr = vms()
-            .vm_allocate(size, MappingType::Data)
+            .vm_allocate(size, MappingType::DATA)
             .unwrap_or(core::ptr::null_mut());
 
         assert!(!ptr.is_null());
@@ -1,5 +1,5 @@
 use elf::ElfBytes;
-use elf::abi::PT_LOAD;
+use elf::abi::{ET_DYN, PT_LOAD, R_AARCH64_RELATIVE, SHT_RELA};
 use elf::endian::LittleEndian;
 use elf::segment::ProgramHeader;
 use hal::address::VirtAddr;
@@ -40,6 +40,34 @@ impl<'a> Elf<'a> {
         )
     }
 
+    /// Position independent image, which can be loaded at any address
+    pub fn is_dyn(&self) -> bool {
+        self.elf_data.ehdr.e_type == ET_DYN
+    }
+
+    /// Returns offsets and addends of relocations. Images are linked statically, so only relative
+    /// relocations are supported
+    pub fn relative_relocations(&self) -> Option<Vec<(usize, usize)>> {
+        let mut res = Vec::new();
+
+        for shdr in self
+            .elf_data
+            .section_headers()?
+            .iter()
+            .filter(|x| x.sh_type == SHT_RELA)
+        {
+            for rela in self.elf_data.section_data_as_relas(&shdr).ok()? {
+                if rela.r_type != R_AARCH64_RELATIVE {
+                    return None;
+                }
+
+                res.push((rela.r_offset as usize, rela.r_addend as usize));
+            }
+        }
+
+        Some(res)
+    }
+
     pub fn entry_point(&self) -> VirtAddr {
         (self.elf_data.ehdr.e_entry as usize).into()
     }
@@ -50,11 +78,11 @@ impl<'a> Elf<'a> {
 
     pub fn program_header_to_mapping_type(h: ProgramHeader) -> MappingType {
         if h.p_flags & PF_W != 0 {
-            MappingType::Data
+            MappingType::DATA
         } else if h.p_flags & PF_X != 0 {
-            MappingType::Text
+            MappingType::TEXT
         } else {
-            MappingType::RoData
+            MappingType::RODATA
         }
     }
 
@@ -0,0 +1,64 @@
+use crate::factory::factory;
+use crate::handle::Handle;
+use crate::syscalls::Syscall;
+use rtl::error::ErrorType;
+use rtl::signal::Signals;
+
+/// Object carrying user-defined signals
+pub struct Event {
+    h: Handle,
+}
+
+impl Event {
+    pub fn create() -> Result<Self, ErrorType> {
+        factory().create_event()
+    }
+
+    pub unsafe fn new(h: Handle) -> Self {
+        Self { h }
+    }
+
+    /// Clears `clear` and then sets `set` signals
+    pub fn signal(&self, set: Signals, clear: Signals) -> Result<(), ErrorType> {
+        Syscall::object_signal(&self.h, set, clear)
+    }
+
+    /// Blocks until any of `signals` is set
+    pub fn wait(&self, signals: Signals) -> Result<(), ErrorType> {
+        Syscall::object_wait(&self.h, signals)
+    }
+
+    pub fn handle(&self) -> &Handle {
+        &self.h
+    }
+}
+
+/// One end of the event pair. Signals are set on the other end
+pub struct EventPair {
+    h: Handle,
+}
+
+impl EventPair {
+    pub fn create() -> Result<(Self, Self), ErrorType> {
+        factory().create_event_pair()
+    }
+
+    pub unsafe fn new(h: Handle) -> Self {
+        Self { h }
+    }
+
+    /// Clears `clear` and then sets `set` signals on the peer
+    pub fn signal_peer(&self, set: Signals, clear: Signals) -> Result<(), ErrorType> {
+        Syscall::object_signal(&self.h, set, clear)
+    }
+
+    /// Blocks until any of `signals` is set by the peer. `Signal::PeerClosed` is set once the
+    /// peer is gone
+    pub fn wait(&self, signals: Signals) -> Result<(), ErrorType> {
+        Syscall::object_wait(&self.h, signals)
+    }
+
+    pub fn handle(&self) -> &Handle {
+        &self.h
+    }
+}
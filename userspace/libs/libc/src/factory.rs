@@ -1,5 +1,7 @@
+use crate::event::{Event, EventPair};
 use crate::handle::Handle;
 use crate::irq::Irq;
+use crate::job::Job;
 use crate::port::Port;
 use crate::syscalls::Syscall;
 use crate::task::Task;
@@ -8,6 +10,7 @@ use crate::vmm::vm_object::VmObject;
 use alloc::string::ToString;
 use rtl::error::ErrorType;
 use rtl::irq::IrqTrigger;
+use rtl::job::JobLimits;
 use rtl::vmm::MappingType;
 
 pub static mut SELF_FACTORY: Option<Factory> = None;
@@ -22,12 +25,22 @@ impl Factory {
     }
 
     pub fn create_task(&self, name: &str) -> Result<Task, ErrorType> {
+        self.create_task_in(name, None)
+    }
+
+    /// Creates task, which resources are charged to `job`
+    pub fn create_task_in(&self, name: &str, job: Option<&Job>) -> Result<Task, ErrorType> {
         Ok(Task::new(
-            Syscall::create_task(&self.h, name)?,
+            Syscall::create_task(&self.h, name, job.map(|job| job.handle()))?,
             name.to_string(),
         ))
     }
 
+    pub fn create_job(&self, parent: Option<&Job>, limits: &JobLimits) -> Result<Job, ErrorType> {
+        Syscall::create_job(&self.h, parent.map(|job| job.handle()), limits)
+            .map(|h| unsafe { Job::new(h) })
+    }
+
     pub fn create_port(&self) -> Result<Port, ErrorType> {
         Syscall::create_port(&self.h).map(|x| unsafe { Port::new(x) })
     }
@@ -36,6 +49,16 @@ impl Factory {
         Syscall::create_irq(&self.h, num, trigger).map(|h| unsafe { Irq::new(h) })
     }
 
+    pub fn create_event(&self) -> Result<Event, ErrorType> {
+        Syscall::create_event(&self.h).map(|h| unsafe { Event::new(h) })
+    }
+
+    pub fn create_event_pair(&self) -> Result<(EventPair, EventPair), ErrorType> {
+        let (first, second) = Syscall::create_event_pair(&self.h)?;
+
+        Ok(unsafe { (EventPair::new(first), EventPair::new(second)) })
+    }
+
     pub fn create_timer(&self) -> Result<Timer, ErrorType> {
         Syscall::create_timer(&self.h).map(|h| unsafe { Timer::new(h) })
     }
@@ -46,6 +69,20 @@ impl Factory {
         Ok(unsafe { VmObject::new(handle) })
     }
 
+    /// Creates VMO, which pages are requested from the pager listening on `port`. Requests carry
+    /// `key` to tell objects sharing the port apart
+    pub fn create_pager_vm_object(
+        &self,
+        size: usize,
+        tp: MappingType,
+        port: &Handle,
+        key: usize,
+    ) -> Result<VmObject, ErrorType> {
+        let handle = Syscall::create_pager_vmo(&self.h, size, tp, port, key)?;
+
+        Ok(unsafe { VmObject::new(handle) })
+    }
+
     pub fn create_vm_object_contig(
         &self,
         size: usize,
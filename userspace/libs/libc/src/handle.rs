@@ -1,4 +1,5 @@
 use super::factory::init_self_factory;
+use super::resource::init_self_resource;
 use super::syscalls::Syscall;
 use super::vmm::vms::init_self_vms;
 use alloc::collections::BTreeMap;
@@ -6,6 +7,7 @@ use hal::arch::PAGE_SIZE;
 use heapless::String;
 use heapless::Vec;
 use postcard::from_bytes;
+use rtl::capabilities::CapabilityBits;
 use rtl::handle::MAX_HANDLE_NAME;
 use rtl::{error::ErrorType, handle};
 use spin::Once;
@@ -28,6 +30,16 @@ impl Handle {
     pub fn clone_handle(&self) -> Result<Self, ErrorType> {
         Syscall::clone_handle(self)
     }
+
+    /// Creates new handle to the same object with reduced rights
+    pub fn duplicate(&self, rights: CapabilityBits) -> Result<Self, ErrorType> {
+        Syscall::duplicate_handle(self, rights)
+    }
+
+    /// Invalidates all handles derived from this one, including ones transferred to other tasks
+    pub fn revoke(&self) -> Result<(), ErrorType> {
+        Syscall::revoke_handle(self)
+    }
 }
 
 impl Drop for Handle {
@@ -53,6 +65,7 @@ pub fn parse_handle_table(ptr: *const u8) -> Result<Option<Handle>, ErrorType> {
                 "VMS" => init_self_vms(Handle::new(entry.1)),
                 "FACTORY" => init_self_factory(Handle::new(entry.1)),
                 "BOOT" => boot_handle = Some(Handle::new(entry.1)),
+                "RESOURCE" => init_self_resource(Handle::new(entry.1)),
                 _ => {
                     map.insert(entry.0, Handle::new(entry.1));
                 }
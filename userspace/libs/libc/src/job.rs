@@ -0,0 +1,29 @@
+use crate::factory::factory;
+use crate::handle::Handle;
+use rtl::error::ErrorType;
+use rtl::job::JobLimits;
+
+/// Group of tasks sharing resource limits
+pub struct Job {
+    h: Handle,
+}
+
+impl Job {
+    /// Creates job nested into the job of the current task
+    pub fn create(limits: &JobLimits) -> Result<Self, ErrorType> {
+        factory().create_job(None, limits)
+    }
+
+    pub unsafe fn new(h: Handle) -> Self {
+        Self { h }
+    }
+
+    /// Creates job nested into this one
+    pub fn create_child(&self, limits: &JobLimits) -> Result<Self, ErrorType> {
+        factory().create_job(Some(self), limits)
+    }
+
+    pub fn handle(&self) -> &Handle {
+        &self.h
+    }
+}
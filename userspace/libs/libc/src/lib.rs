@@ -5,11 +5,15 @@ extern crate alloc;
 
 pub mod allocator;
 pub mod elf;
+pub mod event;
 pub mod factory;
 pub mod handle;
 pub mod irq;
+pub mod job;
 pub mod port;
+pub mod resource;
 pub mod stdio;
+pub mod sync;
 pub mod syscalls;
 pub mod task;
 pub mod timer;
@@ -1,6 +1,10 @@
 use super::handle::Handle;
 use crate::factory::factory;
 use crate::syscalls::Syscall;
+use crate::vmm::vms::vms;
+use core::time::Duration;
+use hal::arch::PAGE_SIZE;
+use rtl::capabilities::Capability;
 use rtl::error::ErrorType;
 use rtl::ipc::IpcMessage;
 use rtl::signal::Signal;
@@ -23,10 +27,20 @@ impl Port {
     }
 
     pub fn call(&self, msg: &mut IpcMessage) -> Result<usize, ErrorType> {
+        self.call_until(msg, None)
+    }
+
+    /// Same as [`Port::call`], but fails with [`ErrorType::TimedOut`] if reply has not arrived
+    /// by `deadline` (time since boot)
+    pub fn call_until(
+        &self,
+        msg: &mut IpcMessage,
+        deadline: Option<Duration>,
+    ) -> Result<usize, ErrorType> {
         let p = Port::create()?;
 
         msg.set_reply_port(unsafe { p.h.as_raw() });
-        Syscall::port_call(&self.h, msg)
+        Syscall::port_call_until(&self.h, msg, deadline)
     }
 
     pub fn send(&self, msg: &mut IpcMessage) -> Result<Port, ErrorType> {
@@ -40,6 +54,44 @@ impl Port {
         Syscall::port_receive(&self.h, msg)
     }
 
+    /// Blocks until message arrives or `deadline` (time since boot) passes
+    pub fn receive_until(
+        &self,
+        msg: &mut IpcMessage,
+        deadline: Option<Duration>,
+    ) -> Result<usize, ErrorType> {
+        Syscall::port_receive_until(&self.h, msg, deadline)
+    }
+
+    /// Replies to the previous message (if any) and waits for the next one in a single syscall
+    pub fn reply_wait(
+        &self,
+        reply_port: Option<Handle>,
+        reply: &IpcMessage,
+        msg: &mut IpcMessage,
+    ) -> Result<usize, ErrorType> {
+        Syscall::port_reply_wait(&self.h, reply_port, reply, msg)
+    }
+
+    /// Creates handle to the same port, which can only be used to send messages
+    pub fn send_only(&self) -> Result<Self, ErrorType> {
+        let rights =
+            Capability::Call | Capability::Send | Capability::Duplicate | Capability::Transfer;
+
+        Ok(unsafe { Self::new(self.h.duplicate(rights)?) })
+    }
+
+    /// Unmaps large payload the kernel has mapped into the address space on receive
+    pub fn release_mapped_arena(msg: &IpcMessage) -> Result<(), ErrorType> {
+        match msg.mapped_arena() {
+            Some(arena) => vms().vm_free(
+                arena.as_ptr() as *mut u8,
+                arena.len().next_multiple_of(PAGE_SIZE),
+            ),
+            None => Ok(()),
+        }
+    }
+
     pub fn handle(&self) -> &Handle {
         &self.h
     }
@@ -0,0 +1,13 @@
+use crate::handle::Handle;
+use spin::Once;
+
+static SELF_RESOURCE: Once<Handle> = Once::new();
+
+pub fn init_self_resource(h: Handle) {
+    SELF_RESOURCE.call_once(|| h);
+}
+
+/// Handle granting privileged operations. Present only if the parent handed one to the task
+pub fn resource() -> Option<&'static Handle> {
+    SELF_RESOURCE.get()
+}
@@ -0,0 +1,22 @@
+use crate::syscalls::Syscall;
+use core::sync::atomic::AtomicU32;
+use core::time::Duration;
+use rtl::error::ErrorType;
+use rtl::locking::futex::Futex;
+
+/// Futex backed by `FutexWait`/`FutexWake` syscalls
+pub struct SysFutex;
+
+impl Futex for SysFutex {
+    fn wait(word: &AtomicU32, expected: u32, timeout: Option<Duration>) -> Result<(), ErrorType> {
+        Syscall::futex_wait(word, expected, timeout.map(|t| Syscall::clock_get() + t))
+    }
+
+    fn wake(word: &AtomicU32, count: usize) -> Result<usize, ErrorType> {
+        Syscall::futex_wake(word, count)
+    }
+}
+
+pub type Mutex<T> = rtl::locking::mutex::Mutex<T, SysFutex>;
+pub type MutexGuard<'a, T> = rtl::locking::mutex::MutexGuard<'a, T, SysFutex>;
+pub type Condvar = rtl::locking::condvar::Condvar<SysFutex>;
@@ -5,43 +5,79 @@ use crate::syscalls_aarch64::*;
 use crate::syscalls_x86_64::*;
 
 use super::handle::Handle;
+use core::sync::atomic::AtomicU32;
 use core::time::Duration;
 use hal::address::{Address, PhysAddr, VirtAddr};
+use rtl::capabilities::{CapabilityBits, SAME_RIGHTS};
 use rtl::error::ErrorType;
-use rtl::handle::Handle as RawHandle;
+use rtl::handle::{HANDLE_INVALID, Handle as RawHandle};
 use rtl::ipc::IpcMessage;
 use rtl::irq::IrqTrigger;
+use rtl::job::JobLimits;
+use rtl::meminfo::{MemInfo, TaskMemInfo};
+use rtl::sched::SchedClass;
 use rtl::signal::{Signals, WaitEntry};
-use rtl::syscalls::SyscallList;
-use rtl::vmm::MappingType;
+use rtl::syscalls::{DEADLINE_INFINITE, SyscallList};
+use rtl::vmm::{MappingType, VmoChildKind};
 
 pub enum Syscall<'a> {
     Write(&'a str),
     Yield,
     CreatePort(RawHandle),
-    CreateTask(RawHandle, &'a str),
+    CreateTask(RawHandle, &'a str, RawHandle),
     VmAllocate(RawHandle, usize, MappingType),
     VmFree(RawHandle, *mut u8, usize),
+    VmProtect(RawHandle, *mut u8, usize, MappingType),
     CreateVmo(RawHandle, usize, MappingType),
     CreateVmoContig(RawHandle, usize, MappingType),
     VmoGetPhysInfo(RawHandle),
+    VmoCreateChild(RawHandle, VmoChildKind, usize, usize),
+    CreatePagerVmo(RawHandle, usize, MappingType, RawHandle, usize),
+    VmoSupplyPages(RawHandle, usize, *const u8, usize),
     VmMapVmo(RawHandle, RawHandle, VirtAddr, MappingType),
-    VmMapPhys(RawHandle, PhysAddr, usize),
-    TaskStart(RawHandle, VirtAddr, RawHandle),
+    VmMapPhys(RawHandle, PhysAddr, usize, RawHandle),
+    TaskStart(RawHandle, VirtAddr, RawHandle, RawHandle),
     VmsHandle(RawHandle),
     CloseHandle(RawHandle),
-    PortCall(RawHandle, *mut IpcMessage<'a>),
+    PortCall(RawHandle, *mut IpcMessage<'a>, Option<Duration>),
     PortSend(RawHandle, *mut IpcMessage<'a>),
     PortReply(RawHandle, RawHandle, *const IpcMessage<'a>),
-    PortReceive(RawHandle, *mut IpcMessage<'a>),
-    CloneHandle(RawHandle),
+    PortReceive(RawHandle, *mut IpcMessage<'a>, Option<Duration>),
+    PortReplyWait(
+        RawHandle,
+        RawHandle,
+        *const IpcMessage<'a>,
+        *mut IpcMessage<'a>,
+    ),
+    CloneHandle(RawHandle, Option<CapabilityBits>),
     GetFdt,
-    ObjectWait(RawHandle, Signals),
-    ObjectWaitMany(&'a mut [WaitEntry]),
+    ObjectWait(RawHandle, Signals, Option<Duration>),
+    ObjectWaitMany(&'a mut [WaitEntry], Option<Duration>),
     CreateIrq(RawHandle, usize, IrqTrigger),
     AckIrq(RawHandle),
     CreateTimer(RawHandle),
     TimerArm(RawHandle, Duration),
+    RevokeHandle(RawHandle),
+    Exit(usize),
+    TaskKill(RawHandle, usize),
+    TaskExitCode(RawHandle, *mut usize),
+    CreateThread(RawHandle),
+    ThreadStart(RawHandle, usize, usize),
+    ThreadExit,
+    ThreadSetPriority(RawHandle, SchedClass, usize, RawHandle),
+    ThreadSetAffinity(RawHandle, usize),
+    ClockGet,
+    CreateEvent(RawHandle),
+    CreateEventPair(RawHandle, *mut [RawHandle; 2]),
+    SignalObject(RawHandle, Signals, Signals),
+    FutexWait(*const AtomicU32, u32, Option<Duration>),
+    FutexWake(*const AtomicU32, usize),
+    CreateJob(RawHandle, RawHandle, *const JobLimits),
+    MemInfo(RawHandle, *mut MemInfo, *mut TaskMemInfo, usize),
+}
+
+fn deadline_arg(deadline: Option<Duration>) -> usize {
+    deadline.map_or(DEADLINE_INFINITE, |deadline| deadline.as_nanos() as usize)
 }
 
 impl<'a> Syscall<'a> {
@@ -58,8 +94,26 @@ impl<'a> Syscall<'a> {
         unsafe { syscall(Self::CreatePort(h.as_raw()).as_args()).map(Handle::new) }
     }
 
-    pub fn create_task(h: &Handle, name: &'a str) -> Result<Handle, ErrorType> {
-        unsafe { syscall(Self::CreateTask(h.as_raw(), name).as_args()).map(Handle::new) }
+    /// Creates task inside `job`. By default it's created in the job of the current task
+    pub fn create_task(
+        h: &Handle,
+        name: &'a str,
+        job: Option<&Handle>,
+    ) -> Result<Handle, ErrorType> {
+        let job = job.map_or(HANDLE_INVALID, |job| unsafe { job.as_raw() });
+
+        unsafe { syscall(Self::CreateTask(h.as_raw(), name, job).as_args()).map(Handle::new) }
+    }
+
+    /// Creates job nested into `parent`. By default it's nested into the job of the current task
+    pub fn create_job(
+        h: &Handle,
+        parent: Option<&Handle>,
+        limits: &JobLimits,
+    ) -> Result<Handle, ErrorType> {
+        let parent = parent.map_or(HANDLE_INVALID, |parent| unsafe { parent.as_raw() });
+
+        unsafe { syscall(Self::CreateJob(h.as_raw(), parent, limits).as_args()).map(Handle::new) }
     }
 
     pub fn vm_allocate(h: &Handle, size: usize, mt: MappingType) -> Result<*mut u8, ErrorType> {
@@ -70,6 +124,15 @@ impl<'a> Syscall<'a> {
         unsafe { syscall(Self::VmFree(h.as_raw(), ptr, size).as_args()).map(|_| ()) }
     }
 
+    pub fn vm_protect(
+        h: &Handle,
+        ptr: *mut u8,
+        size: usize,
+        tp: MappingType,
+    ) -> Result<(), ErrorType> {
+        unsafe { syscall(Self::VmProtect(h.as_raw(), ptr, size, tp).as_args()).map(|_| ()) }
+    }
+
     pub fn create_vmo(h: &Handle, size: usize, tp: MappingType) -> Result<Handle, ErrorType> {
         unsafe { syscall(Self::CreateVmo(h.as_raw(), size, tp).as_args()).map(Handle::new) }
     }
@@ -89,6 +152,37 @@ impl<'a> Syscall<'a> {
         }
     }
 
+    pub fn vmo_create_child(
+        h: &Handle,
+        kind: VmoChildKind,
+        offset: usize,
+        size: usize,
+    ) -> Result<Handle, ErrorType> {
+        unsafe {
+            syscall(Self::VmoCreateChild(h.as_raw(), kind, offset, size).as_args()).map(Handle::new)
+        }
+    }
+
+    pub fn create_pager_vmo(
+        h: &Handle,
+        size: usize,
+        tp: MappingType,
+        port: &Handle,
+        key: usize,
+    ) -> Result<Handle, ErrorType> {
+        unsafe {
+            syscall(Self::CreatePagerVmo(h.as_raw(), size, tp, port.as_raw(), key).as_args())
+                .map(Handle::new)
+        }
+    }
+
+    pub fn vmo_supply_pages(h: &Handle, offset: usize, data: &[u8]) -> Result<(), ErrorType> {
+        unsafe {
+            syscall(Self::VmoSupplyPages(h.as_raw(), offset, data.as_ptr(), data.len()).as_args())
+                .map(|_| ())
+        }
+    }
+
     pub fn vm_map_vmo(
         vms: &Handle,
         vmo: &Handle,
@@ -101,13 +195,38 @@ impl<'a> Syscall<'a> {
         }
     }
 
-    pub fn vm_map_phys(vms: &Handle, pa: PhysAddr, size: usize) -> Result<*mut u8, ErrorType> {
-        unsafe { syscall(Self::VmMapPhys(vms.as_raw(), pa, size).as_args()).map(|x| x as _) }
+    /// Maps physical range into `vms`. Requires `resource` with [`Capability::MapPhys`]
+    ///
+    /// [`Capability::MapPhys`]: rtl::capabilities::Capability::MapPhys
+    pub fn vm_map_phys(
+        vms: &Handle,
+        pa: PhysAddr,
+        size: usize,
+        resource: Option<&Handle>,
+    ) -> Result<*mut u8, ErrorType> {
+        let resource = resource
+            .map(|x| unsafe { x.as_raw() })
+            .unwrap_or(HANDLE_INVALID);
+
+        unsafe {
+            syscall(Self::VmMapPhys(vms.as_raw(), pa, size, resource).as_args()).map(|x| x as _)
+        }
     }
 
-    pub fn task_start(task: &Handle, ep: VirtAddr, boot_handle: &Handle) -> Result<(), ErrorType> {
+    /// Starts `task` at `ep`. `resource` is handed to the task to grant it privileged operations
+    pub fn task_start(
+        task: &Handle,
+        ep: VirtAddr,
+        boot_handle: &Handle,
+        resource: Option<&Handle>,
+    ) -> Result<(), ErrorType> {
+        let resource = resource
+            .map(|x| unsafe { x.as_raw() })
+            .unwrap_or(HANDLE_INVALID);
+
         unsafe {
-            syscall(Self::TaskStart(task.as_raw(), ep, boot_handle.as_raw()).as_args()).map(|_| ())
+            syscall(Self::TaskStart(task.as_raw(), ep, boot_handle.as_raw(), resource).as_args())
+                .map(|_| ())
         }
     }
 
@@ -115,36 +234,156 @@ impl<'a> Syscall<'a> {
         unsafe { syscall(Self::VmsHandle(h.as_raw()).as_args()).map(Handle::new) }
     }
 
+    pub fn task_kill(task: &Handle, code: usize) -> Result<(), ErrorType> {
+        unsafe { syscall(Self::TaskKill(task.as_raw(), code).as_args()).map(|_| ()) }
+    }
+
+    pub fn task_exit_code(task: &Handle) -> Result<usize, ErrorType> {
+        let mut code = 0;
+
+        unsafe { syscall(Self::TaskExitCode(task.as_raw(), &raw mut code).as_args()).map(|_| code) }
+    }
+
+    pub fn exit(code: usize) -> ! {
+        unsafe {
+            let _ = syscall(Self::Exit(code).as_args());
+        }
+
+        unreachable!("Returned from exit")
+    }
+
+    /// Creates thread in `task` or in the current task if `task` is None
+    pub fn create_thread(task: Option<&Handle>) -> Result<Handle, ErrorType> {
+        let task = task
+            .map(|x| unsafe { x.as_raw() })
+            .unwrap_or(HANDLE_INVALID);
+
+        unsafe { syscall(Self::CreateThread(task).as_args()).map(Handle::new) }
+    }
+
+    pub fn thread_start(thread: &Handle, ep: usize, arg: usize) -> Result<(), ErrorType> {
+        unsafe { syscall(Self::ThreadStart(thread.as_raw(), ep, arg).as_args()).map(|_| ()) }
+    }
+
+    /// Sets scheduling class and priority of `thread` or of the current thread if `thread` is None.
+    /// [`SchedClass::RealTime`] requires `resource` with [`Capability::RealTime`]
+    ///
+    /// [`Capability::RealTime`]: rtl::capabilities::Capability::RealTime
+    pub fn thread_set_priority(
+        thread: Option<&Handle>,
+        class: SchedClass,
+        priority: usize,
+        resource: Option<&Handle>,
+    ) -> Result<(), ErrorType> {
+        let thread = thread
+            .map(|x| unsafe { x.as_raw() })
+            .unwrap_or(HANDLE_INVALID);
+        let resource = resource
+            .map(|x| unsafe { x.as_raw() })
+            .unwrap_or(HANDLE_INVALID);
+
+        unsafe {
+            syscall(Self::ThreadSetPriority(thread, class, priority, resource).as_args())
+                .map(|_| ())
+        }
+    }
+
+    /// Restricts `thread` or the current thread if `thread` is None to CPUs from `mask`
+    pub fn thread_set_affinity(thread: Option<&Handle>, mask: usize) -> Result<(), ErrorType> {
+        let thread = thread
+            .map(|x| unsafe { x.as_raw() })
+            .unwrap_or(HANDLE_INVALID);
+
+        unsafe { syscall(Self::ThreadSetAffinity(thread, mask).as_args()).map(|_| ()) }
+    }
+
+    pub fn thread_exit() -> ! {
+        unsafe {
+            let _ = syscall(Self::ThreadExit.as_args());
+        }
+
+        unreachable!("Returned from thread exit")
+    }
+
     pub fn close_handle(h: RawHandle) -> Result<(), ErrorType> {
         unsafe { syscall(Self::CloseHandle(h).as_args()).map(|_| ()) }
     }
 
     pub fn port_call(h: &Handle, msg: *mut IpcMessage<'a>) -> Result<usize, ErrorType> {
-        unsafe { syscall(Self::PortCall(h.as_raw(), msg).as_args()) }
+        Self::port_call_until(h, msg, None)
+    }
+
+    /// Same as [`Syscall::port_call`], but gives up waiting for the reply at `deadline`
+    pub fn port_call_until(
+        h: &Handle,
+        msg: *mut IpcMessage<'a>,
+        deadline: Option<Duration>,
+    ) -> Result<usize, ErrorType> {
+        unsafe { syscall(Self::PortCall(h.as_raw(), msg, deadline).as_args()) }
     }
 
     pub fn port_send(h: &Handle, msg: *mut IpcMessage<'a>) -> Result<(), ErrorType> {
         unsafe { syscall(Self::PortSend(h.as_raw(), msg).as_args()).map(|_| ()) }
     }
 
+    /// Receives a message without blocking. Fails with [`ErrorType::WouldBlock`] if there is none
     pub fn port_receive(h: &Handle, msg: *mut IpcMessage<'a>) -> Result<usize, ErrorType> {
-        unsafe { syscall(Self::PortReceive(h.as_raw(), msg).as_args()) }
+        Self::port_receive_until(h, msg, Some(Duration::ZERO))
+    }
+
+    /// Receives a message, blocking until `deadline`
+    pub fn port_receive_until(
+        h: &Handle,
+        msg: *mut IpcMessage<'a>,
+        deadline: Option<Duration>,
+    ) -> Result<usize, ErrorType> {
+        unsafe { syscall(Self::PortReceive(h.as_raw(), msg, deadline).as_args()) }
     }
 
     pub fn clone_handle(h: &Handle) -> Result<Handle, ErrorType> {
-        unsafe { syscall(Self::CloneHandle(h.as_raw()).as_args()).map(Handle::new) }
+        unsafe { syscall(Self::CloneHandle(h.as_raw(), None).as_args()).map(Handle::new) }
+    }
+
+    pub fn duplicate_handle(h: &Handle, rights: CapabilityBits) -> Result<Handle, ErrorType> {
+        unsafe { syscall(Self::CloneHandle(h.as_raw(), Some(rights)).as_args()).map(Handle::new) }
+    }
+
+    pub fn revoke_handle(h: &Handle) -> Result<(), ErrorType> {
+        unsafe { syscall(Self::RevokeHandle(h.as_raw()).as_args()).map(|_| ()) }
     }
 
     pub fn get_fdt() -> Result<VirtAddr, ErrorType> {
         unsafe { syscall(Self::GetFdt.as_args()).map(<VirtAddr as Address>::from_bits) }
     }
 
+    /// Waits until any of `sig` is set on the object. Setting only some of them is enough, so
+    /// callers, which need all of them, must check signals again
     pub fn object_wait(h: &Handle, sig: Signals) -> Result<(), ErrorType> {
-        unsafe { syscall(Self::ObjectWait(h.as_raw(), sig).as_args()).map(|_| ()) }
+        Self::object_wait_until(h, sig, None)
     }
 
+    /// Waits for any of `sig` until `deadline`. Fails with [`ErrorType::TimedOut`] once it
+    /// passes, or with [`ErrorType::WouldBlock`] if it has already passed
+    pub fn object_wait_until(
+        h: &Handle,
+        sig: Signals,
+        deadline: Option<Duration>,
+    ) -> Result<(), ErrorType> {
+        unsafe { syscall(Self::ObjectWait(h.as_raw(), sig, deadline).as_args()).map(|_| ()) }
+    }
+
+    /// Waits until any entry has any of its signals set. Pending signals of each entry are
+    /// stored back to it
     pub fn object_wait_many(wait_entries: &'a mut [WaitEntry]) -> Result<(), ErrorType> {
-        unsafe { syscall(Self::ObjectWaitMany(wait_entries).as_args()).map(|_| ()) }
+        Self::object_wait_many_until(wait_entries, None)
+    }
+
+    /// Same as [`Syscall::object_wait_many`], but gives up at `deadline`
+    pub fn object_wait_many_until(
+        wait_entries: &'a mut [WaitEntry],
+        deadline: Option<Duration>,
+    ) -> Result<(), ErrorType> {
+        unsafe { syscall(Self::ObjectWaitMany(wait_entries, deadline).as_args()).map(|_| ()) }
     }
 
     pub fn create_irq(
@@ -192,6 +431,82 @@ impl<'a> Syscall<'a> {
         }
     }
 
+    /// Replies to `reply_port` (if any) and blocks until next message arrives to `h`
+    pub fn port_reply_wait(
+        h: &Handle,
+        reply_port: Option<Handle>,
+        reply: *const IpcMessage<'a>,
+        msg: *mut IpcMessage<'a>,
+    ) -> Result<usize, ErrorType> {
+        let reply_port = reply_port.map_or(HANDLE_INVALID, |reply_port| {
+            let raw = unsafe { reply_port.as_raw() };
+
+            core::mem::forget(reply_port);
+            raw
+        });
+
+        unsafe { syscall(Self::PortReplyWait(h.as_raw(), reply_port, reply, msg).as_args()) }
+    }
+
+    pub fn create_event(factory: &Handle) -> Result<Handle, ErrorType> {
+        unsafe { syscall(Self::CreateEvent(factory.as_raw()).as_args()).map(Handle::new) }
+    }
+
+    pub fn create_event_pair(factory: &Handle) -> Result<(Handle, Handle), ErrorType> {
+        let mut handles = [HANDLE_INVALID; 2];
+
+        unsafe { syscall(Self::CreateEventPair(factory.as_raw(), &mut handles).as_args())? };
+        Ok((Handle::new(handles[0]), Handle::new(handles[1])))
+    }
+
+    /// Clears `clear` and then sets `set` user signals of the object
+    pub fn object_signal(h: &Handle, set: Signals, clear: Signals) -> Result<(), ErrorType> {
+        unsafe { syscall(Self::SignalObject(h.as_raw(), set, clear).as_args()).map(|_| ()) }
+    }
+
+    /// Blocks while `word` equals to `expected`, but not past `deadline`
+    pub fn futex_wait(
+        word: &AtomicU32,
+        expected: u32,
+        deadline: Option<Duration>,
+    ) -> Result<(), ErrorType> {
+        unsafe { syscall(Self::FutexWait(word, expected, deadline).as_args()).map(|_| ()) }
+    }
+
+    /// Wakes up to `count` threads blocked on `word`. Returns number of woken threads
+    pub fn futex_wake(word: &AtomicU32, count: usize) -> Result<usize, ErrorType> {
+        unsafe { syscall(Self::FutexWake(word, count).as_args()) }
+    }
+
+    /// Returns time passed since system boot
+    pub fn clock_get() -> Duration {
+        let nanos = unsafe { syscall(Self::ClockGet.as_args()).unwrap() };
+
+        Duration::from_nanos(nanos as u64)
+    }
+
+    /// Returns memory statistics and fills `tasks` with per-task ones. Returns number of alive
+    /// tasks, which may be larger than `tasks.len()`. `resource` must have `MemInfo` right
+    pub fn mem_info(
+        resource: &Handle,
+        tasks: &mut [TaskMemInfo],
+    ) -> Result<(MemInfo, usize), ErrorType> {
+        let mut info = MemInfo::default();
+        let count = unsafe {
+            syscall(
+                Self::MemInfo(
+                    resource.as_raw(),
+                    &mut info,
+                    tasks.as_mut_ptr(),
+                    tasks.len(),
+                )
+                .as_args(),
+            )?
+        };
+
+        Ok((info, count))
+    }
+
     pub fn as_args(self) -> [usize; 8] {
         match self {
             Syscall::Write(string) => [
@@ -211,18 +526,18 @@ impl<'a> Syscall<'a> {
                 SyscallList::VmAllocate.into(),
                 handle,
                 size,
-                tp as usize,
+                tp.bits(),
                 0,
                 0,
                 0,
                 0,
             ],
-            Syscall::CreateTask(handle, name) => [
+            Syscall::CreateTask(handle, name, job) => [
                 SyscallList::CreateTask.into(),
                 handle,
                 name.as_ptr() as usize,
                 name.len(),
-                0,
+                job,
                 0,
                 0,
                 0,
@@ -237,11 +552,21 @@ impl<'a> Syscall<'a> {
                 0,
                 0,
             ],
+            Syscall::VmProtect(handle, ptr, size, tp) => [
+                SyscallList::VmProtect.into(),
+                handle,
+                ptr as usize,
+                size,
+                tp.bits(),
+                0,
+                0,
+                0,
+            ],
             Syscall::CreateVmo(handle, size, tp) => [
                 SyscallList::CreateVmo.into(),
                 handle,
                 size,
-                tp as usize,
+                tp.bits(),
                 0,
                 0,
                 0,
@@ -251,7 +576,7 @@ impl<'a> Syscall<'a> {
                 SyscallList::CreateVmoContig.into(),
                 handle,
                 size,
-                tp as usize,
+                tp.bits(),
                 0,
                 0,
                 0,
@@ -260,32 +585,62 @@ impl<'a> Syscall<'a> {
             Syscall::VmoGetPhysInfo(handle) => {
                 [SyscallList::VmoGetPhysInfo.into(), handle, 0, 0, 0, 0, 0, 0]
             }
+            Syscall::VmoCreateChild(handle, kind, offset, size) => [
+                SyscallList::VmoCreateChild.into(),
+                handle,
+                kind as usize,
+                offset,
+                size,
+                0,
+                0,
+                0,
+            ],
+            Syscall::CreatePagerVmo(handle, size, tp, port, key) => [
+                SyscallList::CreatePagerVmo.into(),
+                handle,
+                size,
+                tp.bits(),
+                port,
+                key,
+                0,
+                0,
+            ],
+            Syscall::VmoSupplyPages(handle, offset, data, len) => [
+                SyscallList::VmoSupplyPages.into(),
+                handle,
+                offset,
+                data as usize,
+                len,
+                0,
+                0,
+                0,
+            ],
             Syscall::VmMapVmo(vms, vmo, to, tp) => [
                 SyscallList::MapVmo.into(),
                 vms,
                 vmo,
                 to.bits(),
-                tp as usize,
+                tp.bits(),
                 0,
                 0,
                 0,
             ],
-            Syscall::TaskStart(handle, ep, boot_handle) => [
+            Syscall::TaskStart(handle, ep, boot_handle, resource) => [
                 SyscallList::TaskStart.into(),
                 handle,
                 ep.into(),
                 boot_handle,
-                0,
+                resource,
                 0,
                 0,
                 0,
             ],
-            Syscall::VmMapPhys(vms, pa, size) => [
+            Syscall::VmMapPhys(vms, pa, size, resource) => [
                 SyscallList::MapPhys.into(),
                 vms,
                 pa.into(),
                 size,
-                0,
+                resource,
                 0,
                 0,
                 0,
@@ -293,11 +648,11 @@ impl<'a> Syscall<'a> {
             Syscall::CloseHandle(handle) => {
                 [SyscallList::CloseHandle.into(), handle, 0, 0, 0, 0, 0, 0]
             }
-            Syscall::PortCall(handle, msg) => [
+            Syscall::PortCall(handle, msg, deadline) => [
                 SyscallList::PortCall.into(),
                 handle,
                 msg as *mut _ as usize,
-                0,
+                deadline_arg(deadline),
                 0,
                 0,
                 0,
@@ -313,11 +668,11 @@ impl<'a> Syscall<'a> {
                 0,
                 0,
             ],
-            Syscall::PortReceive(handle, msg) => [
+            Syscall::PortReceive(handle, msg, deadline) => [
                 SyscallList::PortReceive.into(),
                 handle,
                 msg as *mut _ as usize,
-                0,
+                deadline_arg(deadline),
                 0,
                 0,
                 0,
@@ -333,25 +688,44 @@ impl<'a> Syscall<'a> {
                 0,
                 0,
             ],
+            Syscall::PortReplyWait(handle, reply_port, reply, msg) => [
+                SyscallList::PortReplyWait.into(),
+                handle,
+                reply_port,
+                reply as *const _ as usize,
+                msg as *mut _ as usize,
+                DEADLINE_INFINITE,
+                0,
+                0,
+            ],
             Syscall::VmsHandle(h) => [SyscallList::TaskGetVms.into(), h, 0, 0, 0, 0, 0, 0],
             Syscall::Yield => [SyscallList::Yield.into(), 0, 0, 0, 0, 0, 0, 0],
-            Syscall::CloneHandle(h) => [SyscallList::CloneHandle.into(), h, 0, 0, 0, 0, 0, 0],
+            Syscall::CloneHandle(h, rights) => [
+                SyscallList::CloneHandle.into(),
+                h,
+                rights.map(|x| x.bits()).unwrap_or(SAME_RIGHTS),
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
             Syscall::GetFdt => [SyscallList::MapFdt.into(), 0, 0, 0, 0, 0, 0, 0],
-            Syscall::ObjectWait(h, sig) => [
+            Syscall::ObjectWait(h, sig, deadline) => [
                 SyscallList::WaitObject.into(),
                 h,
                 (*sig).into(),
-                0,
+                deadline_arg(deadline),
                 0,
                 0,
                 0,
                 0,
             ],
-            Syscall::ObjectWaitMany(entries) => [
+            Syscall::ObjectWaitMany(entries, deadline) => [
                 SyscallList::WaitObjectMany.into(),
                 entries.as_mut_ptr() as usize,
                 entries.len(),
-                0,
+                deadline_arg(deadline),
                 0,
                 0,
                 0,
@@ -383,6 +757,114 @@ impl<'a> Syscall<'a> {
                 0,
                 0,
             ],
+            Syscall::RevokeHandle(handle) => {
+                [SyscallList::RevokeHandle.into(), handle, 0, 0, 0, 0, 0, 0]
+            }
+            Syscall::Exit(code) => [SyscallList::Exit.into(), code, 0, 0, 0, 0, 0, 0],
+            Syscall::TaskKill(handle, code) => {
+                [SyscallList::TaskKill.into(), handle, code, 0, 0, 0, 0, 0]
+            }
+            Syscall::TaskExitCode(handle, code) => [
+                SyscallList::TaskExitCode.into(),
+                handle,
+                code as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            Syscall::CreateThread(task) => {
+                [SyscallList::CreateThread.into(), task, 0, 0, 0, 0, 0, 0]
+            }
+            Syscall::ThreadStart(thread, ep, arg) => {
+                [SyscallList::ThreadStart.into(), thread, ep, arg, 0, 0, 0, 0]
+            }
+            Syscall::ThreadExit => [SyscallList::ThreadExit.into(), 0, 0, 0, 0, 0, 0, 0],
+            Syscall::ThreadSetPriority(thread, class, priority, resource) => [
+                SyscallList::ThreadSetPriority.into(),
+                thread,
+                class as usize,
+                priority,
+                resource,
+                0,
+                0,
+                0,
+            ],
+            Syscall::ThreadSetAffinity(thread, mask) => [
+                SyscallList::ThreadSetAffinity.into(),
+                thread,
+                mask,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            Syscall::ClockGet => [SyscallList::ClockGet.into(), 0, 0, 0, 0, 0, 0, 0],
+            Syscall::CreateEvent(factory) => {
+                [SyscallList::CreateEvent.into(), factory, 0, 0, 0, 0, 0, 0]
+            }
+            Syscall::CreateEventPair(factory, handles) => [
+                SyscallList::CreateEventPair.into(),
+                factory,
+                handles as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            Syscall::SignalObject(h, set, clear) => [
+                SyscallList::SignalObject.into(),
+                h,
+                (*set).into(),
+                (*clear).into(),
+                0,
+                0,
+                0,
+                0,
+            ],
+            Syscall::FutexWait(word, expected, deadline) => [
+                SyscallList::FutexWait.into(),
+                word as usize,
+                expected as usize,
+                deadline_arg(deadline),
+                0,
+                0,
+                0,
+                0,
+            ],
+            Syscall::FutexWake(word, count) => [
+                SyscallList::FutexWake.into(),
+                word as usize,
+                count,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            Syscall::CreateJob(factory, parent, limits) => [
+                SyscallList::CreateJob.into(),
+                factory,
+                parent,
+                limits as usize,
+                0,
+                0,
+                0,
+                0,
+            ],
+            Syscall::MemInfo(resource, info, tasks, count) => [
+                SyscallList::MemInfo.into(),
+                resource,
+                info as usize,
+                tasks as usize,
+                count,
+                0,
+                0,
+                0,
+            ],
         }
     }
 }
@@ -7,4 +7,10 @@ type ComponentString = String<200>;
 pub struct Manifest {
     pub name: ComponentString,
     pub env: Option<ComponentString>,
+    /// Component may run real-time threads
+    pub realtime: bool,
+    /// Component may read memory statistics of all tasks
+    pub meminfo: bool,
+    /// Component may map device memory
+    pub mapphys: bool,
 }
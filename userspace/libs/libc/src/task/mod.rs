@@ -1,5 +1,7 @@
 pub mod manifest;
 pub mod task;
+pub mod thread;
 
 pub use manifest::*;
 pub use task::*;
+pub use thread::*;
@@ -2,15 +2,18 @@ use super::Manifest;
 use crate::elf::Elf;
 use crate::factory::factory;
 use crate::handle::Handle;
+use crate::job::Job;
 use crate::syscalls::Syscall;
 use crate::vmm::vms::Vms;
 use crate::vmm::vms::vms;
 use alloc::string::String;
 use alloc::vec::Vec;
+use elf::segment::ProgramHeader;
 use hal::address::{Address, VirtAddr, VirtualAddress};
 use hal::arch::{PAGE_MASK, PAGE_SIZE};
 use postcard::from_bytes;
 use rtl::error::ErrorType;
+use rtl::signal::Signal;
 use rtl::vmm::MappingType;
 
 pub struct Task {
@@ -45,61 +48,114 @@ impl Task {
     }
 
     pub fn create_from_elf(elf_data: &[u8], name: String) -> Result<Self, ErrorType> {
+        Self::create_from_elf_in(elf_data, name, None)
+    }
+
+    /// Picks load bias of position independent image. Space is reserved and released right away,
+    /// so the image gets the same (possibly randomized) placement as any other mapping
+    fn load_bias(vms: &Vms, ph: &[ProgramHeader]) -> Result<usize, ErrorType> {
+        let start = ph
+            .iter()
+            .map(|x| x.p_vaddr as usize & !PAGE_MASK)
+            .min()
+            .ok_or(ErrorType::InvalidArgument)?;
+        let end = ph
+            .iter()
+            .map(|x| (x.p_vaddr + x.p_memsz) as usize)
+            .max()
+            .ok_or(ErrorType::InvalidArgument)?
+            .next_multiple_of(PAGE_SIZE);
+        let base = vms.vm_allocate(end - start, MappingType::RODATA)?;
+
+        vms.vm_free(base, end - start)?;
+        Ok(base as usize - start)
+    }
+
+    /// Same as [`Task::create_from_elf`], but the new task is placed into `job`
+    pub fn create_from_elf_in(
+        elf_data: &[u8],
+        name: String,
+        job: Option<&Job>,
+    ) -> Result<Self, ErrorType> {
         let elf = Elf::new(elf_data).ok_or(ErrorType::InvalidArgument)?;
         let ph = elf.program_headers().ok_or(ErrorType::InvalidArgument)?;
         let mut h = Vec::with_capacity(ph.len());
         let manifest = Self::parse_manifest(&elf)?;
+        let mut new_task = factory().create_task_in(name.as_str(), job)?;
+        let task_vms = new_task.vms().unwrap();
+        let (bias, relocations) = if elf.is_dyn() {
+            (
+                Self::load_bias(&task_vms, &ph)?,
+                elf.relative_relocations()
+                    .ok_or(ErrorType::InvalidArgument)?,
+            )
+        } else {
+            (0, Vec::new())
+        };
 
         for phdr in ph {
             let load_addr = VirtAddr::from(phdr.p_vaddr as usize);
             let to_allocate = ((load_addr.bits() + phdr.p_memsz as usize + PAGE_SIZE) & !PAGE_MASK)
                 - (load_addr.bits() & !PAGE_MASK);
 
+            let tp = Elf::program_header_to_mapping_type(phdr);
             let vm = if phdr.p_filesz != 0 {
-                let res = factory().create_vm_object(to_allocate, MappingType::Rwx)?;
+                let image = factory().create_vm_object(to_allocate, MappingType::RWX)?;
+                let mut va = vms().map_vm_object(&image, None, MappingType::DATA)?;
 
                 unsafe {
-                    // TODO: unmap
-                    let mut va = vms().map_vm_object(&res, None, MappingType::Data)?;
                     let slice = va.as_slice_at_offset_mut::<u8>(
                         phdr.p_filesz as usize,
                         phdr.p_vaddr as usize & PAGE_MASK,
                     );
 
                     slice.copy_from_slice(elf.program_header_to_data(phdr));
+
+                    for (offset, addend) in relocations.iter().filter(|(offset, _)| {
+                        (phdr.p_vaddr..phdr.p_vaddr + phdr.p_memsz).contains(&(*offset as u64))
+                    }) {
+                        let slice = va.as_slice_at_offset_mut::<u8>(
+                            size_of::<usize>(),
+                            offset - (load_addr.bits() & !PAGE_MASK),
+                        );
+
+                        slice.copy_from_slice(&(bias + addend).to_le_bytes());
+                    }
                 }
 
-                res
+                vms().vm_free(va.to_raw_mut::<u8>(), to_allocate)?;
+
+                // Writable segments get private copy of the image on first write
+                if tp.contains(MappingType::WRITE) {
+                    image.create_cow_child(0, to_allocate)?
+                } else {
+                    image
+                }
             } else {
-                factory()
-                    .create_vm_object(to_allocate, Elf::program_header_to_mapping_type(phdr))?
+                factory().create_vm_object(to_allocate, tp)?
             };
 
-            h.push((vm, load_addr, Elf::program_header_to_mapping_type(phdr)));
+            h.push((vm, load_addr, tp));
         }
 
-        let mut new_task = factory().create_task(name.as_str())?;
-        let vms = new_task.vms().unwrap();
-
+        // Mapping keeps VMO alive, so handles can be dropped afterwards
         for (vmo, load, tp) in h {
-            let mut load = load.bits();
+            let mut load = load.bits() + bias;
 
             load &= !PAGE_MASK;
-            vms.map_vm_object(&vmo, Some(VirtAddr::from_bits(load)), tp)
+            task_vms
+                .map_vm_object(&vmo, Some(VirtAddr::from_bits(load)), tp)
                 .unwrap();
-
-            // TODO: dropping vmo would mean freeing pages. These pages are mapped to the new task,
-            // so here we leak VMO on purpose, keeping pages around.
-            core::mem::forget(vmo);
         }
 
-        new_task.set_ep(elf.entry_point());
+        new_task.set_ep(VirtAddr::from_bits(elf.entry_point().bits() + bias));
         new_task.manifest = Some(manifest);
         Ok(new_task)
     }
 
-    pub fn start(&mut self, h: &Handle) -> Option<()> {
-        Syscall::task_start(&self.h, self.ep, h).ok()
+    /// Starts the task with `h` as its boot handle. `resource` grants it privileged operations
+    pub fn start(&mut self, h: &Handle, resource: Option<&Handle>) -> Option<()> {
+        Syscall::task_start(&self.h, self.ep, h, resource).ok()
     }
 
     pub fn name(&self) -> &str {
@@ -109,4 +165,28 @@ impl Task {
     pub fn vms(&self) -> Option<Vms> {
         Some(Vms::new(Syscall::task_get_vms(&self.h).ok()?))
     }
+
+    pub fn handle(&self) -> &Handle {
+        &self.h
+    }
+
+    pub fn kill(&self, code: usize) -> Result<(), ErrorType> {
+        Syscall::task_kill(&self.h, code)
+    }
+
+    /// Blocks until task is terminated and returns its exit code
+    pub fn wait(&self) -> Result<usize, ErrorType> {
+        Syscall::object_wait(&self.h, Signal::TaskTerminated.into())?;
+        self.exit_code()
+    }
+
+    /// Returns exit code of the task. Fails with WouldBlock if task is still running
+    pub fn exit_code(&self) -> Result<usize, ErrorType> {
+        Syscall::task_exit_code(&self.h)
+    }
+}
+
+/// Terminates current task
+pub fn exit(code: usize) -> ! {
+    Syscall::exit(code)
 }
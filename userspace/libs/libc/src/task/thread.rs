@@ -0,0 +1,63 @@
+use crate::handle::Handle;
+use crate::resource::resource;
+use crate::syscalls::Syscall;
+use alloc::boxed::Box;
+use rtl::error::ErrorType;
+use rtl::sched::SchedClass;
+use rtl::signal::Signal;
+
+type ThreadFn = Box<dyn FnOnce() + Send>;
+
+/// User-space thread of the current task
+pub struct Thread {
+    h: Handle,
+}
+
+extern "C" fn thread_entry(arg: usize) -> ! {
+    let f = unsafe { Box::from_raw(arg as *mut ThreadFn) };
+
+    f();
+    Syscall::thread_exit()
+}
+
+impl Thread {
+    /// Spawns new thread running `f`
+    pub fn spawn<F: FnOnce() + Send + 'static>(f: F) -> Result<Self, ErrorType> {
+        let h = Syscall::create_thread(None)?;
+        let f: Box<ThreadFn> = Box::new(Box::new(f));
+        let arg = Box::into_raw(f);
+
+        Syscall::thread_start(&h, thread_entry as usize, arg as usize).inspect_err(|_| {
+            drop(unsafe { Box::from_raw(arg) });
+        })?;
+
+        Ok(unsafe { Self::new(h) })
+    }
+
+    pub unsafe fn new(h: Handle) -> Self {
+        Self { h }
+    }
+
+    /// Blocks until thread is terminated
+    pub fn join(&self) -> Result<(), ErrorType> {
+        Syscall::object_wait(&self.h, Signal::ThreadTerminated.into())
+    }
+
+    pub fn set_priority(&self, class: SchedClass, priority: usize) -> Result<(), ErrorType> {
+        Syscall::thread_set_priority(Some(&self.h), class, priority, resource())
+    }
+
+    /// Restricts thread to CPUs from `mask`, one bit per CPU
+    pub fn set_affinity(&self, mask: usize) -> Result<(), ErrorType> {
+        Syscall::thread_set_affinity(Some(&self.h), mask)
+    }
+
+    pub fn handle(&self) -> &Handle {
+        &self.h
+    }
+}
+
+/// Terminates current thread. Task is terminated with its last thread
+pub fn exit_thread() -> ! {
+    Syscall::thread_exit()
+}
@@ -1,7 +1,9 @@
 use crate::handle::Handle;
 use crate::syscalls::Syscall;
 use hal::address::PhysAddr;
+use rtl::capabilities::Capability;
 use rtl::error::ErrorType;
+use rtl::vmm::VmoChildKind;
 
 pub struct VmObject {
     h: Handle,
@@ -16,6 +18,28 @@ impl VmObject {
         &self.h
     }
 
+    /// Creates read-only handle to the same VMO
+    pub fn read_only(&self) -> Result<Self, ErrorType> {
+        let rights =
+            Capability::Read | Capability::Map | Capability::Duplicate | Capability::Transfer;
+
+        Ok(unsafe { Self::new(self.h.duplicate(rights)?) })
+    }
+
+    /// Creates copy-on-write snapshot of `size` bytes of the VMO starting from `offset`. Pages
+    /// are shared until either VMO writes to them
+    pub fn create_cow_child(&self, offset: usize, size: usize) -> Result<Self, ErrorType> {
+        let h = Syscall::vmo_create_child(&self.h, VmoChildKind::Cow, offset, size)?;
+
+        Ok(unsafe { Self::new(h) })
+    }
+
+    /// Supplies contents of pager VMO starting from `offset`. Both `offset` and length of `data`
+    /// must be page aligned
+    pub fn supply_pages(&self, offset: usize, data: &[u8]) -> Result<(), ErrorType> {
+        Syscall::vmo_supply_pages(&self.h, offset, data)
+    }
+
     pub fn get_phys_info(&self) -> Result<PhysAddr, ErrorType> {
         Syscall::vmo_get_phys_info(&self.h)
     }
@@ -1,4 +1,5 @@
 use crate::handle::Handle;
+use crate::resource::resource;
 use crate::syscalls::Syscall;
 use crate::vmm::vm_object::VmObject;
 use hal::address::*;
@@ -24,6 +25,12 @@ impl Vms {
         Syscall::vm_free(&self.h, addr, size)
     }
 
+    /// Changes permissions of the whole mapping at `addr`. Memory type stays the same. VMO
+    /// mappings can't get permissions they were not mapped with
+    pub fn vm_protect(&self, addr: *mut u8, size: usize, tp: MappingType) -> Result<(), ErrorType> {
+        Syscall::vm_protect(&self.h, addr, size, tp)
+    }
+
     pub fn map_vm_object(
         &self,
         o: &VmObject,
@@ -38,8 +45,9 @@ impl Vms {
         )
     }
 
+    /// Maps device memory. Requires resource handle of the task with `MapPhys` right
     pub fn map_phys(&self, p: MemRange<PhysAddr>) -> Result<VirtAddr, ErrorType> {
-        Syscall::vm_map_phys(&self.h, p.start(), p.size()).map(VirtAddr::from)
+        Syscall::vm_map_phys(&self.h, p.start(), p.size(), resource()).map(VirtAddr::from)
     }
 }
 
@@ -8,7 +8,19 @@ extern crate proc_macro;
 extern crate syn;
 
 #[proc_macro_attribute]
-pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn main(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut workers: Option<syn::LitInt> = None;
+    let attr_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("worker_threads") {
+            workers = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported rokio::main property"))
+        }
+    });
+
+    parse_macro_input!(attr with attr_parser);
+
     // Parse the input tokens into a syntax tree
     let input_fn = parse_macro_input!(item as ItemFn);
     let fn_name = &input_fn.sig.ident;
@@ -30,11 +42,21 @@ pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
         panic!("Function must be called main!")
     }
 
+    let block_on = match workers {
+        Some(workers) => quote! {
+            rokio::executor::block_on_multi(real_main(handle), #workers)
+                .expect("Failed to spawn worker threads");
+        },
+        None => quote! {
+            rokio::executor::block_on(real_main(handle));
+        },
+    };
+
     // Build the output, possibly using quasi-quotation
     let expanded = quote! {
         #[libc::main]
         fn main(handle: Option<Handle>) {
-            rokio::executor::block_on(real_main(handle));
+            #block_on
         }
 
         #old_func
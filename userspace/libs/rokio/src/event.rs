@@ -0,0 +1,129 @@
+use super::executor::{Waiter, WaiterState};
+use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use libc::event::{Event as LibcEvent, EventPair as LibcEventPair};
+use libc::handle::Handle;
+use rtl::error::ErrorType;
+use rtl::signal::Signals;
+
+struct SignalFuture<'a> {
+    handle: &'a Handle,
+    signals: Signals,
+    state: Option<Arc<WaiterState>>,
+}
+
+impl Drop for SignalFuture<'_> {
+    fn drop(&mut self) {
+        if let Some(ref state) = self.state {
+            state.cancel();
+        }
+    }
+}
+
+impl Future for SignalFuture<'_> {
+    type Output = Result<(), ErrorType>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let cur = self.get_mut();
+
+        if let Some(ref state) = cur.state {
+            if state.completed() {
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Pending
+            }
+        } else {
+            let state = WaiterState::new(cx.waker().clone());
+
+            let waiter = Waiter::new(unsafe { cur.handle.as_raw() }, cur.signals, state.clone());
+
+            cur.state = Some(state);
+            super::executor::current_runtime().add_wait(waiter);
+            Poll::Pending
+        }
+    }
+}
+
+/// Waits until any of `signals` is set on the object behind `handle`
+pub async fn wait_signals(handle: &Handle, signals: Signals) -> Result<(), ErrorType> {
+    SignalFuture {
+        handle,
+        signals,
+        state: None,
+    }
+    .await
+}
+
+pub struct Event {
+    event: LibcEvent,
+}
+
+impl Event {
+    pub fn new() -> Result<Self, ErrorType> {
+        Ok(Self {
+            event: LibcEvent::create()?,
+        })
+    }
+
+    pub unsafe fn new_from_handle(h: Handle) -> Self {
+        Self {
+            event: unsafe { LibcEvent::new(h) },
+        }
+    }
+
+    pub fn signal(&self, set: Signals, clear: Signals) -> Result<(), ErrorType> {
+        self.event.signal(set, clear)
+    }
+
+    /// Waits until any of `signals` is set
+    pub async fn wait(&self, signals: Signals) -> Result<(), ErrorType> {
+        SignalFuture {
+            handle: self.event.handle(),
+            signals,
+            state: None,
+        }
+        .await
+    }
+
+    pub fn handle(&self) -> &Handle {
+        self.event.handle()
+    }
+}
+
+pub struct EventPair {
+    pair: LibcEventPair,
+}
+
+impl EventPair {
+    pub fn new() -> Result<(Self, Self), ErrorType> {
+        let (first, second) = LibcEventPair::create()?;
+
+        Ok((Self { pair: first }, Self { pair: second }))
+    }
+
+    pub unsafe fn new_from_handle(h: Handle) -> Self {
+        Self {
+            pair: unsafe { LibcEventPair::new(h) },
+        }
+    }
+
+    pub fn signal_peer(&self, set: Signals, clear: Signals) -> Result<(), ErrorType> {
+        self.pair.signal_peer(set, clear)
+    }
+
+    /// Waits until any of `signals` is set by the peer
+    pub async fn wait(&self, signals: Signals) -> Result<(), ErrorType> {
+        SignalFuture {
+            handle: self.pair.handle(),
+            signals,
+            state: None,
+        }
+        .await
+    }
+
+    pub fn handle(&self) -> &Handle {
+        self.pair.handle()
+    }
+}
@@ -1,11 +1,15 @@
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use async_task::Runnable;
 use core::future::Future;
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use core::task::Waker;
+use core::time::Duration;
 use crossbeam::queue::SegQueue;
+use libc::sync::Mutex;
 use libc::syscalls::Syscall;
+use libc::task::Thread;
 use rtl::error::ErrorType;
 use rtl::handle::Handle as RawHandle;
 use rtl::signal::{Signal, Signals, WaitEntry};
@@ -14,14 +18,23 @@ use spin::lazy::Lazy;
 static CURRENT_RUNTIME: Lazy<Runtime> = Lazy::new(Runtime::new);
 
 /// Async runtime on top of SAMOS objects
-#[derive(Default)]
 pub struct Runtime {
     runnable: SegQueue<Runnable>,
     waiting: SegQueue<Waiter>,
+    // Number of spawned, but not yet completed tasks
+    tasks: AtomicUsize,
+    // Sleeping futures keyed by (deadline, id). Earliest deadline bounds the wait for events
+    timers: Mutex<BTreeMap<(Duration, u64), Waker>>,
+    timer_ids: AtomicU64,
+    // Bumped whenever idle workers may have something to do. They sleep on it as on a futex
+    work_seq: AtomicU32,
+    // Number of workers sleeping on `work_seq`
+    idle: AtomicUsize,
 }
 
 pub(crate) struct WaiterState {
     completed: AtomicBool,
+    cancelled: AtomicBool,
     waker: Waker,
 }
 
@@ -35,6 +48,7 @@ impl WaiterState {
     pub fn new(waker: Waker) -> Arc<Self> {
         Arc::new(Self {
             completed: false.into(),
+            cancelled: false.into(),
             waker,
         })
     }
@@ -42,6 +56,15 @@ impl WaiterState {
     pub fn completed(&self) -> bool {
         self.completed.load(Ordering::Acquire)
     }
+
+    /// Called when future waiting for the event is dropped, so runtime stops waiting for it
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
 }
 
 impl Waiter {
@@ -54,24 +77,99 @@ impl Waiter {
     }
 }
 
+impl Default for Runtime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Runtime {
     /// Constructs new runtime
     pub fn new() -> Self {
-        Self::default()
+        Self {
+            runnable: SegQueue::new(),
+            waiting: SegQueue::new(),
+            tasks: AtomicUsize::new(0),
+            timers: Mutex::new(BTreeMap::new()),
+            timer_ids: AtomicU64::new(0),
+            work_seq: AtomicU32::new(0),
+            idle: AtomicUsize::new(0),
+        }
+    }
+
+    /// Spawns `f`, which may be polled by any worker thread
+    pub fn spawn<F: Future + Send + 'static>(&'static self, f: F)
+    where
+        F::Output: Send,
+    {
+        unsafe { self.spawn_unchecked(f) }
     }
 
-    pub fn spawn<F: Future>(&'static self, f: F)
+    /// # Safety
+    ///
+    /// `f` is not checked to be [`Send`] and `'static`, so caller must make sure it's polled only
+    /// by the current thread and completes before anything it borrows goes away
+    unsafe fn spawn_unchecked<F: Future>(&'static self, f: F)
     where
         F::Output: Send,
     {
+        let f = async move {
+            let res = f.await;
+
+            // Last task is done, so idle workers should exit
+            if self.tasks.fetch_sub(1, Ordering::AcqRel) == 1 {
+                self.notify(usize::MAX);
+            }
+
+            res
+        };
+
+        self.tasks.fetch_add(1, Ordering::AcqRel);
+
         let (runnable, task) = unsafe {
-            async_task::spawn_unchecked(f, |runnable: Runnable| self.runnable.push(runnable))
+            async_task::spawn_unchecked(f, |runnable: Runnable| {
+                self.runnable.push(runnable);
+                self.notify(1);
+            })
         };
 
         task.detach();
         runnable.schedule();
     }
 
+    /// Wakes up to `count` idle workers
+    fn notify(&self, count: usize) {
+        self.work_seq.fetch_add(1, Ordering::SeqCst);
+
+        if self.idle.load(Ordering::SeqCst) != 0 {
+            Syscall::futex_wake(&self.work_seq, count).unwrap();
+        }
+    }
+
+    fn has_work(&self) -> bool {
+        !self.runnable.is_empty()
+            || !self.waiting.is_empty()
+            || self.next_deadline().is_some()
+            || self.tasks.load(Ordering::Acquire) == 0
+    }
+
+    /// Blocks the worker until it's notified about new work
+    fn park(&self) {
+        self.idle.fetch_add(1, Ordering::SeqCst);
+
+        let seq = self.work_seq.load(Ordering::SeqCst);
+
+        // Work could be queued before this worker became visible as idle
+        if !self.has_work() {
+            match Syscall::futex_wait(&self.work_seq, seq, None) {
+                Ok(()) | Err(ErrorType::TryAgain) => {}
+                Err(err) => panic!("Failed to park worker: {err:?}"),
+            }
+        }
+
+        self.idle.fetch_sub(1, Ordering::SeqCst);
+    }
+
     fn poll_runnable(&'static self) {
         while let Some(task) = self.runnable.pop() {
             task.run();
@@ -80,12 +178,54 @@ impl Runtime {
 
     pub(crate) fn add_wait(&self, w: Waiter) {
         self.waiting.push(w);
+        self.notify(1);
+    }
+
+    pub(crate) fn alloc_timer_id(&self) -> u64 {
+        self.timer_ids.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Wakes `waker` once `deadline` passes. Replaces waker of the timer with the same `id`
+    pub(crate) fn set_timer(&self, deadline: Duration, id: u64, waker: Waker) {
+        self.timers.lock().insert((deadline, id), waker);
+        self.notify(1);
+    }
+
+    pub(crate) fn remove_timer(&self, deadline: Duration, id: u64) {
+        self.timers.lock().remove(&(deadline, id));
+    }
+
+    fn next_deadline(&self) -> Option<Duration> {
+        self.timers
+            .lock()
+            .first_key_value()
+            .map(|((deadline, _), _)| *deadline)
+    }
+
+    fn fire_timers(&self) -> usize {
+        let now = Syscall::clock_get();
+        let mut timers = self.timers.lock();
+        let mut fired = 0;
+
+        while let Some(entry) = timers.first_entry()
+            && entry.key().0 <= now
+        {
+            entry.remove().wake();
+            fired += 1;
+        }
+
+        fired
     }
 
     fn wait(&self) -> Result<usize, ErrorType> {
         let mut wait_entries = Vec::new();
 
         while let Some(entry) = self.waiting.pop() {
+            // Nobody is interested in this event anymore
+            if entry.state.cancelled() {
+                continue;
+            }
+
             let we = WaitEntry {
                 handle: entry.handle,
                 waitfor: entry.waitfor,
@@ -96,13 +236,18 @@ impl Runtime {
             wait_entries.push(we);
         }
 
-        if wait_entries.is_empty() {
+        let deadline = self.next_deadline();
+
+        if wait_entries.is_empty() && deadline.is_none() {
             return Ok(0);
         }
 
-        Syscall::object_wait_many(&mut wait_entries)?;
+        match Syscall::object_wait_many_until(&mut wait_entries, deadline) {
+            Ok(()) | Err(ErrorType::TimedOut | ErrorType::WouldBlock) => {}
+            Err(err) => return Err(err),
+        }
 
-        let mut waked = 0;
+        let mut waked = self.fire_timers();
 
         for entry in wait_entries {
             let state: Arc<WaiterState> = unsafe { Arc::from_raw(entry.context as *const _) };
@@ -124,14 +269,29 @@ impl Runtime {
     }
 
     pub fn run(&'static self) {
-        while !self.waiting.is_empty() || !self.runnable.is_empty() {
+        while self.tasks.load(Ordering::Acquire) != 0 {
             // Poll ready tasks
             self.poll_runnable();
 
-            // Wait for events to occur
-            self.wait().unwrap();
+            // Wait for events to occur. Waiters could be taken by other worker threads, so if
+            // there is nothing to do sleep until some task is scheduled or starts waiting
+            if self.wait().unwrap() == 0 && self.runnable.is_empty() {
+                self.park();
+            }
         }
     }
+
+    /// Spawns `count` additional worker threads, which run tasks of this runtime
+    fn spawn_workers(&'static self, count: usize) -> Result<(), ErrorType> {
+        for _ in 0..count {
+            let worker = Thread::spawn(move || self.run())?;
+
+            // Workers are detached. They exit once runtime has no more tasks
+            core::mem::forget(worker);
+        }
+
+        Ok(())
+    }
 }
 
 pub(crate) fn current_runtime() -> &'static Runtime {
@@ -145,11 +305,23 @@ where
     CURRENT_RUNTIME.spawn(f)
 }
 
-// TODO: add back + Send when I figure out wtf rust wants from me
+/// Runs `f` and all tasks spawned by it on the current thread
 pub fn block_on<F: Future>(f: F)
+where
+    F::Output: Send,
+{
+    // Single worker polls `f` and does not return until all tasks are done
+    unsafe { CURRENT_RUNTIME.spawn_unchecked(f) };
+    CURRENT_RUNTIME.run();
+}
+
+/// Same as [`block_on`], but runs tasks on `workers` threads
+pub fn block_on_multi<F: Future + Send + 'static>(f: F, workers: usize) -> Result<(), ErrorType>
 where
     F::Output: Send,
 {
     CURRENT_RUNTIME.spawn(f);
+    CURRENT_RUNTIME.spawn_workers(workers.saturating_sub(1))?;
     CURRENT_RUNTIME.run();
+    Ok(())
 }
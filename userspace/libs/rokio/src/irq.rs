@@ -17,6 +17,14 @@ struct IrqFuture<'a> {
     state: Option<Arc<WaiterState>>,
 }
 
+impl Drop for IrqFuture<'_> {
+    fn drop(&mut self) {
+        if let Some(ref state) = self.state {
+            state.cancel();
+        }
+    }
+}
+
 impl Future for IrqFuture<'_> {
     type Output = Result<(), ErrorType>;
 
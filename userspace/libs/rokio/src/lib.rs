@@ -2,8 +2,10 @@
 
 extern crate alloc;
 
+pub mod event;
 pub mod executor;
 pub mod port;
+pub mod select;
 pub mod timer;
 pub mod irq;
 pub use rokio_proc::*;
@@ -7,6 +7,7 @@ use libc::handle::Handle;
 use libc::port::Port as LibcPort;
 use rtl::error::ErrorType;
 use rtl::ipc::IpcMessage;
+use rtl::signal::Signal;
 
 pub struct Port {
     port: LibcPort,
@@ -15,7 +16,15 @@ pub struct Port {
 struct RecvFuture<'a> {
     port: &'a LibcPort,
     msg: usize,
-    _state: Option<Arc<WaiterState>>,
+    state: Option<Arc<WaiterState>>,
+}
+
+impl Drop for RecvFuture<'_> {
+    fn drop(&mut self) {
+        if let Some(ref state) = self.state {
+            state.cancel();
+        }
+    }
 }
 
 impl Future for RecvFuture<'_> {
@@ -30,12 +39,17 @@ impl Future for RecvFuture<'_> {
             Err(ErrorType::WouldBlock) => {
                 let state = WaiterState::new(cx.waker().clone());
 
+                // Peer may go away instead of sending anything. Next receive reports it
                 let waiter = Waiter::new(
                     unsafe { cur.port.handle().as_raw() },
-                    rtl::signal::Signal::MessageReady.into(),
+                    Signal::MessageReady | Signal::PeerClosed,
                     state.clone(),
                 );
 
+                if let Some(old) = cur.state.replace(state) {
+                    old.cancel();
+                }
+
                 super::executor::current_runtime().add_wait(waiter);
                 Poll::Pending
             }
@@ -65,7 +79,7 @@ impl Port {
         RecvFuture {
             port: &reply_port,
             msg: msg as *mut _ as usize,
-            _state: None,
+            state: None,
         }
         .await
     }
@@ -78,8 +92,13 @@ impl Port {
         RecvFuture {
             port: &self.port,
             msg: msg as *mut _ as usize,
-            _state: None,
+            state: None,
         }
         .await
     }
+
+    /// Unmaps large payload the kernel has mapped into the address space on receive
+    pub fn release_mapped_arena(msg: &IpcMessage) -> Result<(), ErrorType> {
+        LibcPort::release_mapped_arena(msg)
+    }
 }
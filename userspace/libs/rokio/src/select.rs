@@ -0,0 +1,25 @@
+use core::future::{Future, poll_fn};
+use core::pin::pin;
+use core::task::Poll;
+
+/// Output of [`select`]
+pub enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+
+/// Waits for the first of two futures to complete and drops the other one. `a` is polled first,
+/// so it wins if both are ready.
+pub async fn select<A: Future, B: Future>(a: A, b: B) -> Either<A::Output, B::Output> {
+    let mut a = pin!(a);
+    let mut b = pin!(b);
+
+    poll_fn(|cx| {
+        if let Poll::Ready(res) = a.as_mut().poll(cx) {
+            return Poll::Ready(Either::Left(res));
+        }
+
+        b.as_mut().poll(cx).map(Either::Right)
+    })
+    .await
+}
@@ -1,9 +1,11 @@
-use super::executor::{Waiter, WaiterState};
+use super::executor::{Waiter, WaiterState, current_runtime};
+use super::select::{Either, select};
 use alloc::sync::Arc;
 use core::future::Future;
 use core::pin::Pin;
 use core::task::{Context, Poll};
 use core::time::Duration;
+use libc::syscalls::Syscall;
 use libc::timer::Timer as LibcTimer;
 use rtl::error::ErrorType;
 
@@ -15,6 +17,14 @@ struct TimerFuture<'a> {
     state: Option<Arc<WaiterState>>,
 }
 
+impl Drop for TimerFuture<'_> {
+    fn drop(&mut self) {
+        if let Some(ref state) = self.state {
+            state.cancel();
+        }
+    }
+}
+
 impl Future for TimerFuture<'_> {
     type Output = Result<(), ErrorType>;
 
@@ -57,3 +67,53 @@ impl Timer {
         .await
     }
 }
+
+struct Sleep {
+    deadline: Duration,
+    id: Option<u64>,
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            current_runtime().remove_timer(self.deadline, id);
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let cur = self.get_mut();
+
+        if Syscall::clock_get() >= cur.deadline {
+            return Poll::Ready(());
+        }
+
+        let runtime = current_runtime();
+        let id = *cur.id.get_or_insert_with(|| runtime.alloc_timer_id());
+
+        runtime.set_timer(cur.deadline, id, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// Sleeps until `deadline` (time since boot). Does not need any kernel objects, since runtime
+/// passes the earliest deadline to the wait syscall
+pub async fn sleep_until(deadline: Duration) {
+    Sleep { deadline, id: None }.await
+}
+
+pub async fn sleep(duration: Duration) {
+    sleep_until(Syscall::clock_get() + duration).await
+}
+
+/// Runs `fut` for at most `duration`. Fails with [`ErrorType::TimedOut`] if it did not complete
+/// in time
+pub async fn timeout<F: Future>(fut: F, duration: Duration) -> Result<F::Output, ErrorType> {
+    match select(fut, sleep(duration)).await {
+        Either::Left(res) => Ok(res),
+        Either::Right(()) => Err(ErrorType::TimedOut),
+    }
+}
@@ -9,6 +9,17 @@ pub trait Card: Send + Deref<Target = SdhciIface> {
     /// Reads one block of data from the device
     fn read_block(&mut self, block: u32) -> Result<Vec<u8>, ErrorType>;
 
+    /// Reads `count` consecutive blocks of data from the device
+    fn read_blocks(&mut self, block: u32, count: u16) -> Result<Vec<u8>, ErrorType> {
+        let mut data = Vec::new();
+
+        for i in 0..count as u32 {
+            data.extend(self.read_block(block + i)?);
+        }
+
+        Ok(data)
+    }
+
     /// Writes one block of data to the device
     fn write_block(&mut self, block: u32, data: &[u8]) -> Result<(), ErrorType>;
 
@@ -7,6 +7,9 @@ use rokio::port::Port;
 use rtl::error::ErrorType;
 use rtl::locking::spinlock::Spinlock;
 
+/// Capacity of `Blocks` type in the interface
+const MAX_READ_SIZE: usize = 65536;
+
 pub async fn start_server(sdhci: Box<dyn Card>, ns: NameServer) -> Result<(), ErrorType> {
     let port = Port::create()?;
     let sdhci = Arc::new(Spinlock::new(sdhci));
@@ -26,6 +29,17 @@ pub async fn start_server(sdhci: Box<dyn Card>, ns: NameServer) -> Result<(), Er
 
                     responder.reply(data.into_iter().collect())?;
                 }
+                BlkDevRequest::ReadBlocks { value, responder } => {
+                    let mut card = sdhci.lock();
+
+                    if value.count as usize * card.block_size() as usize > MAX_READ_SIZE {
+                        return Err(ErrorType::BufferTooBig);
+                    }
+
+                    let data = card.read_blocks(value.blockIdx, value.count)?;
+
+                    responder.reply(data.into_iter().collect())?;
+                }
                 BlkDevRequest::WriteBlock { value, responder } => {
                     let mut card = sdhci.lock();
 
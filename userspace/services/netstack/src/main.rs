@@ -16,7 +16,7 @@ mod netstack;
 mod packet;
 mod socket;
 
-#[rokio::main]
+#[rokio::main(worker_threads = 4)]
 async fn main(root: Option<Handle>) -> Result<(), ErrorType> {
     let ns = NameServer::new(unsafe { Port::new(root.unwrap()) });
     let nic = Nic::new(&ns).await?;
@@ -34,8 +34,28 @@ impl Netdev {
         &self,
         to: Mac,
         protocol: FrameType,
-        mut packet: Packet,
+        packet: Packet,
     ) -> Result<(), ErrorType> {
+        self.nic
+            .send_packet(&self.make_frame(to, protocol, packet))
+            .await
+    }
+
+    pub async fn send_packets(
+        &self,
+        to: Mac,
+        protocol: FrameType,
+        packets: Vec<Packet>,
+    ) -> Result<(), ErrorType> {
+        let frames = packets
+            .into_iter()
+            .map(|packet| self.make_frame(to, protocol, packet))
+            .collect::<Vec<_>>();
+
+        self.nic.send_packets(&frames).await
+    }
+
+    fn make_frame(&self, to: Mac, protocol: FrameType, mut packet: Packet) -> Vec<u8> {
         match packet.mac_header_mut::<EthHeader>() {
             Some(header) => {
                 header.set_destination(to);
@@ -47,6 +67,6 @@ impl Netdev {
             }
         };
 
-        self.nic.send_packet(&packet.into_frame()).await
+        packet.into_frame()
     }
 }
@@ -1,6 +1,7 @@
 use crate::bindings_NameServer::NameServer;
 use alloc::vec::Vec;
 use bindings_Nic::Nic as NicBindings;
+use heapless::Vec as HLVec;
 use net::ethernet::Mac;
 use rokio::port::Port;
 use rtl::error::ErrorType;
@@ -32,6 +33,31 @@ impl Nic {
         Ok(())
     }
 
+    /// Sends several frames in as few requests as possible, so large bursts are mapped into the
+    /// driver instead of being copied frame by frame
+    pub async fn send_packets(&self, frames: &[Vec<u8>]) -> Result<(), ErrorType> {
+        let mut data = HLVec::new();
+        let mut sizes = HLVec::new();
+
+        for frame in frames {
+            if data.len() + frame.len() > data.capacity() || sizes.is_full() {
+                self.nic
+                    .SendBatch(core::mem::take(&mut data), core::mem::take(&mut sizes))
+                    .await?;
+            }
+
+            data.extend_from_slice(frame)
+                .map_err(|_| ErrorType::BufferTooBig)?;
+            sizes.push(frame.len() as u16).unwrap();
+        }
+
+        if !sizes.is_empty() {
+            self.nic.SendBatch(data, sizes).await?;
+        }
+
+        Ok(())
+    }
+
     pub async fn mac(&self) -> Result<Mac, ErrorType> {
         let res = self.nic.Mac().await?;
 
@@ -117,11 +117,9 @@ impl NetStack {
                     frame_type,
                     packets,
                 } => {
-                    for packet in packets {
-                        self.netdev
-                            .send_packet(destination, frame_type, packet)
-                            .await?;
-                    }
+                    self.netdev
+                        .send_packets(destination, frame_type, packets)
+                        .await?;
                 }
                 PacketDecision::Handled => {}
                 PacketDecision::Drop => println!("Packet drop!"),
@@ -3,6 +3,7 @@ use crate::e1000::regs::E1000Regs;
 use dma::DmaBuffer;
 use hal::address::{Address, PhysAddr};
 use rtl::error::ErrorType;
+use rtl::vmm::CacheAttr;
 use spin::Mutex;
 
 const ONE_SLOT: usize = 1500;
@@ -22,7 +23,8 @@ impl TxBuffer {
         let buffer_size = num_descriptors * ONE_SLOT;
 
         let ring = DmaBuffer::new(num_descriptors)?;
-        let data = DmaBuffer::new(buffer_size)?;
+        // Packets are only written by the CPU, so stores could be merged
+        let data = DmaBuffer::with_cache(buffer_size, CacheAttr::WriteCombining)?;
 
         Ok(Self {
             inner: Mutex::new(TxBufferInner {
@@ -4,8 +4,11 @@
 use alloc::boxed::Box;
 use bindings_NameServer::NameServer;
 use libc::handle::Handle;
+use libc::resource::resource;
+use libc::syscalls::Syscall;
 use rokio::port::Port;
 use rtl::error::ErrorType;
+use rtl::sched::{PRIORITY_DEFAULT, SchedClass};
 
 mod e1000;
 mod driver;
@@ -13,6 +16,9 @@ mod server;
 
 #[rokio::main]
 async fn main(root: Option<Handle>) -> Result<(), ErrorType> {
+    // Rx interrupts should be served, even if busy tasks are running
+    Syscall::thread_set_priority(None, SchedClass::RealTime, PRIORITY_DEFAULT, resource())?;
+
     let ns = NameServer::new(unsafe { Port::new(root.unwrap()) });
     let e1000 = e1000::E1000::new(&ns).await?;
 
@@ -28,6 +28,20 @@ pub async fn start_server(nic: Box<dyn Nic>, ns: NameServer) -> Result<(), Error
                     nic.send_frame(&value.data)?;
                     responder.reply()?;
                 }
+                NicRequest::SendBatch { value, responder } => {
+                    let mut frames = value.data.as_slice();
+
+                    for size in value.sizes {
+                        let (frame, rest) = frames
+                            .split_at_checked(size as usize)
+                            .ok_or(ErrorType::InvalidArgument)?;
+
+                        nic.send_frame(frame)?;
+                        frames = rest;
+                    }
+
+                    responder.reply()?;
+                }
                 NicRequest::Mac { responder, .. } => {
                     let mac = nic.mac();
 
@@ -2,8 +2,9 @@
 #![no_std]
 
 use alloc::string::ToString;
-use libc::{handle::Handle, task::Task};
+use libc::{handle::Handle, resource::resource, task::Task};
 use rokio::port::Port;
+use rtl::capabilities::{Capability, CapabilityBits};
 use rtl::error::ErrorType;
 
 static CPIO: &[u8] = include_bytes!("/tmp/archive.cpio");
@@ -25,7 +26,31 @@ async fn main(_: Option<Handle>) -> Result<(), ErrorType> {
 
         let mut task = Task::create_from_elf(elf, name.to_string()).expect("Failed to create task");
 
-        task.start(p.handle()).unwrap();
+        // Components get only privileges their manifest asks for
+        let manifest = task.manifest().as_ref();
+        let mut rights = CapabilityBits::from(Capability::None);
+
+        if manifest.is_some_and(|x| x.realtime) {
+            rights = rights | Capability::RealTime;
+        }
+
+        if manifest.is_some_and(|x| x.meminfo) {
+            rights = rights | Capability::MemInfo;
+        }
+
+        if manifest.is_some_and(|x| x.mapphys) {
+            rights = rights | Capability::MapPhys;
+        }
+
+        let granted = if rights.bits() != 0 {
+            let rights = rights | Capability::Transfer;
+
+            Some(resource().unwrap().duplicate(rights).unwrap())
+        } else {
+            None
+        };
+
+        task.start(p.handle(), granted.as_ref()).unwrap();
         println!("Spawned '{}'", task.name());
 
         // Don't drop the last reference, since it will close a task
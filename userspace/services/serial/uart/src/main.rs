@@ -6,14 +6,20 @@ use bindings_Serial::{Serial, SerialRequest};
 use fdt::Fdt;
 use hal::address::VirtualAddress;
 use libc::handle::Handle;
+use libc::resource::resource;
 use libc::syscalls::Syscall;
 use rokio::port::Port;
 use rtl::locking::spinlock::Spinlock;
+use rtl::sched::{PRIORITY_DEFAULT, SchedClass};
 
 mod pl011;
 
 #[rokio::main]
 async fn main(nameserver: Option<Handle>) {
+    // Console output should not wait behind busy tasks
+    Syscall::thread_set_priority(None, SchedClass::RealTime, PRIORITY_DEFAULT, resource())
+        .expect("Failed to set priority");
+
     let fdt = Syscall::get_fdt().unwrap();
     let fdt = unsafe { Fdt::from_ptr(fdt.to_raw::<u8>()).unwrap() };
 
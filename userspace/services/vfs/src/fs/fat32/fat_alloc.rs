@@ -1,7 +1,7 @@
 //! FAT entries allocator
 
 use super::fat::FatEntry;
-use super::sb::{BlockDevice, Cluster, Sector};
+use super::sb::{BlockDevice, Cluster, MAX_SECTORS_PER_READ, Sector};
 use adt::BitAllocator;
 use alloc::vec::Vec;
 use rtl::error::ErrorType;
@@ -43,22 +43,28 @@ impl FatAlloc {
     ) -> Result<BitAllocator, ErrorType> {
         let mut new_cache = BitAllocator::new(512 / core::mem::size_of::<FatEntry>() * fat_length);
 
-        for i in 0..fat_length {
-            let mut sector = vec![0; 512];
+        let mut sectors = vec![0; 512 * MAX_SECTORS_PER_READ];
 
-            blk.read_sector(fat_start + i as _, &mut sector).await?;
-            let fats = unsafe {
-                core::slice::from_raw_parts::<FatEntry>(
-                    sector.as_ptr() as _,
-                    Self::fats_per_sector() as usize,
-                )
-            };
+        for first in (0..fat_length).step_by(MAX_SECTORS_PER_READ) {
+            let count = (fat_length - first).min(MAX_SECTORS_PER_READ);
+            let sectors = &mut sectors[..512 * count];
+
+            blk.read_sectors(fat_start + first as _, sectors).await?;
+
+            for (i, sector) in sectors.chunks(512).enumerate() {
+                let fats = unsafe {
+                    core::slice::from_raw_parts::<FatEntry>(
+                        sector.as_ptr() as _,
+                        Self::fats_per_sector() as usize,
+                    )
+                };
 
-            for (j, fat) in fats.iter().enumerate() {
-                if !fat.is_free() {
-                    new_cache
-                        .allocate_specific(Self::fats_per_sector() as usize * i + j)
-                        .unwrap();
+                for (j, fat) in fats.iter().enumerate() {
+                    if !fat.is_free() {
+                        new_cache
+                            .allocate_specific(Self::fats_per_sector() as usize * (first + i) + j)
+                            .unwrap();
+                    }
                 }
             }
         }
@@ -130,4 +130,7 @@ impl FileOperations for FatFile {
         file.parent.update_size(new_size).await?;
         Ok(processed)
     }
+    fn size(&self) -> usize {
+        self.inner.lock().parent.size() as usize
+    }
 }
@@ -190,6 +190,9 @@ impl BitOrAssign for CallbackRes {
 /// Super-block reference
 pub type SuperBlockRef = Arc<SuperBlock>;
 
+/// Number of sectors fitting into one `ReadBlocks` request
+pub(super) const MAX_SECTORS_PER_READ: usize = 128;
+
 /// Wrapper for block device
 pub(super) struct BlockDevice(BlkDev);
 
@@ -202,6 +205,15 @@ impl BlockDevice {
         Ok(())
     }
 
+    /// Reads consecutive sectors of data from the backing block device. Length of `to` must be a
+    /// multiple of sector size and can't exceed [`MAX_SECTORS_PER_READ`] sectors
+    pub async fn read_sectors(&self, sector: Sector, to: &mut [u8]) -> Result<(), ErrorType> {
+        let res = self.0.ReadBlocks(sector.0, (to.len() / 512) as u16).await?;
+
+        to.copy_from_slice(&res.data);
+        Ok(())
+    }
+
     /// Writes one sector of data to the backing block device
     pub async fn write_sector(&self, sector: Sector, from: &[u8]) -> Result<(), ErrorType> {
         self.0
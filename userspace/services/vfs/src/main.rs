@@ -11,7 +11,7 @@ use rtl::error::ErrorType;
 mod fs;
 mod vfs;
 
-#[rokio::main]
+#[rokio::main(worker_threads = 4)]
 async fn main(root: Option<Handle>) -> Result<(), ErrorType> {
     let ns = NameServer::new(unsafe { Port::new(root.unwrap()) });
     let root = ns.Get("blkdev".try_into().unwrap()).await?;
@@ -1,5 +1,6 @@
 use crate::bindings_Vfs::{File, FileRequest};
 use crate::vfs::inode::{FileOperations, Inode, InodeKind};
+use crate::vfs::pager;
 use alloc::sync::Arc;
 use hal::address::VirtualAddress;
 use libc::handle::Handle;
@@ -32,13 +33,14 @@ impl OpenFile {
         Ok((
             File::for_each(port, move |req| {
                 let file = file.clone();
+                let inode = inode.clone();
 
                 async move {
                     match req {
                         FileRequest::Read { value, responder } => {
                             let mut file = file.lock();
                             let vmo = unsafe { VmObject::new(value.vmo) };
-                            let mut buf = vms().map_vm_object(&vmo, None, MappingType::Data)?;
+                            let mut buf = vms().map_vm_object(&vmo, None, MappingType::DATA)?;
 
                             // TODO: this is really unsafe and we should check the size of the VMO
                             // and do not believe the user.
@@ -53,7 +55,7 @@ impl OpenFile {
                             let mut file = file.lock();
 
                             let vmo = unsafe { VmObject::new(value.vmo) };
-                            let buf = vms().map_vm_object(&vmo, None, MappingType::RoData)?;
+                            let buf = vms().map_vm_object(&vmo, None, MappingType::RODATA)?;
 
                             // TODO: this is really unsafe and we should check the size of the VMO
                             // and do not believe the user.
@@ -64,6 +66,11 @@ impl OpenFile {
 
                             responder.reply(res)?;
                         }
+                        FileRequest::Map { responder, .. } => {
+                            let (vmo, size) = pager::map_file(&inode)?;
+
+                            responder.reply(size, vmo.handle())?;
+                        }
                     }
 
                     Ok(())
@@ -5,6 +5,7 @@ use alloc::sync::Arc;
 use alloc::vec::Vec;
 use rtl::error::ErrorType;
 use rtl::locking::spinlock::Spinlock;
+use super::pager::FilePager;
 use super::{Directory, File};
 
 #[async_trait::async_trait]
@@ -29,6 +30,9 @@ pub trait FileOperations: Send + Sync {
 
     /// Write data to the file
     async fn write(&self, buf: &[u8], offset: usize) -> Result<usize, ErrorType>;
+
+    /// Current size of the file
+    fn size(&self) -> usize;
 }
 
 pub enum InodeKind {
@@ -41,6 +45,8 @@ pub enum InodeKind {
 pub struct Inode {
     num: usize,
     kind: InodeKind,
+    /// VMO shared by all clients mapping the file
+    pager: Spinlock<Option<FilePager>>,
 }
 
 impl Inode {
@@ -50,6 +56,7 @@ impl Inode {
         Arc::new(Self {
             num: ID_ALLOCATOR.lock().allocate(),
             kind,
+            pager: Spinlock::new(None),
         })
     }
 
@@ -71,4 +78,8 @@ impl Inode {
     pub fn kind(&self) -> &InodeKind {
         &self.kind
     }
+
+    pub fn pager(&self) -> &Spinlock<Option<FilePager>> {
+        &self.pager
+    }
 }
@@ -13,6 +13,7 @@ mod dcache;
 mod dir;
 mod file;
 pub mod inode;
+mod pager;
 
 pub struct Vfs {
     root: Arc<Dentry>,
@@ -0,0 +1,104 @@
+//! Memory mapped files. Pages of the file VMO are read from the file, when they are touched
+//! for the first time
+
+use crate::vfs::File;
+use crate::vfs::inode::{Inode, InodeKind};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::time::Duration;
+use libc::factory::factory;
+use libc::syscalls::Syscall;
+use libc::vmm::vm_object::VmObject;
+use rokio::event::wait_signals;
+use rokio::port::Port;
+use rokio::select::{Either, select};
+use rtl::error::ErrorType;
+use rtl::ipc::IpcMessage;
+use rtl::signal::Signal;
+use rtl::vmm::{MappingType, PagerRequest};
+
+/// VMO backed by a file, which is cached in the inode while anyone maps the file
+pub struct FilePager {
+    vmo: Arc<VmObject>,
+    size: usize,
+}
+
+/// Returns read-only handle to the VMO backed by file of `inode` and size of the file. All
+/// clients share one VMO and its pager, which is stopped once the last of them is gone.
+///
+/// Pages are read once, so later writes to the file are not visible through resident pages
+pub fn map_file(inode: &Arc<Inode>) -> Result<(VmObject, usize), ErrorType> {
+    let InodeKind::File(file) = inode.kind() else {
+        return Err(ErrorType::InvalidArgument);
+    };
+    let mut pager = inode.pager().lock();
+
+    if let Some(pager) = pager.as_ref() {
+        return Ok((pager.vmo.read_only()?, pager.size));
+    }
+
+    let port = Port::create()?;
+    let size = file.size();
+    let vmo = factory().create_pager_vm_object(size, MappingType::RODATA, port.handle(), 0)?;
+    let vmo = Arc::new(vmo);
+    let client = vmo.read_only()?;
+
+    rokio::executor::spawn(serve(inode.clone(), file.clone(), vmo.clone(), port));
+    *pager = Some(FilePager { vmo, size });
+
+    Ok((client, size))
+}
+
+/// Supplies pages of `vmo` until all clients of it are gone
+async fn serve(
+    inode: Arc<Inode>,
+    file: File,
+    vmo: Arc<VmObject>,
+    port: Port,
+) -> Result<(), ErrorType> {
+    loop {
+        let request = async {
+            let mut buf = [0; size_of::<PagerRequest>()];
+            let mut msg = IpcMessage::new();
+
+            msg.set_in_arena(buf.as_mut_slice());
+            port.receive(&mut msg).await?;
+
+            Ok::<_, ErrorType>(unsafe {
+                core::ptr::read_unaligned(msg.in_arena().unwrap().as_ptr().cast::<PagerRequest>())
+            })
+        };
+
+        let closed = wait_signals(vmo.handle(), Signal::PeerClosed.into());
+
+        match select(request, closed).await {
+            Either::Left(req) => {
+                let req = req?;
+                let mut page = Vec::new();
+
+                // Tail of the last page past the end of the file stays zeroed
+                page.resize(req.size, 0);
+                file.read(&mut page, req.offset).await?;
+                vmo.supply_pages(req.offset, &page)?;
+            }
+            Either::Right(res) => {
+                res?;
+
+                let mut pager = inode.pager().lock();
+
+                // File could have been mapped again meanwhile, which clears the signal
+                let closed = Syscall::object_wait_until(
+                    vmo.handle(),
+                    Signal::PeerClosed.into(),
+                    Some(Duration::ZERO),
+                )
+                .is_ok();
+
+                if closed {
+                    *pager = None;
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
@@ -11,6 +11,9 @@ use tempfile::NamedTempFile;
 
 static TARGET: &str = "aarch64-unknown-none-softfloat";
 
+// Kernel is loaded at a random address, so it must be position independent
+static KERNEL_RUSTFLAGS: &str = "-C relocation-model=pie";
+
 fn has_manifest(path: &Path) -> std::io::Result<bool> {
     for entry in read_dir(path)? {
         let entry = entry?;
@@ -238,8 +241,13 @@ fn build_component(name: &str, b: &BuildScript, command: &str) -> Result<(), Str
     } else {
         String::new()
     };
+    let flags = if name == "kernel" {
+        format!("{opt_level} {KERNEL_RUSTFLAGS}")
+    } else {
+        opt_level
+    };
 
-    run_cargo_build(command, name, &opt_level, &b.board)
+    run_cargo_build(command, name, &flags, &b.board)
 }
 
 // Returns path to kernel
@@ -263,7 +271,10 @@ fn build_test_kernel() -> Result<String, String> {
         None,
         None,
         Some(&mut stdout),
-        Some(&[("RUSTFLAGS", "-C force-frame-pointers")]),
+        Some(&[(
+            "RUSTFLAGS",
+            &format!("-C force-frame-pointers {KERNEL_RUSTFLAGS}"),
+        )]),
     )?;
 
     let string = from_utf8(stdout.as_slice()).unwrap().to_owned();
@@ -406,6 +417,8 @@ fn run_impl(gdb: bool, c: Option<&BuildScript>) -> Result<(), String> {
         "2G",
         "-cpu",
         "cortex-a53",
+        "-smp",
+        "4",
         "-nographic",
         "-kernel",
         &bin,
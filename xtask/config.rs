@@ -13,4 +13,10 @@ pub struct BuildScript {
 pub struct Component {
     pub name: String,
     pub env: Option<String>,
+    #[serde(default)]
+    pub realtime: bool,
+    #[serde(default)]
+    pub meminfo: bool,
+    #[serde(default)]
+    pub mapphys: bool,
 }